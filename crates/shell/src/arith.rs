@@ -0,0 +1,677 @@
+//! 算術式展開 `$(( ... ))` の式パーサーと評価器
+//!
+//! 整数演算、比較、論理・ビット演算、三項演算子、代入演算子、変数参照をサポートする。
+//! 演算子の優先順位は bash に合わせている。
+
+use std::collections::HashMap;
+
+/// 算術式のエラー
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ArithError {
+    #[error("syntax error in expression (error token is \"{0}\")")]
+    Syntax(String),
+    #[error("division by 0")]
+    DivisionByZero,
+    #[error("exponent less than 0")]
+    NegativeExponent,
+    #[error("expression recursion level exceeded")]
+    Recursion,
+}
+
+/// 変数の値を式として評価するときの入れ子の上限
+const MAX_DEPTH: usize = 32;
+
+/// 式から参照・代入する変数
+/// $1 や $# などの位置パラメータ・特殊パラメータも名前（"1", "#"）で引く
+pub trait Variables {
+    /// 変数の値（未設定なら None）
+    fn get(&self, name: &str) -> Option<String>;
+    fn set(&mut self, name: &str, value: String);
+}
+
+impl Variables for HashMap<String, String> {
+    fn get(&self, name: &str) -> Option<String> {
+        HashMap::get(self, name).cloned()
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        self.insert(name.to_string(), value);
+    }
+}
+
+/// 式を評価する
+/// 代入演算子や ++ / -- による変更は `vars` に反映される
+pub fn evaluate(expr: &str, vars: &mut dyn Variables) -> Result<i64, ArithError> {
+    eval_str(expr, vars, 0)
+}
+
+/// 式の構文だけを検査する
+pub fn check(expr: &str) -> Result<(), ArithError> {
+    parse(expr).map(|_| ())
+}
+
+fn eval_str(expr: &str, vars: &mut dyn Variables, depth: usize) -> Result<i64, ArithError> {
+    if depth > MAX_DEPTH {
+        return Err(ArithError::Recursion);
+    }
+    match parse(expr)? {
+        Some(ast) => Evaluator { vars, depth }.eval(&ast),
+        // 空の式は 0
+        None => Ok(0),
+    }
+}
+
+fn parse(expr: &str) -> Result<Option<Expr>, ArithError> {
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Ok(None);
+    }
+    let mut parser = Parser { tokens, pos: 0 };
+    let ast = parser.comma()?;
+    match parser.tokens.get(parser.pos) {
+        Some(tok) => Err(ArithError::Syntax(tok.to_string())),
+        None => Ok(Some(ast)),
+    }
+}
+
+/// 二項演算子
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinOp {
+    Comma,
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+/// 単項演算子
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum UnaryOp {
+    Neg,
+    Plus,
+    Not,
+    BitNot,
+}
+
+/// 式の構文木
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    /// ${#name}（値の文字数）
+    Length(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Ternary(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// 代入（演算子付き代入は演算子を持つ）
+    Assign(String, Option<BinOp>, Box<Expr>),
+    /// ++ / --（prefix なら変更後の値を返す）
+    IncDec {
+        name: String,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+/// トークン
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Num(i64),
+    Ident(String),
+    /// 位置パラメータと特殊パラメータ（$1, $# など。代入はできない）
+    Param(String),
+    /// ${#name}
+    Length(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Tok {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tok::Num(n) => write!(f, "{}", n),
+            Tok::Ident(s) => write!(f, "{}", s),
+            Tok::Param(s) => write!(f, "${}", s),
+            Tok::Length(s) => write!(f, "${{#{}}}", s),
+            Tok::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+/// 演算子（最長一致のため長いものから並べる）
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "**", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+=", "-=",
+    "*=", "/=", "%=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "=", "!", "~", "&", "^",
+    "|", "?", ":", "(", ")", ",",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<Tok>, ArithError> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_ascii_alphanumeric() || c == '_' || c == '#' {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '#'))
+                .unwrap_or(rest.len());
+            let word = &rest[..end];
+            tokens.push(if c.is_ascii_digit() {
+                Tok::Num(parse_number(word)?)
+            } else {
+                Tok::Ident(word.to_string())
+            });
+            rest = &rest[end..];
+        } else if c == '$' {
+            // $NAME と ${NAME} は変数参照、$1 や $# はパラメータ参照として扱う
+            let (name, len) = match rest[1..].strip_prefix('{') {
+                Some(inner) => {
+                    let end = inner
+                        .find('}')
+                        .ok_or_else(|| ArithError::Syntax(rest.to_string()))?;
+                    (&inner[..end], end + 3)
+                }
+                None => {
+                    let inner = &rest[1..];
+                    let end = match inner.chars().next() {
+                        Some('0'..='9' | '#' | '?' | '$' | '!' | '@' | '*') => 1,
+                        _ => inner
+                            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                            .unwrap_or(inner.len()),
+                    };
+                    (&inner[..end], end + 1)
+                }
+            };
+            let token = match name.strip_prefix('#') {
+                Some(target) if is_parameter(target) => Tok::Length(target.to_string()),
+                _ if is_name(name) => Tok::Ident(name.to_string()),
+                _ if is_parameter(name) => Tok::Param(name.to_string()),
+                _ => return Err(ArithError::Syntax(rest.to_string())),
+            };
+            tokens.push(token);
+            rest = &rest[len..];
+        } else {
+            let op = OPERATORS
+                .iter()
+                .find(|op| rest.starts_with(**op))
+                .ok_or_else(|| ArithError::Syntax(rest.to_string()))?;
+            tokens.push(Tok::Op(op));
+            rest = &rest[op.len()..];
+        }
+        rest = rest.trim_start();
+    }
+
+    Ok(tokens)
+}
+
+/// 代入できる変数名かどうか
+fn is_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 参照できるパラメータ名（変数名、位置パラメータ、特殊パラメータ）かどうか
+fn is_parameter(s: &str) -> bool {
+    is_name(s)
+        || (!s.is_empty() && s.chars().all(|c| c.is_ascii_digit()))
+        || matches!(s, "#" | "?" | "$" | "!" | "@" | "*")
+}
+
+/// 数値リテラル（10 進、0x16 進、0 始まりの 8 進、base#digits）
+fn parse_number(word: &str) -> Result<i64, ArithError> {
+    let invalid = || ArithError::Syntax(word.to_string());
+
+    let (radix, digits) = if let Some((base, digits)) = word.split_once('#') {
+        let base: u32 = base.parse().map_err(|_| invalid())?;
+        if !(2..=36).contains(&base) {
+            return Err(invalid());
+        }
+        (base, digits)
+    } else if let Some(hex) = word.strip_prefix("0x").or_else(|| word.strip_prefix("0X")) {
+        (16, hex)
+    } else if word.len() > 1 && word.starts_with('0') {
+        (8, &word[1..])
+    } else {
+        (10, word)
+    };
+
+    i64::from_str_radix(digits, radix).map_err(|_| invalid())
+}
+
+/// 優先順位に従って構文木を組み立てる
+struct Parser {
+    tokens: Vec<Tok>,
+    pos: usize,
+}
+
+impl Parser {
+    /// 二項演算子の優先順位（大きいほど強く結合する）
+    fn binary_op(tok: &Tok) -> Option<(BinOp, u8)> {
+        let Tok::Op(op) = tok else {
+            return None;
+        };
+        Some(match *op {
+            "||" => (BinOp::Or, 1),
+            "&&" => (BinOp::And, 2),
+            "|" => (BinOp::BitOr, 3),
+            "^" => (BinOp::BitXor, 4),
+            "&" => (BinOp::BitAnd, 5),
+            "==" => (BinOp::Eq, 6),
+            "!=" => (BinOp::Ne, 6),
+            "<" => (BinOp::Lt, 7),
+            "<=" => (BinOp::Le, 7),
+            ">" => (BinOp::Gt, 7),
+            ">=" => (BinOp::Ge, 7),
+            "<<" => (BinOp::Shl, 8),
+            ">>" => (BinOp::Shr, 8),
+            "+" => (BinOp::Add, 9),
+            "-" => (BinOp::Sub, 9),
+            "*" => (BinOp::Mul, 10),
+            "/" => (BinOp::Div, 10),
+            "%" => (BinOp::Rem, 10),
+            "**" => (BinOp::Pow, 11),
+            _ => return None,
+        })
+    }
+
+    /// 代入演算子
+    fn assign_op(op: &str) -> Option<Option<BinOp>> {
+        Some(match op {
+            "=" => None,
+            "+=" => Some(BinOp::Add),
+            "-=" => Some(BinOp::Sub),
+            "*=" => Some(BinOp::Mul),
+            "/=" => Some(BinOp::Div),
+            "%=" => Some(BinOp::Rem),
+            "<<=" => Some(BinOp::Shl),
+            ">>=" => Some(BinOp::Shr),
+            "&=" => Some(BinOp::BitAnd),
+            "^=" => Some(BinOp::BitXor),
+            "|=" => Some(BinOp::BitOr),
+            _ => return None,
+        })
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Tok::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), ArithError> {
+        match self.peek() {
+            Some(Tok::Op(found)) if *found == op => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(tok) => Err(ArithError::Syntax(tok.to_string())),
+            None => Err(ArithError::Syntax(op.to_string())),
+        }
+    }
+
+    /// expr , expr
+    fn comma(&mut self) -> Result<Expr, ArithError> {
+        let mut lhs = self.assignment()?;
+        while self.peek_op() == Some(",") {
+            self.pos += 1;
+            let rhs = self.assignment()?;
+            lhs = Expr::Binary(BinOp::Comma, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// name = expr（右結合）
+    fn assignment(&mut self) -> Result<Expr, ArithError> {
+        if let (Some(Tok::Ident(name)), Some(Tok::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            if let Some(op) = Self::assign_op(op) {
+                let name = name.clone();
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(name, op, Box::new(value)));
+            }
+        }
+        self.ternary()
+    }
+
+    /// cond ? a : b（右結合）
+    fn ternary(&mut self) -> Result<Expr, ArithError> {
+        let cond = self.binary(1)?;
+        if self.peek_op() != Some("?") {
+            return Ok(cond);
+        }
+        self.pos += 1;
+        let then = self.comma()?;
+        self.expect(":")?;
+        let otherwise = self.assignment()?;
+        Ok(Expr::Ternary(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// 優先順位 min_prec 以上の二項演算子を結合する（** のみ右結合）
+    fn binary(&mut self, min_prec: u8) -> Result<Expr, ArithError> {
+        let mut lhs = self.unary()?;
+        while let Some((op, prec)) = self.peek().and_then(Self::binary_op) {
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let next = if op == BinOp::Pow { prec } else { prec + 1 };
+            let rhs = self.binary(next)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    /// 単項演算子と前置 ++ / --
+    fn unary(&mut self) -> Result<Expr, ArithError> {
+        let op = match self.peek_op() {
+            Some("-") => UnaryOp::Neg,
+            Some("+") => UnaryOp::Plus,
+            Some("!") => UnaryOp::Not,
+            Some("~") => UnaryOp::BitNot,
+            Some(op @ ("++" | "--")) => {
+                self.pos += 1;
+                let name = self.variable()?;
+                return Ok(Expr::IncDec {
+                    name,
+                    delta: if op == "++" { 1 } else { -1 },
+                    prefix: true,
+                });
+            }
+            _ => return self.postfix(),
+        };
+        self.pos += 1;
+        Ok(Expr::Unary(op, Box::new(self.unary()?)))
+    }
+
+    /// 後置 ++ / -- と一次式
+    fn postfix(&mut self) -> Result<Expr, ArithError> {
+        match self.peek().cloned() {
+            Some(Tok::Num(n)) => {
+                self.pos += 1;
+                Ok(Expr::Num(n))
+            }
+            Some(Tok::Param(name)) => {
+                self.pos += 1;
+                Ok(Expr::Var(name))
+            }
+            Some(Tok::Length(name)) => {
+                self.pos += 1;
+                Ok(Expr::Length(name))
+            }
+            Some(Tok::Ident(name)) => {
+                self.pos += 1;
+                match self.peek_op() {
+                    Some(op @ ("++" | "--")) => {
+                        self.pos += 1;
+                        Ok(Expr::IncDec {
+                            name,
+                            delta: if op == "++" { 1 } else { -1 },
+                            prefix: false,
+                        })
+                    }
+                    _ => Ok(Expr::Var(name)),
+                }
+            }
+            Some(Tok::Op("(")) => {
+                self.pos += 1;
+                let expr = self.comma()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(tok) => Err(ArithError::Syntax(tok.to_string())),
+            None => Err(ArithError::Syntax("end of expression".to_string())),
+        }
+    }
+
+    fn variable(&mut self) -> Result<String, ArithError> {
+        match self.peek().cloned() {
+            Some(Tok::Ident(name)) => {
+                self.pos += 1;
+                Ok(name)
+            }
+            Some(tok) => Err(ArithError::Syntax(tok.to_string())),
+            None => Err(ArithError::Syntax("end of expression".to_string())),
+        }
+    }
+}
+
+/// 構文木を評価する
+struct Evaluator<'a> {
+    vars: &'a mut dyn Variables,
+    depth: usize,
+}
+
+impl Evaluator<'_> {
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        match expr {
+            Expr::Num(n) => Ok(*n),
+            Expr::Var(name) => self.get(name),
+            // ${#@} と ${#*} は位置パラメータの数
+            Expr::Length(name) if matches!(name.as_str(), "@" | "*") => self.get("#"),
+            Expr::Length(name) => {
+                Ok(self.vars.get(name).unwrap_or_default().chars().count() as i64)
+            }
+            Expr::Unary(op, operand) => {
+                let value = self.eval(operand)?;
+                Ok(match op {
+                    UnaryOp::Neg => value.wrapping_neg(),
+                    UnaryOp::Plus => value,
+                    UnaryOp::Not => (value == 0) as i64,
+                    UnaryOp::BitNot => !value,
+                })
+            }
+            // 論理演算は短絡評価する
+            Expr::Binary(BinOp::And, lhs, rhs) => {
+                Ok((self.eval(lhs)? != 0 && self.eval(rhs)? != 0) as i64)
+            }
+            Expr::Binary(BinOp::Or, lhs, rhs) => {
+                Ok((self.eval(lhs)? != 0 || self.eval(rhs)? != 0) as i64)
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = self.eval(lhs)?;
+                let rhs = self.eval(rhs)?;
+                apply(*op, lhs, rhs)
+            }
+            Expr::Ternary(cond, then, otherwise) => {
+                if self.eval(cond)? != 0 {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Assign(name, op, value) => {
+                let rhs = self.eval(value)?;
+                let value = match op {
+                    Some(op) => apply(*op, self.get(name)?, rhs)?,
+                    None => rhs,
+                };
+                self.vars.set(name, value.to_string());
+                Ok(value)
+            }
+            Expr::IncDec {
+                name,
+                delta,
+                prefix,
+            } => {
+                let old = self.get(name)?;
+                let new = old.wrapping_add(*delta);
+                self.vars.set(name, new.to_string());
+                Ok(if *prefix { new } else { old })
+            }
+        }
+    }
+
+    /// 変数の値（未設定や空は 0、数値でなければ式として評価する）
+    fn get(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.vars.get(name).unwrap_or_default();
+        let value = value.trim();
+        if value.is_empty() {
+            return Ok(0);
+        }
+        if let Ok(n) = value.parse() {
+            return Ok(n);
+        }
+        eval_str(value, self.vars, self.depth + 1)
+    }
+}
+
+/// 二項演算を適用
+fn apply(op: BinOp, lhs: i64, rhs: i64) -> Result<i64, ArithError> {
+    Ok(match op {
+        BinOp::Comma => rhs,
+        BinOp::Or => (lhs != 0 || rhs != 0) as i64,
+        BinOp::And => (lhs != 0 && rhs != 0) as i64,
+        BinOp::BitOr => lhs | rhs,
+        BinOp::BitXor => lhs ^ rhs,
+        BinOp::BitAnd => lhs & rhs,
+        BinOp::Eq => (lhs == rhs) as i64,
+        BinOp::Ne => (lhs != rhs) as i64,
+        BinOp::Lt => (lhs < rhs) as i64,
+        BinOp::Le => (lhs <= rhs) as i64,
+        BinOp::Gt => (lhs > rhs) as i64,
+        BinOp::Ge => (lhs >= rhs) as i64,
+        BinOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinOp::Shr => lhs.wrapping_shr(rhs as u32),
+        BinOp::Add => lhs.wrapping_add(rhs),
+        BinOp::Sub => lhs.wrapping_sub(rhs),
+        BinOp::Mul => lhs.wrapping_mul(rhs),
+        BinOp::Div | BinOp::Rem if rhs == 0 => return Err(ArithError::DivisionByZero),
+        BinOp::Div => lhs.wrapping_div(rhs),
+        BinOp::Rem => lhs.wrapping_rem(rhs),
+        BinOp::Pow if rhs < 0 => return Err(ArithError::NegativeExponent),
+        BinOp::Pow => lhs.wrapping_pow(rhs.min(u32::MAX as i64) as u32),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expr: &str) -> Result<i64, ArithError> {
+        evaluate(expr, &mut HashMap::new())
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(eval("1 + 2"), Ok(3));
+        assert_eq!(eval("2 + 3 * 4"), Ok(14));
+        assert_eq!(eval("(2 + 3) * 4"), Ok(20));
+        assert_eq!(eval("7 / 2"), Ok(3));
+        assert_eq!(eval("-7 % 3"), Ok(-1));
+        assert_eq!(eval("2 ** 3 ** 2"), Ok(512));
+        assert_eq!(eval("-2 ** 2"), Ok(4));
+        assert_eq!(eval("10 - 2 - 3"), Ok(5));
+        assert_eq!(eval(""), Ok(0));
+    }
+
+    #[test]
+    fn test_number_literals() {
+        assert_eq!(eval("0x1f"), Ok(31));
+        assert_eq!(eval("010"), Ok(8));
+        assert_eq!(eval("2#101"), Ok(5));
+        assert!(matches!(eval("09"), Err(ArithError::Syntax(_))));
+    }
+
+    #[test]
+    fn test_comparison_and_logic() {
+        assert_eq!(eval("1 < 2 && 2 <= 2"), Ok(1));
+        assert_eq!(eval("1 == 2 || 3 != 3"), Ok(0));
+        assert_eq!(eval("!0"), Ok(1));
+        assert_eq!(eval("5 & 3 | 8 ^ 1"), Ok(9));
+        assert_eq!(eval("~0"), Ok(-1));
+        assert_eq!(eval("1 << 4 >> 2"), Ok(4));
+    }
+
+    #[test]
+    fn test_ternary() {
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2));
+        assert_eq!(eval("0 ? 2 : 0 ? 3 : 4"), Ok(4));
+    }
+
+    #[test]
+    fn test_variables_and_assignment() {
+        let mut vars = HashMap::new();
+        vars.insert("i".to_string(), "5".to_string());
+        vars.insert("expr".to_string(), "i * 2".to_string());
+
+        assert_eq!(evaluate("i + $i + ${i}", &mut vars), Ok(15));
+        assert_eq!(evaluate("expr + missing", &mut vars), Ok(10));
+        assert_eq!(evaluate("i += 2", &mut vars), Ok(7));
+        assert_eq!(evaluate("i++", &mut vars), Ok(7));
+        assert_eq!(evaluate("--i", &mut vars), Ok(7));
+        assert_eq!(evaluate("a = b = 3, a * b", &mut vars), Ok(9));
+        assert_eq!(vars["i"], "7");
+        assert_eq!(vars["a"], "3");
+    }
+
+    #[test]
+    fn test_parameters() {
+        let mut vars = HashMap::new();
+        vars.insert("1".to_string(), "5".to_string());
+        vars.insert("#".to_string(), "2".to_string());
+
+        assert_eq!(evaluate("$1 + 1", &mut vars), Ok(6));
+        assert_eq!(evaluate("${1} * $#", &mut vars), Ok(10));
+        assert_eq!(evaluate("$# > 0", &mut vars), Ok(1));
+        assert_eq!(evaluate("${#1} + ${#@}", &mut vars), Ok(3));
+        assert_eq!(evaluate("$2 + ${#missing}", &mut vars), Ok(0));
+        // パラメータには代入できない
+        assert_eq!(
+            evaluate("$1 = 2", &mut vars),
+            Err(ArithError::Syntax("=".to_string()))
+        );
+        assert!(evaluate("${1x}", &mut vars).is_err());
+    }
+
+    #[test]
+    fn test_short_circuit() {
+        let mut vars = HashMap::new();
+        assert_eq!(evaluate("0 && (x = 1)", &mut vars), Ok(0));
+        assert_eq!(evaluate("1 ? y = 2 : (z = 3)", &mut vars), Ok(2));
+        assert!(!vars.contains_key("x"));
+        assert!(!vars.contains_key("z"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(eval("1 / 0"), Err(ArithError::DivisionByZero));
+        assert_eq!(eval("2 ** -1"), Err(ArithError::NegativeExponent));
+        assert_eq!(
+            eval("1 +"),
+            Err(ArithError::Syntax("end of expression".to_string()))
+        );
+        assert_eq!(eval("(1"), Err(ArithError::Syntax(")".to_string())));
+        assert_eq!(eval("1 2"), Err(ArithError::Syntax("2".to_string())));
+        assert_eq!(eval("3 = 4"), Err(ArithError::Syntax("=".to_string())));
+
+        let mut vars = HashMap::new();
+        vars.insert("loop".to_string(), "loop".to_string());
+        assert_eq!(evaluate("loop", &mut vars), Err(ArithError::Recursion));
+        assert!(check("1 + (2").is_err());
+        assert!(check("x++ * 2").is_ok());
+    }
+}
@@ -1,10 +1,111 @@
 //! シェルの抽象構文木（AST）型定義
+//!
+//! ワード・単純コマンド・リダイレクト・パイプラインは入力中の範囲を持つ。
+//! 範囲は比較に含めないので、同じ内容のノードは位置が違っても等しい。
+
+/// 入力中の範囲（バイト位置、end は含まない）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Span {
+    pub fn new(start: u32, end: u32) -> Self {
+        Self { start, end }
+    }
+}
 
 /// 環境変数参照
 #[derive(Debug, Clone, PartialEq)]
 pub struct EnvRef {
     pub name: String,
-    pub default: Option<String>,
+    /// ${VAR:-word} などの展開演算子
+    pub op: Option<ParamOp>,
+    /// 演算子のオペランド中のコマンド置換の ID（入れ子も含め、出現順の連番）
+    pub substitutions: Vec<u32>,
+}
+
+impl EnvRef {
+    /// 演算子のない $VAR
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            op: None,
+            substitutions: Vec::new(),
+        }
+    }
+}
+
+/// パラメータ展開の演算子
+/// colon が真の場合は空文字列も未設定として扱う
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamOp {
+    /// ${#VAR}
+    Length,
+    /// ${VAR:-word}, ${VAR-word}
+    Default { colon: bool, word: String },
+    /// ${VAR:=word}, ${VAR=word}
+    Assign { colon: bool, word: String },
+    /// ${VAR:?message}, ${VAR?message}
+    Error { colon: bool, word: String },
+    /// ${VAR:+word}, ${VAR+word}
+    Alternative { colon: bool, word: String },
+    /// ${VAR#pattern}, ${VAR##pattern}
+    RemovePrefix { pattern: String, longest: bool },
+    /// ${VAR%pattern}, ${VAR%%pattern}
+    RemoveSuffix { pattern: String, longest: bool },
+    /// ${VAR/pattern/replacement} とその変種
+    Replace {
+        pattern: String,
+        replacement: String,
+        mode: ReplaceMode,
+    },
+    /// ${VAR:offset}, ${VAR:offset:length}（どちらも算術式）
+    Substring {
+        offset: String,
+        length: Option<String>,
+    },
+    /// ${VAR^pattern}, ${VAR^^pattern}, ${VAR,pattern}, ${VAR,,pattern}
+    CaseMod {
+        upper: bool,
+        all: bool,
+        pattern: String,
+    },
+}
+
+impl ParamOp {
+    /// ワードとしてパースするオペランド（出現順）
+    pub fn operands(&self) -> Vec<&str> {
+        match self {
+            ParamOp::Default { word, .. }
+            | ParamOp::Assign { word, .. }
+            | ParamOp::Error { word, .. }
+            | ParamOp::Alternative { word, .. } => vec![word],
+            ParamOp::RemovePrefix { pattern, .. }
+            | ParamOp::RemoveSuffix { pattern, .. }
+            | ParamOp::CaseMod { pattern, .. } => vec![pattern],
+            ParamOp::Replace {
+                pattern,
+                replacement,
+                ..
+            } => vec![pattern, replacement],
+            ParamOp::Length | ParamOp::Substring { .. } => Vec::new(),
+        }
+    }
+}
+
+/// ${VAR/pattern/replacement} の置換方法
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaceMode {
+    /// 最初の 1 箇所 (/)
+    First,
+    /// すべて (//)
+    All,
+    /// 先頭に一致する場合のみ (/#)
+    Prefix,
+    /// 末尾に一致する場合のみ (/%)
+    Suffix,
 }
 
 /// ワードセグメント
@@ -12,31 +113,124 @@ pub struct EnvRef {
 pub enum WordSegment {
     /// リテラル文字列
     Literal(String),
-    /// 環境変数参照 ($VAR, ${VAR}, ${VAR:-default})
+    /// 環境変数参照 ($VAR, ${VAR}, ${VAR:-default} など)
     EnvVar(EnvRef),
     /// コマンド置換 $()
     CommandSubst(u32),
+    /// 位置パラメータ ($0 - $9, ${10} など)
+    Positional(u32),
+    /// 特殊パラメータ ($?, $$ など)
+    Special(SpecialParam),
+    /// チルダ接頭辞（~ の後ろ。~ は空、~user は user、~+ は +、~- は -）
+    Tilde(String),
+    /// 算術式展開 $(( ... ))（中身の式）
+    Arith(String),
 }
 
-/// パース済みワード
+/// 特殊パラメータ
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpecialParam {
+    /// $? 直前のコマンドの終了ステータス
+    ExitStatus,
+    /// $$ シェルのプロセス ID
+    Pid,
+    /// $! 直前のバックグラウンドジョブのプロセス ID
+    LastBackgroundPid,
+    /// $# 位置パラメータの数
+    ArgCount,
+    /// $@ 位置パラメータ（それぞれ別のフィールド）
+    AllArgs,
+    /// $* 位置パラメータ（1 つに連結）
+    AllArgsJoined,
+}
+
+impl SpecialParam {
+    /// パラメータ名（$ の後ろの 1 文字）から変換
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            '?' => Some(Self::ExitStatus),
+            '$' => Some(Self::Pid),
+            '!' => Some(Self::LastBackgroundPid),
+            '#' => Some(Self::ArgCount),
+            '@' => Some(Self::AllArgs),
+            '*' => Some(Self::AllArgsJoined),
+            _ => None,
+        }
+    }
+}
+
+/// ワードの断片
 #[derive(Debug, Clone, PartialEq)]
+pub struct WordPart {
+    pub segment: WordSegment,
+    /// クォートされていた（フィールド分割とパス名展開を行わない）
+    pub quoted: bool,
+}
+
+/// パース済みワード
+/// `a"b"'c'$X` のように空白を挟まずに続く断片は 1 つのワードになる
+#[derive(Debug, Clone)]
 pub struct ParsedWord {
-    pub segments: Vec<WordSegment>,
+    pub parts: Vec<WordPart>,
+    pub span: Span,
+}
+
+impl PartialEq for ParsedWord {
+    fn eq(&self, other: &Self) -> bool {
+        self.parts == other.parts
+    }
 }
 
 impl ParsedWord {
+    /// クォートされていないセグメントの列からワードを作成
+    pub fn unquoted(segments: Vec<WordSegment>) -> Self {
+        Self {
+            parts: segments
+                .into_iter()
+                .map(|segment| WordPart {
+                    segment,
+                    quoted: false,
+                })
+                .collect(),
+            span: Span::default(),
+        }
+    }
+
     /// リテラル文字列からワードを作成
     pub fn literal(s: impl Into<String>) -> Self {
-        Self {
-            segments: vec![WordSegment::Literal(s.into())],
+        Self::unquoted(vec![WordSegment::Literal(s.into())])
+    }
+
+    /// すべての断片をクォートされたものとして扱う
+    pub fn into_quoted(mut self) -> Self {
+        for part in &mut self.parts {
+            part.quoted = true;
         }
+        self
     }
 
-    /// 空のワードかどうか
-    pub fn is_empty(&self) -> bool {
-        self.segments.is_empty()
-            || (self.segments.len() == 1
-                && matches!(&self.segments[0], WordSegment::Literal(s) if s.is_empty()))
+    /// 後ろにワードを連結する（クォートの有無が同じリテラルが隣り合えばまとめる）
+    pub fn append(&mut self, other: ParsedWord) {
+        for part in other.parts {
+            match (self.parts.last_mut(), part) {
+                (
+                    Some(WordPart {
+                        segment: WordSegment::Literal(prev),
+                        quoted,
+                    }),
+                    WordPart {
+                        segment: WordSegment::Literal(s),
+                        quoted: next_quoted,
+                    },
+                ) if *quoted == next_quoted => prev.push_str(&s),
+                (_, part) => self.parts.push(part),
+            }
+        }
+    }
+
+    /// セグメントを順に返す
+    pub fn segments(&self) -> impl Iterator<Item = &WordSegment> {
+        self.parts.iter().map(|part| &part.segment)
     }
 }
 
@@ -49,20 +243,62 @@ pub enum RedirectKind {
     Stdout,
     /// 標準出力追記 (>>)
     StdoutAppend,
+    /// ヒアドキュメント (<<, <<-)
+    HereDoc,
+    /// ヒアストリング (<<<)
+    HereString,
+    /// 読み書き (<>)
+    ReadWrite,
 }
 
-/// リダイレクト
+impl RedirectKind {
+    /// IO 番号が省略されたときの対象ファイルディスクリプタ
+    pub fn default_fd(&self) -> u32 {
+        match self {
+            RedirectKind::Stdout | RedirectKind::StdoutAppend => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// リダイレクト先
 #[derive(Debug, Clone, PartialEq)]
+pub enum RedirectTarget {
+    /// ファイル名（ヒアドキュメントでは本文、ヒアストリングでは文字列）
+    Word(ParsedWord),
+    /// 複製元のファイルディスクリプタ（>&2 の 2）
+    Fd(u32),
+}
+
+/// リダイレクト
+#[derive(Debug, Clone)]
 pub struct Redirect {
+    /// 対象のファイルディスクリプタ
+    pub fd: u32,
     pub kind: RedirectKind,
-    pub target: ParsedWord,
+    pub target: RedirectTarget,
+    /// IO 番号からターゲットまで（ヒアドキュメントの本文は含まない）
+    pub span: Span,
+}
+
+impl PartialEq for Redirect {
+    fn eq(&self, other: &Self) -> bool {
+        self.fd == other.fd && self.kind == other.kind && self.target == other.target
+    }
 }
 
 /// 単純コマンド（パイプや条件なし）
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct SimpleCommand {
     pub args: Vec<ParsedWord>,
     pub redirects: Vec<Redirect>,
+    pub span: Span,
+}
+
+impl PartialEq for SimpleCommand {
+    fn eq(&self, other: &Self) -> bool {
+        self.args == other.args && self.redirects == other.redirects
+    }
 }
 
 impl SimpleCommand {
@@ -70,6 +306,7 @@ impl SimpleCommand {
         Self {
             args: Vec::new(),
             redirects: Vec::new(),
+            span: Span::default(),
         }
     }
 
@@ -84,22 +321,164 @@ impl Default for SimpleCommand {
     }
 }
 
-/// パイプライン（パイプで接続されたコマンド群）
+/// コマンド（パイプラインの構成要素）
 #[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// 単純コマンド
+    Simple(SimpleCommand),
+    /// 複合コマンド（ParseResult::compounds のインデックス）
+    Compound(u32),
+    /// break / continue
+    LoopControl(LoopControl),
+    /// 関数定義（ParseResult::functions のインデックス）
+    FunctionDef(u32),
+}
+
+/// ループ制御の種別
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopControlKind {
+    Break,
+    Continue,
+}
+
+/// ループ制御（break [n] / continue [n]）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopControl {
+    pub kind: LoopControlKind,
+    /// 対象とするループの深さ（1 が最も内側）
+    pub depth: u32,
+}
+
+/// if / elif 節
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfBranch {
+    pub condition: Vec<ConditionalElement>,
+    pub body: Vec<ConditionalElement>,
+}
+
+/// if 文
+#[derive(Debug, Clone, PartialEq)]
+pub struct IfClause {
+    /// if 節と elif 節（先頭から順に評価）
+    pub branches: Vec<IfBranch>,
+    pub else_body: Option<Vec<ConditionalElement>>,
+}
+
+/// ループ種別
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoopKind {
+    /// 条件が成功する間繰り返す
+    While,
+    /// 条件が失敗する間繰り返す
+    Until,
+}
+
+/// while / until ループ
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopClause {
+    pub kind: LoopKind,
+    pub condition: Vec<ConditionalElement>,
+    pub body: Vec<ConditionalElement>,
+}
+
+/// for ループ
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForClause {
+    pub variable: String,
+    /// in に続くワードリスト（None の場合は位置パラメータを対象とする）
+    pub words: Option<Vec<ParsedWord>>,
+    pub body: Vec<ConditionalElement>,
+}
+
+/// case の分岐
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseArm {
+    /// `|` で区切られたパターン
+    pub patterns: Vec<ParsedWord>,
+    pub body: Vec<ConditionalElement>,
+}
+
+/// case 文
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseClause {
+    pub word: ParsedWord,
+    pub arms: Vec<CaseArm>,
+}
+
+/// 複合コマンドの種別
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundKind {
+    /// if ... then ... elif ... else ... fi
+    If(IfClause),
+    /// while / until ... do ... done
+    Loop(LoopClause),
+    /// for name in words; do ... done
+    For(ForClause),
+    /// case word in pattern) ... ;; esac
+    Case(CaseClause),
+    /// { list; }
+    BraceGroup(Vec<ConditionalElement>),
+    /// ( list )
+    Subshell(Vec<ConditionalElement>),
+}
+
+/// 複合コマンド
+#[derive(Debug, Clone)]
+pub struct CompoundCommand {
+    pub kind: CompoundKind,
+    /// 複合コマンド全体に適用されるリダイレクト
+    pub redirects: Vec<Redirect>,
+    /// 開始の予約語または ( からリダイレクトの終わりまで
+    pub span: Span,
+}
+
+impl PartialEq for CompoundCommand {
+    fn eq(&self, other: &Self) -> bool {
+        self.kind == other.kind && self.redirects == other.redirects
+    }
+}
+
+/// 関数定義
+#[derive(Debug, Clone)]
+pub struct FunctionDef {
+    pub name: String,
+    /// 本体の複合コマンド（ParseResult::compounds のインデックス）
+    pub body: u32,
+    /// 関数名から本体の終わりまで
+    pub span: Span,
+}
+
+impl PartialEq for FunctionDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.body == other.body
+    }
+}
+
+/// パイプライン（パイプで接続されたコマンド群）
+#[derive(Debug, Clone)]
 pub struct Pipeline {
-    pub commands: Vec<SimpleCommand>,
+    pub commands: Vec<Command>,
+    pub span: Span,
+}
+
+impl PartialEq for Pipeline {
+    fn eq(&self, other: &Self) -> bool {
+        self.commands == other.commands
+    }
 }
 
 impl Pipeline {
     pub fn new() -> Self {
         Self {
             commands: Vec::new(),
+            span: Span::default(),
         }
     }
 
     pub fn single(cmd: SimpleCommand) -> Self {
         Self {
-            commands: vec![cmd],
+            span: cmd.span,
+            commands: vec![Command::Simple(cmd)],
         }
     }
 }
@@ -119,13 +498,27 @@ pub enum Connector {
     And,
     /// || (前のコマンドが失敗した場合のみ実行)
     Or,
+    /// ; (前のコマンドの結果に関わらず実行)
+    Sequential,
 }
 
 /// 条件要素
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone)]
 pub struct ConditionalElement {
     pub connector: Connector,
     pub pipeline: Pipeline,
+    /// & で終わる AND-OR リストに属する（リスト内の全要素に付与）
+    pub background: bool,
+    /// パイプラインの範囲（直後が & ならそれも含む）
+    pub span: Span,
+}
+
+impl PartialEq for ConditionalElement {
+    fn eq(&self, other: &Self) -> bool {
+        self.connector == other.connector
+            && self.pipeline == other.pipeline
+            && self.background == other.background
+    }
 }
 
 /// コマンド置換
@@ -140,6 +533,10 @@ pub struct CommandSubstitution {
 pub struct ParseResult {
     pub elements: Vec<ConditionalElement>,
     pub substitutions: Vec<CommandSubstitution>,
+    /// 複合コマンドのテーブル（Command::Compound から参照）
+    pub compounds: Vec<CompoundCommand>,
+    /// 関数定義のテーブル（Command::FunctionDef から参照）
+    pub functions: Vec<FunctionDef>,
 }
 
 impl ParseResult {
@@ -147,6 +544,8 @@ impl ParseResult {
         Self {
             elements: Vec::new(),
             substitutions: Vec::new(),
+            compounds: Vec::new(),
+            functions: Vec::new(),
         }
     }
 
@@ -154,9 +553,13 @@ impl ParseResult {
         Self {
             elements: vec![ConditionalElement {
                 connector: Connector::None,
+                span: cmd.span,
                 pipeline: Pipeline::single(cmd),
+                background: false,
             }],
             substitutions: Vec::new(),
+            compounds: Vec::new(),
+            functions: Vec::new(),
         }
     }
 }
@@ -172,7 +575,10 @@ impl Default for ParseResult {
 #[error("{message} at position {position}")]
 pub struct ParseError {
     pub message: String,
+    /// 入力中のバイト位置
     pub position: u32,
+    /// 入力が途中で終わっている（続きの行があればパースできる）場合の理由
+    pub incomplete: Option<Incomplete>,
 }
 
 impl ParseError {
@@ -180,6 +586,37 @@ impl ParseError {
         Self {
             message: message.into(),
             position,
+            incomplete: None,
         }
     }
+
+    /// 入力の終わりで構文が閉じていないエラー
+    pub fn incomplete(message: impl Into<String>, position: u32, reason: Incomplete) -> Self {
+        Self {
+            message: message.into(),
+            position,
+            incomplete: Some(reason),
+        }
+    }
+}
+
+/// 入力が途中で終わっている理由
+#[derive(Debug, Clone, PartialEq)]
+pub enum Incomplete {
+    /// シングルクォートが閉じていない
+    SingleQuote,
+    /// ダブルクォートが閉じていない
+    DoubleQuote,
+    /// 行末の \
+    LineContinuation,
+    /// ヒアドキュメントの終端行がない（区切り文字）
+    HereDoc(String),
+    /// コマンド置換 $( が閉じていない
+    CommandSubstitution,
+    /// | の後にコマンドがない
+    Pipe,
+    /// && または || の後にコマンドがない
+    AndOr,
+    /// 複合コマンドが閉じていない（待っている予約語または `)`）
+    Compound(String),
 }
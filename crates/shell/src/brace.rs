@@ -0,0 +1,278 @@
+//! ブレース展開
+//!
+//! `{a,b,c}` の選択肢と `{1..10..2}`, `{a..z}` の連番を展開する。入れ子にも対応する。
+//! 変数展開より前に、クォートされていないワードに対して行う。
+
+/// 1 つのワードから展開できるワード数の上限
+pub const MAX_WORDS: usize = 10_000;
+
+/// 展開後のワードが多すぎる
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("brace expansion produces more than {MAX_WORDS} words")]
+pub struct TooManyWords;
+
+/// ワードをブレース展開する
+/// 展開できるブレースがなければワードをそのまま返す
+pub fn expand(word: &str) -> Result<Vec<String>, TooManyWords> {
+    let chars: Vec<char> = word.chars().collect();
+
+    let Some((open, close, items)) = find_brace(&chars)? else {
+        return Ok(vec![word.to_string()]);
+    };
+
+    let prefix: String = chars[..open].iter().collect();
+    let suffixes = expand(&chars[close + 1..].iter().collect::<String>())?;
+
+    let mut result = Vec::new();
+    for item in items {
+        for expanded in expand(&item)? {
+            if result.len() + suffixes.len() > MAX_WORDS {
+                return Err(TooManyWords);
+            }
+            for suffix in &suffixes {
+                result.push(format!("{}{}{}", prefix, expanded, suffix));
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// 展開できる最初のブレースを探す
+/// 戻り値は ({ の位置, } の位置, 展開後の要素)
+fn find_brace(chars: &[char]) -> Result<Option<(usize, usize, Vec<String>)>, TooManyWords> {
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => {
+                i += 2;
+                continue;
+            }
+            // ${VAR} は変数参照なので読み飛ばす
+            '$' if chars.get(i + 1) == Some(&'{') => {
+                i = matching_close(chars, i + 1).map_or(chars.len(), |end| end + 1);
+                continue;
+            }
+            '{' => {
+                if let Some(close) = matching_close(chars, i) {
+                    let inner = &chars[i + 1..close];
+                    let items = match split_items(inner) {
+                        Some(items) => Some(items),
+                        None => sequence(inner)?,
+                    };
+                    if let Some(items) = items {
+                        return Ok(Some((i, close, items)));
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    Ok(None)
+}
+
+/// 対応する } の位置を探す
+fn matching_close(chars: &[char], open: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut i = open;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// トップレベルの , で選択肢に分割（, がなければ None）
+fn split_items(inner: &[char]) -> Option<Vec<String>> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < inner.len() {
+        let c = inner[i];
+        match c {
+            '\\' => {
+                current.push(c);
+                if let Some(&next) = inner.get(i + 1) {
+                    current.push(next);
+                }
+                i += 2;
+                continue;
+            }
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(std::mem::take(&mut current));
+                i += 1;
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+        i += 1;
+    }
+
+    if items.is_empty() {
+        return None;
+    }
+    items.push(current);
+    Some(items)
+}
+
+/// 連番 {start..end} または {start..end..step} を展開（連番でなければ None）
+fn sequence(inner: &[char]) -> Result<Option<Vec<String>>, TooManyWords> {
+    let inner: String = inner.iter().collect();
+    let parts: Vec<&str> = inner.split("..").collect();
+    let (start, end, step) = match parts[..] {
+        [start, end] => (start, end, None),
+        [start, end, step] => match step.parse::<i64>() {
+            Ok(step) => (start, end, Some(step)),
+            Err(_) => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    // 増分の符号は無視し、向きは始点と終点から決める
+    let step = step.map_or(1, |s| s.unsigned_abs().max(1)) as usize;
+
+    if let (Ok(from), Ok(to)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        // 0 埋めされた端点があれば桁数を揃える
+        let padded = |s: &str| {
+            let digits = s.trim_start_matches('-');
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = if padded(start) || padded(end) {
+            start.len().max(end.len())
+        } else {
+            0
+        };
+        // i64::MIN は符号を外せないので連番として扱わない
+        return Ok(range(from, to, step)?
+            .map(|n| {
+                if n < 0 {
+                    let abs = n.checked_neg()?;
+                    Some(format!("-{:0>w$}", abs, w = width.saturating_sub(1)))
+                } else {
+                    Some(format!("{:0>w$}", n, w = width))
+                }
+            })
+            .collect());
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(from), None, Some(to), None)
+            if from.is_ascii_alphabetic() && to.is_ascii_alphabetic() =>
+        {
+            let values = range(from as i64, to as i64, step)?;
+            Ok(Some(
+                values.map(|n| (n as u8 as char).to_string()).collect(),
+            ))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// 始点から終点まで（両端を含む）step ずつ進む
+/// 要素数が上限を超える場合はエラー
+fn range(from: i64, to: i64, step: usize) -> Result<Box<dyn Iterator<Item = i64>>, TooManyWords> {
+    let count = (from as i128 - to as i128).unsigned_abs() / step as u128 + 1;
+    if count > MAX_WORDS as u128 {
+        return Err(TooManyWords);
+    }
+    Ok(if from <= to {
+        Box::new((from..=to).step_by(step))
+    } else {
+        Box::new((to..=from).rev().step_by(step))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alternatives() {
+        assert_eq!(
+            expand("notes/{2025,2026}").unwrap(),
+            vec!["notes/2025", "notes/2026"]
+        );
+        assert_eq!(expand("a{b,c}d").unwrap(), vec!["abd", "acd"]);
+        assert_eq!(expand("{a,b}{1,2}").unwrap(), vec!["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("x{,y}").unwrap(), vec!["x", "xy"]);
+    }
+
+    #[test]
+    fn test_nested() {
+        assert_eq!(expand("{a,b{1,2}}c").unwrap(), vec!["ac", "b1c", "b2c"]);
+        assert_eq!(expand("{a,{b,c}").unwrap(), vec!["{a,b", "{a,c"]);
+    }
+
+    #[test]
+    fn test_numeric_sequence() {
+        assert_eq!(
+            expand("log{1..5}.txt").unwrap(),
+            vec!["log1.txt", "log2.txt", "log3.txt", "log4.txt", "log5.txt"]
+        );
+        assert_eq!(expand("{1..10..3}").unwrap(), vec!["1", "4", "7", "10"]);
+        assert_eq!(expand("{3..1}").unwrap(), vec!["3", "2", "1"]);
+        assert_eq!(expand("{-1..1}").unwrap(), vec!["-1", "0", "1"]);
+        assert_eq!(expand("{5..1..-2}").unwrap(), vec!["5", "3", "1"]);
+    }
+
+    #[test]
+    fn test_zero_padded_sequence() {
+        assert_eq!(expand("{08..11}").unwrap(), vec!["08", "09", "10", "11"]);
+        assert_eq!(expand("{1..003}").unwrap(), vec!["001", "002", "003"]);
+    }
+
+    #[test]
+    fn test_char_sequence() {
+        assert_eq!(expand("{a..e..2}").unwrap(), vec!["a", "c", "e"]);
+        assert_eq!(expand("{c..a}").unwrap(), vec!["c", "b", "a"]);
+    }
+
+    #[test]
+    fn test_not_expanded() {
+        assert_eq!(expand("{}").unwrap(), vec!["{}"]);
+        assert_eq!(expand("{a}").unwrap(), vec!["{a}"]);
+        assert_eq!(expand("{").unwrap(), vec!["{"]);
+        assert_eq!(expand("${HOME}").unwrap(), vec!["${HOME}"]);
+        assert_eq!(expand("${X:-a,b}").unwrap(), vec!["${X:-a,b}"]);
+        assert_eq!(expand("\\{a,b}").unwrap(), vec!["\\{a,b}"]);
+        assert_eq!(expand("{1..a}").unwrap(), vec!["{1..a}"]);
+        assert_eq!(
+            expand("{-9223372036854775808..-9223372036854775807}").unwrap(),
+            vec!["{-9223372036854775808..-9223372036854775807}"]
+        );
+    }
+
+    #[test]
+    fn test_too_many_words() {
+        assert_eq!(expand("{1..10000}").unwrap().len(), 10_000);
+        assert_eq!(expand("{1..10001}"), Err(TooManyWords));
+        assert_eq!(expand("{1..100000000}"), Err(TooManyWords));
+        assert_eq!(
+            expand("{-9223372036854775807..9223372036854775807}"),
+            Err(TooManyWords)
+        );
+        assert_eq!(expand("{1..1000}{1..1000}"), Err(TooManyWords));
+        assert_eq!(expand("{a,b}{1..6000}"), Err(TooManyWords));
+    }
+}
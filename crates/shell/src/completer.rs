@@ -0,0 +1,391 @@
+//! タブ補完
+//!
+//! カーソルより前の入力をシンタックスハイライトと同じ規則で読み、カーソル位置のワードが
+//! コマンド名・引数・リダイレクト先・変数参照のどれかを判断して候補を返す。
+//! コマンド名や変数名などの一覧は `CompletionSource` から受け取る。
+
+use crate::ast::Span;
+use crate::glob::DirEntry;
+use crate::highlight::{current_word, HighlightKind};
+
+/// 補完候補の取得元
+pub trait CompletionSource {
+    /// PATH から実行できるコマンド名
+    fn commands(&self) -> Vec<String>;
+    /// ディレクトリのエントリ（`dir` は入力されたままのパス。空文字列ならカレントディレクトリ）
+    fn list_dir(&self, dir: &str) -> Vec<DirEntry>;
+    /// 環境変数名
+    fn env_names(&self) -> Vec<String>;
+    /// エイリアス名
+    fn aliases(&self) -> Vec<String>;
+}
+
+/// 補完候補の種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateKind {
+    Command,
+    Alias,
+    File,
+    Directory,
+    Variable,
+}
+
+/// 補完候補
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// 置き換える範囲に入れる文字列（必要なエスケープ済み）
+    pub value: String,
+    pub kind: CandidateKind,
+}
+
+/// 補完結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// 候補で置き換える範囲（バイト位置）
+    pub replace: Span,
+    /// 候補（ソート済み）
+    pub candidates: Vec<Candidate>,
+}
+
+/// カーソル位置のワードを補完する
+/// `cursor` は `line` 中のバイト位置で、カーソルより後ろは見ない
+pub fn complete<S: CompletionSource>(line: &str, cursor: usize, source: &S) -> Completion {
+    let cursor = cursor.min(line.len());
+    let none = Completion {
+        replace: Span::new(cursor as u32, cursor as u32),
+        candidates: Vec::new(),
+    };
+    let Some(input) = line.get(..cursor) else {
+        return none;
+    };
+    let Some(word) = current_word(input) else {
+        return none;
+    };
+    let text = &input[word.start as usize..];
+
+    if let Some((offset, name, braced)) = variable_prefix(text) {
+        let candidates = matching(source.env_names(), name)
+            .map(|name| Candidate {
+                value: if braced { format!("{}}}", name) } else { name },
+                kind: CandidateKind::Variable,
+            })
+            .collect();
+        return Completion {
+            replace: Span::new(word.start + offset as u32, cursor as u32),
+            candidates,
+        };
+    }
+
+    // 変数などの展開を含むワードは補完しない
+    let Some((quote, prefix)) = unquote(text) else {
+        return none;
+    };
+    let candidates = match word.kind {
+        HighlightKind::Command | HighlightKind::Keyword
+            if !word.redirect_target && !prefix.contains('/') =>
+        {
+            commands(source, &prefix)
+        }
+        // for の変数名
+        HighlightKind::Variable => Vec::new(),
+        _ => files(source, &prefix),
+    };
+
+    Completion {
+        replace: Span::new(word.start, cursor as u32),
+        candidates: candidates
+            .into_iter()
+            .map(|c| Candidate {
+                value: quoted(&c.value, quote),
+                kind: c.kind,
+            })
+            .collect(),
+    }
+}
+
+/// コマンド名とエイリアスの候補
+fn commands<S: CompletionSource>(source: &S, prefix: &str) -> Vec<Candidate> {
+    let mut candidates: Vec<Candidate> = matching(source.aliases(), prefix)
+        .map(|value| Candidate {
+            value,
+            kind: CandidateKind::Alias,
+        })
+        .chain(matching(source.commands(), prefix).map(|value| Candidate {
+            value,
+            kind: CandidateKind::Command,
+        }))
+        .collect();
+    // 同名ならエイリアスを残す
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates.dedup_by(|a, b| a.value == b.value);
+    candidates
+}
+
+/// ファイル名の候補（ディレクトリには / を付ける）
+fn files<S: CompletionSource>(source: &S, prefix: &str) -> Vec<Candidate> {
+    let (dir, name) = match prefix.rfind('/') {
+        Some(i) => prefix.split_at(i + 1),
+        None => ("", prefix),
+    };
+    let mut candidates: Vec<Candidate> = source
+        .list_dir(dir)
+        .into_iter()
+        .filter(|entry| entry.name.starts_with(name))
+        // . で始まる名前は入力も . で始まる場合だけ
+        .filter(|entry| !entry.name.starts_with('.') || name.starts_with('.'))
+        .map(|entry| {
+            if entry.is_dir {
+                Candidate {
+                    value: format!("{}{}/", dir, entry.name),
+                    kind: CandidateKind::Directory,
+                }
+            } else {
+                Candidate {
+                    value: format!("{}{}", dir, entry.name),
+                    kind: CandidateKind::File,
+                }
+            }
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.value.cmp(&b.value));
+    candidates
+}
+
+/// 前方一致する名前（ソート・重複除去済み）
+fn matching(mut names: Vec<String>, prefix: &str) -> impl Iterator<Item = String> + '_ {
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(move |name| name.starts_with(prefix))
+}
+
+/// 末尾が変数参照の途中なら ($ の次の位置, 入力済みの名前, ${ か) を返す
+fn variable_prefix(text: &str) -> Option<(usize, &str, bool)> {
+    let mut dollar = None;
+    let mut single_quoted = false;
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if !single_quoted => {
+                chars.next();
+            }
+            '\'' => single_quoted = !single_quoted,
+            '$' if !single_quoted => dollar = Some(i),
+            _ => {}
+        }
+    }
+
+    let start = dollar? + 1;
+    let rest = &text[start..];
+    let (start, name, braced) = match rest.strip_prefix('{') {
+        Some(name) => (start + 1, name, true),
+        None => (start, rest, false),
+    };
+    let valid = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !name.starts_with(|c: char| c.is_ascii_digit());
+    valid.then_some((start, name, braced))
+}
+
+/// ワードのクォートとエスケープを外す
+/// 戻り値は (閉じていないクォート, 文字列)。展開を含む場合は None
+fn unquote(text: &str) -> Option<(Option<char>, String)> {
+    let mut result = String::new();
+    let mut quote = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => quote = None,
+            (Some('\''), _) => result.push(c),
+            (None, '\'' | '"') => quote = Some(c),
+            (_, '$' | '`') => return None,
+            (None, '\\') => result.extend(chars.next()),
+            (Some(_), '\\') => match chars.peek() {
+                Some(&next) if matches!(next, '"' | '\\' | '$' | '`') => {
+                    result.push(next);
+                    chars.next();
+                }
+                _ => result.push(c),
+            },
+            _ => result.push(c),
+        }
+    }
+    Some((quote, result))
+}
+
+/// 候補を入力中のクォートに合わせてエスケープする
+fn quoted(value: &str, quote: Option<char>) -> String {
+    let mut result = String::new();
+    match quote {
+        Some('\'') => {
+            result.push('\'');
+            result.push_str(value);
+        }
+        Some(q) => {
+            result.push(q);
+            for c in value.chars() {
+                if matches!(c, '"' | '\\' | '$' | '`') {
+                    result.push('\\');
+                }
+                result.push(c);
+            }
+        }
+        None => {
+            for c in value.chars() {
+                if c.is_whitespace() || "'\"\\$`|&;<>()*?[]{}#~!".contains(c) {
+                    result.push('\\');
+                }
+                result.push(c);
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用の取得元
+    struct Source;
+
+    impl CompletionSource for Source {
+        fn commands(&self) -> Vec<String> {
+            ["cat", "cd", "echo", "ls", "grep"]
+                .map(String::from)
+                .to_vec()
+        }
+
+        fn list_dir(&self, dir: &str) -> Vec<DirEntry> {
+            let names: &[&str] = match dir {
+                "" => &["docs/", "notes.md", "my file.txt", ".hidden"],
+                "docs/" => &["guide.md", "api/"],
+                _ => &[],
+            };
+            names
+                .iter()
+                .map(|n| DirEntry {
+                    name: n.trim_end_matches('/').to_string(),
+                    is_dir: n.ends_with('/'),
+                })
+                .collect()
+        }
+
+        fn env_names(&self) -> Vec<String> {
+            ["HOME", "HOSTNAME", "PATH"].map(String::from).to_vec()
+        }
+
+        fn aliases(&self) -> Vec<String> {
+            vec!["ll".to_string()]
+        }
+    }
+
+    /// (置き換える文字列, 候補)
+    fn complete_at_end(line: &str) -> (&str, Vec<String>) {
+        let completion = complete(line, line.len(), &Source);
+        let replaced = &line[completion.replace.start as usize..completion.replace.end as usize];
+        (
+            replaced,
+            completion.candidates.into_iter().map(|c| c.value).collect(),
+        )
+    }
+
+    #[test]
+    fn test_command_position() {
+        assert_eq!(complete_at_end("c"), ("c", vec!["cat".into(), "cd".into()]));
+        assert_eq!(complete_at_end("l"), ("l", vec!["ll".into(), "ls".into()]));
+        assert_eq!(complete_at_end("ls | g"), ("g", vec!["grep".to_string()]));
+        assert_eq!(complete_at_end("FOO=1 e"), ("e", vec!["echo".to_string()]));
+        assert_eq!(
+            complete_at_end("if true; then e"),
+            ("e", vec!["echo".to_string()])
+        );
+        assert_eq!(
+            complete_at_end("echo $(ca"),
+            ("ca", vec!["cat".to_string()])
+        );
+
+        let completion = complete("ll", 2, &Source);
+        assert_eq!(completion.candidates[0].kind, CandidateKind::Alias);
+    }
+
+    #[test]
+    fn test_arguments() {
+        assert_eq!(
+            complete_at_end("cat n"),
+            ("n", vec!["notes.md".to_string()])
+        );
+        assert_eq!(
+            complete_at_end("cat "),
+            (
+                "",
+                vec!["docs/".into(), "my\\ file.txt".into(), "notes.md".into()]
+            )
+        );
+        assert_eq!(
+            complete_at_end("cat docs/"),
+            ("docs/", vec!["docs/api/".into(), "docs/guide.md".into()])
+        );
+        assert_eq!(complete_at_end("cat ."), (".", vec![".hidden".to_string()]));
+        // / を含むコマンド名はファイルとして補完する
+        assert_eq!(
+            complete_at_end("docs/g"),
+            ("docs/g", vec!["docs/guide.md".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_quoted_arguments() {
+        assert_eq!(
+            complete_at_end("cat my\\ "),
+            ("my\\ ", vec!["my\\ file.txt".to_string()])
+        );
+        assert_eq!(
+            complete_at_end("cat 'my"),
+            ("'my", vec!["'my file.txt".to_string()])
+        );
+        assert_eq!(
+            complete_at_end("cat \"my"),
+            ("\"my", vec!["\"my file.txt".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_redirect_target() {
+        assert_eq!(complete_at_end(">n"), ("n", vec!["notes.md".to_string()]));
+        assert_eq!(
+            complete_at_end("echo hi > d"),
+            ("d", vec!["docs/".to_string()])
+        );
+        // リダイレクト先の後はまだコマンド名
+        assert_eq!(complete_at_end("> out e"), ("e", vec!["echo".to_string()]));
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            complete_at_end("echo $HO"),
+            ("HO", vec!["HOME".into(), "HOSTNAME".into()])
+        );
+        assert_eq!(
+            complete_at_end("echo \"${P"),
+            ("P", vec!["PATH}".to_string()])
+        );
+        assert_eq!(
+            complete_at_end("cd $"),
+            ("", vec!["HOME".into(), "HOSTNAME".into(), "PATH".into()])
+        );
+        assert_eq!(complete_at_end("echo '$HO"), ("'$HO", vec![]));
+    }
+
+    #[test]
+    fn test_no_completion() {
+        assert_eq!(complete_at_end("echo hi # c"), ("", vec![]));
+        assert_eq!(complete_at_end("for x"), ("x", vec![]));
+        assert_eq!(complete_at_end("cat $HOME/d"), ("", vec![]));
+
+        // カーソルより後ろは見ない
+        let completion = complete("cat n rest", 5, &Source);
+        assert_eq!(completion.replace, Span::new(4, 5));
+    }
+}
@@ -1,28 +1,523 @@
 //! 変数展開
 
-use crate::ast::{ParsedWord, WordSegment};
+use crate::arith::{self, ArithError};
+use crate::ast::{EnvRef, ParamOp, ParsedWord, ReplaceMode, SpecialParam, WordSegment};
+use crate::parser::Parser;
+use crate::pattern;
 use std::collections::HashMap;
 
+/// 展開エラー
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ExpandError {
+    /// ${VAR:?message} で変数が未設定
+    #[error("{name}: {message}")]
+    Unset { name: String, message: String },
+    /// 位置パラメータや特殊パラメータへの ${1:=word}
+    #[error("${0}: cannot assign in this way")]
+    CannotAssign(String),
+    #[error(transparent)]
+    Arith(#[from] ArithError),
+}
+
+/// 展開時に参照するシェルの状態
+#[derive(Debug, Clone, Default)]
+pub struct ExpandContext {
+    pub env: HashMap<String, String>,
+    /// $0
+    pub arg0: String,
+    /// $1 以降
+    pub positional: Vec<String>,
+    /// $?
+    pub last_status: i32,
+    /// $$
+    pub pid: u32,
+    /// $!（バックグラウンドジョブを実行していなければ None）
+    pub last_background_pid: Option<u32>,
+    /// コマンド置換の結果（TypeScript 側で実行済み）
+    pub substitutions: HashMap<u32, String>,
+}
+
+/// IFS が未設定の場合の区切り文字
+const DEFAULT_IFS: &str = " \t\n";
+
+impl ExpandContext {
+    /// フィールド分割の区切り文字
+    fn ifs(&self) -> &str {
+        self.env.get("IFS").map_or(DEFAULT_IFS, String::as_str)
+    }
+
+    /// 名前でパラメータの値を引く（未設定なら None）
+    fn lookup(&self, name: &str) -> Option<String> {
+        if let Ok(n) = name.parse::<usize>() {
+            return self.positional_arg(n);
+        }
+        let mut chars = name.chars();
+        match (chars.next().and_then(SpecialParam::from_char), chars.next()) {
+            (Some(param), None) => self.special(param),
+            _ => self.env.get(name).cloned(),
+        }
+    }
+
+    /// 位置パラメータ（0 は $0）
+    fn positional_arg(&self, n: usize) -> Option<String> {
+        match n {
+            0 => Some(self.arg0.clone()),
+            n => self.positional.get(n - 1).cloned(),
+        }
+    }
+
+    /// 特殊パラメータの値
+    fn special(&self, param: SpecialParam) -> Option<String> {
+        match param {
+            SpecialParam::ExitStatus => Some(self.last_status.to_string()),
+            SpecialParam::Pid => Some(self.pid.to_string()),
+            SpecialParam::LastBackgroundPid => self.last_background_pid.map(|pid| pid.to_string()),
+            SpecialParam::ArgCount => Some(self.positional.len().to_string()),
+            // 位置パラメータが空なら未設定として扱う
+            SpecialParam::AllArgs => {
+                (!self.positional.is_empty()).then(|| self.positional.join(" "))
+            }
+            // $* は IFS の最初の文字で連結する
+            SpecialParam::AllArgsJoined => (!self.positional.is_empty()).then(|| {
+                let separator = self.ifs().chars().next().map(String::from);
+                self.positional.join(separator.as_deref().unwrap_or(""))
+            }),
+        }
+    }
+}
+
+/// 算術式からは位置パラメータや特殊パラメータも参照できる
+impl arith::Variables for ExpandContext {
+    fn get(&self, name: &str) -> Option<String> {
+        self.lookup(name)
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        self.env.insert(name.to_string(), value);
+    }
+}
+
 /// パース済みワードを展開
-pub fn expand_word(word: &ParsedWord, env: &HashMap<String, String>) -> String {
-    word.segments
-        .iter()
-        .map(|seg| match seg {
-            WordSegment::Literal(s) => s.clone(),
-            WordSegment::EnvVar(env_ref) => {
-                env.get(&env_ref.name)
-                    .cloned()
-                    .unwrap_or_else(|| env_ref.default.clone().unwrap_or_default())
+/// ${VAR:=word} や算術式の代入は呼び出し元の環境には反映しない
+#[cfg(test)]
+pub fn expand_word(word: &ParsedWord, ctx: &ExpandContext) -> Result<String, ExpandError> {
+    expand_segments(word, &mut ctx.clone())
+}
+
+/// 展開後のフィールド
+#[derive(Debug, Clone, PartialEq)]
+pub struct Field {
+    pub value: String,
+    /// パス名展開のパターン（クォートされていないワイルドカードを含む場合のみ）
+    /// クォートされていた部分の *, ?, [ などは \ でエスケープされる
+    pub pattern: Option<String>,
+}
+
+/// パース済みワードを展開し、IFS でフィールドに分割する
+/// クォートされていない展開結果だけを分割し、何も残らなければフィールドを作らない。
+/// ${VAR:=word} などの代入は `ctx.env` に反映する
+pub fn expand_fields(
+    word: &ParsedWord,
+    ctx: &mut ExpandContext,
+) -> Result<Vec<Field>, ExpandError> {
+    let ifs = ctx.ifs().to_string();
+    let mut fields = Fields::new(&ifs);
+
+    for part in &word.parts {
+        let quoted = part.quoted;
+        match &part.segment {
+            // リテラルは分割しない
+            WordSegment::Literal(s) => fields.push_text(s, quoted),
+            // チルダ展開の結果は分割もパス名展開もしない
+            WordSegment::Tilde(prefix) => fields.push_text(&expand_tilde(prefix, &ctx.env), true),
+            // "$@" は位置パラメータをそれぞれ別のフィールドにする
+            WordSegment::Special(SpecialParam::AllArgs) if quoted => {
+                for (i, arg) in ctx.positional.iter().enumerate() {
+                    if i > 0 {
+                        fields.finish();
+                    }
+                    fields.push_text(arg, true);
+                }
+            }
+            WordSegment::Special(SpecialParam::AllArgs | SpecialParam::AllArgsJoined)
+                if !quoted =>
+            {
+                for (i, arg) in ctx.positional.iter().enumerate() {
+                    if i > 0 {
+                        fields.delimit();
+                    }
+                    fields.push_split(arg);
+                }
+            }
+            seg => {
+                let value = segment_value(seg, ctx)?;
+                if quoted {
+                    fields.push_text(&value, true);
+                } else {
+                    fields.push_split(&value);
+                }
+            }
+        }
+    }
+    Ok(fields.finish_all())
+}
+
+/// パース済みワードを case 文のパターンに展開する
+/// クォートされていた部分とチルダ展開の結果はエスケープしてリテラルとして扱わせる
+pub fn expand_pattern(word: &ParsedWord, ctx: &mut ExpandContext) -> Result<String, ExpandError> {
+    let mut result = String::new();
+    for part in &word.parts {
+        let value = segment_value(&part.segment, ctx)?;
+        if part.quoted || matches!(part.segment, WordSegment::Tilde(_)) {
+            result.push_str(&pattern::escape(&value));
+        } else {
+            result.push_str(&value);
+        }
+    }
+    Ok(result)
+}
+
+/// パラメータ展開を評価し、${VAR:=word} などの代入を `ctx.env` に反映する
+pub fn expand_param(env_ref: &EnvRef, ctx: &mut ExpandContext) -> Result<String, ExpandError> {
+    let value = ctx.lookup(&env_ref.name);
+    let Some(op) = &env_ref.op else {
+        return Ok(value.unwrap_or_default());
+    };
+
+    // 演算子が word を使うかどうか（colon 付きなら空文字列も未設定扱い）
+    let missing = |colon: bool| match &value {
+        Some(v) => colon && v.is_empty(),
+        None => true,
+    };
+    let value_str = value.clone().unwrap_or_default();
+
+    // オペランドはパース時と同じコマンド置換の ID で読み直す（読めなければリテラル）
+    let operands = Parser::parse_operands(env_ref)
+        .unwrap_or_else(|_| op.operands().into_iter().map(ParsedWord::literal).collect());
+    let operand = |i: usize| &operands[i];
+
+    match op {
+        // ${#@} と ${#*} は位置パラメータの数
+        ParamOp::Length if matches!(env_ref.name.as_str(), "@" | "*") => {
+            Ok(ctx.positional.len().to_string())
+        }
+        ParamOp::Length => Ok(value_str.chars().count().to_string()),
+        ParamOp::Default { colon, .. } => {
+            if missing(*colon) {
+                expand_segments(operand(0), ctx)
+            } else {
+                Ok(value_str)
+            }
+        }
+        ParamOp::Assign { colon, .. } => {
+            if missing(*colon) {
+                if !is_valid_name(&env_ref.name) {
+                    return Err(ExpandError::CannotAssign(env_ref.name.clone()));
+                }
+                let assigned = expand_segments(operand(0), ctx)?;
+                ctx.env.insert(env_ref.name.clone(), assigned.clone());
+                Ok(assigned)
+            } else {
+                Ok(value_str)
             }
-            WordSegment::CommandSubst(_) => {
-                // コマンド置換はここでは展開しない
-                // TypeScript 側で解決される
-                String::new()
+        }
+        ParamOp::Error { colon, word } => {
+            if !missing(*colon) {
+                return Ok(value_str);
+            }
+            let message = if !word.is_empty() {
+                expand_segments(operand(0), ctx)?
+            } else if *colon {
+                "parameter null or not set".to_string()
+            } else {
+                "parameter not set".to_string()
+            };
+            Err(ExpandError::Unset {
+                name: env_ref.name.clone(),
+                message,
+            })
+        }
+        ParamOp::Alternative { colon, .. } => {
+            if missing(*colon) {
+                Ok(String::new())
+            } else {
+                expand_segments(operand(0), ctx)
+            }
+        }
+        ParamOp::RemovePrefix { longest, .. } => {
+            let pattern = expand_pattern(operand(0), ctx)?;
+            Ok(remove_prefix(&value_str, &pattern, *longest))
+        }
+        ParamOp::RemoveSuffix { longest, .. } => {
+            let pattern = expand_pattern(operand(0), ctx)?;
+            Ok(remove_suffix(&value_str, &pattern, *longest))
+        }
+        ParamOp::Replace { mode, .. } => {
+            let pattern = expand_pattern(operand(0), ctx)?;
+            let replacement = expand_segments(operand(1), ctx)?;
+            Ok(replace(&value_str, &pattern, &replacement, *mode))
+        }
+        ParamOp::Substring { offset, length } => {
+            let offset = arith::evaluate(offset, ctx)?;
+            let length = match length {
+                Some(length) => Some(arith::evaluate(length, ctx)?),
+                None => None,
+            };
+            Ok(substring(&value_str, offset, length))
+        }
+        ParamOp::CaseMod { upper, all, .. } => {
+            let pattern = expand_pattern(operand(0), ctx)?;
+            Ok(case_mod(&value_str, *upper, *all, &pattern))
+        }
+    }
+}
+
+/// パース済みワードを 1 つの文字列に展開する
+/// ${VAR:=word} や算術式の代入は `ctx.env` に反映する
+pub fn expand_segments(word: &ParsedWord, ctx: &mut ExpandContext) -> Result<String, ExpandError> {
+    let mut result = String::new();
+    for seg in word.segments() {
+        result.push_str(&segment_value(seg, ctx)?);
+    }
+    Ok(result)
+}
+
+/// セグメント 1 つを展開
+fn segment_value(seg: &WordSegment, ctx: &mut ExpandContext) -> Result<String, ExpandError> {
+    Ok(match seg {
+        WordSegment::Literal(s) => s.clone(),
+        WordSegment::EnvVar(env_ref) => expand_param(env_ref, ctx)?,
+        WordSegment::Positional(n) => ctx.positional_arg(*n as usize).unwrap_or_default(),
+        WordSegment::Special(param) => ctx.special(*param).unwrap_or_default(),
+        WordSegment::Tilde(prefix) => expand_tilde(prefix, &ctx.env),
+        WordSegment::Arith(expr) => arith::evaluate(expr, ctx)?.to_string(),
+        WordSegment::CommandSubst(id) => ctx.substitutions.get(id).cloned().unwrap_or_default(),
+    })
+}
+
+/// フィールド分割の途中経過
+struct Fields<'a> {
+    ifs: &'a str,
+    fields: Vec<Field>,
+    current: String,
+    /// パス名展開のパターン（クォートされた部分はエスケープする）
+    pattern: String,
+    /// クォートされていないワイルドカードを含むか
+    has_wildcards: bool,
+    /// current が空でもフィールドとして残すか
+    started: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn new(ifs: &'a str) -> Self {
+        Self {
+            ifs,
+            fields: Vec::new(),
+            current: String::new(),
+            pattern: String::new(),
+            has_wildcards: false,
+            started: false,
+        }
+    }
+
+    /// 分割せずに追加（クォートされた空文字列もフィールドになる）
+    fn push_text(&mut self, s: &str, quoted: bool) {
+        self.current.push_str(s);
+        if quoted {
+            self.pattern.push_str(&pattern::escape(s));
+            self.started = true;
+        } else {
+            self.pattern.push_str(s);
+            self.has_wildcards |= pattern::has_wildcards(s);
+            self.started |= !s.is_empty();
+        }
+    }
+
+    /// IFS で分割しながら追加
+    /// IFS の空白の並びは 1 つの区切りになり、それ以外の IFS 文字は空のフィールドも区切る
+    fn push_split(&mut self, s: &str) {
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            if !self.ifs.contains(c) {
+                self.current.push(c);
+                self.pattern.push(c);
+                self.has_wildcards |= matches!(c, '*' | '?' | '[');
+                self.started = true;
+                continue;
+            }
+
+            // 空白* (空白以外の IFS 文字 空白*)? を 1 つの区切りとして読む
+            let mut hard = !c.is_whitespace();
+            while let Some(&next) = chars.peek() {
+                if !self.ifs.contains(next) || (hard && !next.is_whitespace()) {
+                    break;
+                }
+                hard |= !next.is_whitespace();
+                chars.next();
+            }
+            if hard {
+                self.finish();
+            } else {
+                self.delimit();
+            }
+        }
+    }
+
+    /// 現在のフィールドを確定する
+    fn finish(&mut self) {
+        let pattern = std::mem::take(&mut self.pattern);
+        self.fields.push(Field {
+            value: std::mem::take(&mut self.current),
+            pattern: self.has_wildcards.then_some(pattern),
+        });
+        self.has_wildcards = false;
+        self.started = false;
+    }
+
+    /// 空のフィールドを作らずに区切る
+    fn delimit(&mut self) {
+        if self.started {
+            self.finish();
+        }
+    }
+
+    fn finish_all(mut self) -> Vec<Field> {
+        self.delimit();
+        self.fields
+    }
+}
+
+/// 代入できる変数名かどうか
+fn is_valid_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// 文字境界のバイト位置（0 と末尾を含む）
+fn boundaries(s: &str) -> Vec<usize> {
+    s.char_indices().map(|(i, _)| i).chain([s.len()]).collect()
+}
+
+/// 先頭からパターンに一致する部分を取り除く
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends = boundaries(value);
+    if longest {
+        ends.reverse();
+    }
+    ends.into_iter()
+        .find(|&end| pattern::matches_single(pattern, &value[..end]))
+        .map_or_else(|| value.to_string(), |end| value[end..].to_string())
+}
+
+/// 末尾からパターンに一致する部分を取り除く
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts = boundaries(value);
+    if !longest {
+        starts.reverse();
+    }
+    starts
+        .into_iter()
+        .find(|&start| pattern::matches_single(pattern, &value[start..]))
+        .map_or_else(|| value.to_string(), |start| value[..start].to_string())
+}
+
+/// パターンに一致する部分を置換（空文字列への一致は /# と /% でのみ有効）
+fn replace(value: &str, pattern: &str, replacement: &str, mode: ReplaceMode) -> String {
+    let bounds = boundaries(value);
+    match mode {
+        ReplaceMode::Prefix => bounds
+            .iter()
+            .rev()
+            .find(|&&end| pattern::matches_single(pattern, &value[..end]))
+            .map_or_else(
+                || value.to_string(),
+                |&end| format!("{}{}", replacement, &value[end..]),
+            ),
+        ReplaceMode::Suffix => bounds
+            .iter()
+            .find(|&&start| pattern::matches_single(pattern, &value[start..]))
+            .map_or_else(
+                || value.to_string(),
+                |&start| format!("{}{}", &value[..start], replacement),
+            ),
+        ReplaceMode::First | ReplaceMode::All => {
+            let mut result = String::new();
+            let mut i = 0;
+            while i < bounds.len() - 1 {
+                let start = bounds[i];
+                // 最長一致する終端を探す
+                let end = bounds[i + 1..]
+                    .iter()
+                    .rposition(|&end| pattern::matches_single(pattern, &value[start..end]));
+                match end {
+                    Some(j) => {
+                        result.push_str(replacement);
+                        i += j + 1;
+                        if mode == ReplaceMode::First {
+                            break;
+                        }
+                    }
+                    None => {
+                        result.push_str(&value[start..bounds[i + 1]]);
+                        i += 1;
+                    }
+                }
+            }
+            result.push_str(&value[bounds[i]..]);
+            result
+        }
+    }
+}
+
+/// 部分文字列を取り出す（負の offset / length は末尾から数える）
+fn substring(value: &str, offset: i64, length: Option<i64>) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    let len = chars.len() as i64;
+    let start = if offset < 0 { len + offset } else { offset };
+    if start < 0 || start > len {
+        return String::new();
+    }
+    let end = match length {
+        Some(length) if length < 0 => len + length,
+        Some(length) => (start + length).min(len),
+        None => len,
+    };
+    if end <= start {
+        return String::new();
+    }
+    chars[start as usize..end as usize].iter().collect()
+}
+
+/// 大文字・小文字を変換（パターンが空なら任意の文字に一致）
+fn case_mod(value: &str, upper: bool, all: bool, pattern: &str) -> String {
+    value
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let target = (all || i == 0)
+                && (pattern.is_empty() || pattern::matches_single(pattern, &c.to_string()));
+            match (target, upper) {
+                (false, _) => c.to_string(),
+                (true, true) => c.to_uppercase().to_string(),
+                (true, false) => c.to_lowercase().to_string(),
             }
         })
         .collect()
 }
 
+/// チルダ接頭辞を展開（解決できなければ元の文字列に戻す）
+/// ~user は USER が一致する場合だけ HOME に展開する（ユーザーデータベースはない）
+fn expand_tilde(prefix: &str, env: &HashMap<String, String>) -> String {
+    let resolved = match prefix {
+        "" => env.get("HOME").cloned(),
+        "+" => env.get("PWD").cloned(),
+        "-" => env.get("OLDPWD").cloned(),
+        user if env.get("USER").map(String::as_str) == Some(user) => env.get("HOME").cloned(),
+        _ => None,
+    };
+    resolved.unwrap_or_else(|| format!("~{}", prefix))
+}
+
 /// 環境変数リストを HashMap に変換
 pub fn env_list_to_map(env: &[(String, String)]) -> HashMap<String, String> {
     env.iter().cloned().collect()
@@ -31,54 +526,426 @@ pub fn env_list_to_map(env: &[(String, String)]) -> HashMap<String, String> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::ast::EnvRef;
 
     #[test]
     fn test_literal() {
         let word = ParsedWord::literal("hello");
-        let env = HashMap::new();
-        assert_eq!(expand_word(&word, &env), "hello");
+        let ctx = ExpandContext::default();
+        assert_eq!(expand_word(&word, &ctx).unwrap(), "hello");
     }
 
     #[test]
     fn test_env_var() {
-        let word = ParsedWord {
-            segments: vec![WordSegment::EnvVar(EnvRef {
-                name: "HOME".to_string(),
-                default: None,
-            })],
+        let word = ParsedWord::unquoted(vec![WordSegment::EnvVar(EnvRef::new("HOME"))]);
+        let mut ctx = ExpandContext::default();
+        ctx.env.insert("HOME".to_string(), "/home/user".to_string());
+        assert_eq!(expand_word(&word, &ctx).unwrap(), "/home/user");
+    }
+
+    #[test]
+    fn test_env_var_default() {
+        let word = ParsedWord::unquoted(vec![WordSegment::EnvVar(EnvRef {
+            name: "MISSING".to_string(),
+            op: Some(ParamOp::Default {
+                colon: true,
+                word: "default".to_string(),
+            }),
+            substitutions: Vec::new(),
+        })]);
+        let ctx = ExpandContext::default();
+        assert_eq!(expand_word(&word, &ctx).unwrap(), "default");
+    }
+
+    #[test]
+    fn test_mixed() {
+        let word = ParsedWord::unquoted(vec![
+            WordSegment::Literal("Hello, ".to_string()),
+            WordSegment::EnvVar(EnvRef::new("USER")),
+            WordSegment::Literal("!".to_string()),
+        ]);
+        let mut ctx = ExpandContext::default();
+        ctx.env.insert("USER".to_string(), "world".to_string());
+        assert_eq!(expand_word(&word, &ctx).unwrap(), "Hello, world!");
+    }
+
+    #[test]
+    fn test_tilde() {
+        let tilde = |prefix: &str, rest: &str| {
+            ParsedWord::unquoted(vec![
+                WordSegment::Tilde(prefix.to_string()),
+                WordSegment::Literal(rest.to_string()),
+            ])
         };
+        let mut ctx = ExpandContext::default();
+        ctx.env.insert("HOME".to_string(), "/home/me".to_string());
+        ctx.env.insert("USER".to_string(), "me".to_string());
+        ctx.env.insert("PWD".to_string(), "/tmp".to_string());
+
+        assert_eq!(
+            expand_word(&tilde("", "/notes"), &ctx).unwrap(),
+            "/home/me/notes"
+        );
+        assert_eq!(expand_word(&tilde("+", ""), &ctx).unwrap(), "/tmp");
+        assert_eq!(expand_word(&tilde("me", "/x"), &ctx).unwrap(), "/home/me/x");
+        // 解決できない ~user はそのまま残す
+        assert_eq!(expand_word(&tilde("alice", ""), &ctx).unwrap(), "~alice");
+        // OLDPWD が未設定なら展開しない
+        assert_eq!(expand_word(&tilde("-", "/a"), &ctx).unwrap(), "~-/a");
+    }
+
+    #[test]
+    fn test_arith() {
+        let word = ParsedWord::unquoted(vec![
+            WordSegment::Literal("n=".to_string()),
+            WordSegment::Arith("N * 2 + 1".to_string()),
+        ]);
+        let mut ctx = ExpandContext::default();
+        ctx.env.insert("N".to_string(), "20".to_string());
+        assert_eq!(expand_word(&word, &ctx).unwrap(), "n=41");
+    }
+
+    #[test]
+    fn test_arith_parameters() {
+        let ctx = ExpandContext {
+            positional: vec!["5".to_string()],
+            last_status: 3,
+            ..Default::default()
+        };
+        let expand = |input: &str| {
+            let word = Parser::parse_word_text(input).unwrap();
+            expand_word(&word, &ctx).unwrap()
+        };
+        assert_eq!(expand("$(($1 + 1))"), "6");
+        assert_eq!(expand("$((${#1}))"), "1");
+        assert_eq!(expand("$(($# > 0)):$(($? * 2))"), "1:6");
+        assert_eq!(expand("${1:$# - 1}"), "5");
+    }
+
+    #[test]
+    fn test_expand_pattern() {
+        let mut ctx = ExpandContext::default();
+        ctx.env.insert("P".to_string(), "a*".to_string());
+        let mut expand = |input: &str| {
+            let result = Parser::parse_input(&format!("echo {}", input)).unwrap();
+            let crate::ast::Command::Simple(cmd) = &result.elements[0].pipeline.commands[0] else {
+                panic!("Expected simple command");
+            };
+            expand_pattern(&cmd.args[1], &mut ctx).unwrap()
+        };
+
+        // クォートされた * はリテラル
+        assert_eq!(expand("\"*\""), "\\*");
+        assert_eq!(expand("'*'.txt"), "\\*.txt");
+        assert_eq!(expand("*.txt"), "*.txt");
+        // クォートされていない展開結果はパターンとして扱う
+        assert_eq!(expand("$P"), "a*");
+        assert_eq!(expand("\"$P\""), "a\\*");
+        assert!(pattern::matches(&expand("\"*\""), "*"));
+        assert!(!pattern::matches(&expand("\"*\""), "abc"));
+    }
+
+    #[test]
+    fn test_operand_substitutions() {
+        let input = "echo $(echo A) ${X:-$(echo B)} ${Y/$(echo C)/${Z:-$(echo D)}}";
+        let result = Parser::parse_input(input).unwrap();
+        let crate::ast::Command::Simple(cmd) = &result.elements[0].pipeline.commands[0] else {
+            panic!("Expected simple command");
+        };
+        // オペランド中のコマンド置換も親の置換表に登録される
+        let inputs: Vec<_> = result
+            .substitutions
+            .iter()
+            .map(|s| s.input.as_str())
+            .collect();
+        assert_eq!(inputs, vec!["echo A", "echo B", "echo C", "echo D"]);
+        let ids = |word: &ParsedWord| match &word.parts[0].segment {
+            WordSegment::EnvVar(env_ref) => env_ref.substitutions.clone(),
+            other => panic!("Expected env var, got {:?}", other),
+        };
+        assert_eq!(ids(&cmd.args[2]), vec![1]);
+        assert_eq!(ids(&cmd.args[3]), vec![2, 3]);
+
+        let mut ctx = ExpandContext::default();
+        ctx.env.insert("Y".to_string(), "xCx".to_string());
+        for (id, output) in ["A", "B", "C", "D"].into_iter().enumerate() {
+            ctx.substitutions.insert(id as u32, output.to_string());
+        }
+        assert_eq!(expand_segments(&cmd.args[2], &mut ctx).unwrap(), "B");
+        assert_eq!(expand_segments(&cmd.args[3], &mut ctx).unwrap(), "xDx");
+    }
+
+    /// ${NAME<op>} を展開するテスト用ヘルパー
+    fn param(content: &str, ctx: &mut ExpandContext) -> Result<String, ExpandError> {
+        let word = Parser::parse_word_text(&format!("${{{}}}", content)).unwrap();
+        expand_segments(&word, ctx)
+    }
+
+    fn test_env() -> ExpandContext {
         let mut env = HashMap::new();
-        env.insert("HOME".to_string(), "/home/user".to_string());
-        assert_eq!(expand_word(&word, &env), "/home/user");
+        env.insert("FILE".to_string(), "/home/me/notes.tar.gz".to_string());
+        env.insert("EMPTY".to_string(), String::new());
+        env.insert("NAME".to_string(), "hello world".to_string());
+        ExpandContext {
+            env,
+            ..Default::default()
+        }
     }
 
     #[test]
-    fn test_env_var_default() {
-        let word = ParsedWord {
-            segments: vec![WordSegment::EnvVar(EnvRef {
+    fn test_default_and_alternative() {
+        let mut env = test_env();
+        assert_eq!(param("EMPTY:-x", &mut env).unwrap(), "x");
+        assert_eq!(param("EMPTY-x", &mut env).unwrap(), "");
+        assert_eq!(param("MISSING-$NAME", &mut env).unwrap(), "hello world");
+        assert_eq!(param("NAME:+set", &mut env).unwrap(), "set");
+        assert_eq!(param("EMPTY:+set", &mut env).unwrap(), "");
+        assert_eq!(param("EMPTY+set", &mut env).unwrap(), "set");
+        assert_eq!(param("#NAME", &mut env).unwrap(), "11");
+        assert_eq!(param("#MISSING", &mut env).unwrap(), "0");
+    }
+
+    #[test]
+    fn test_assign() {
+        let mut env = test_env();
+        assert_eq!(param("COUNT:=3", &mut env).unwrap(), "3");
+        assert_eq!(env.env.get("COUNT"), Some(&"3".to_string()));
+        assert_eq!(param("COUNT:=5", &mut env).unwrap(), "3");
+
+        // expand_word では呼び出し元に反映しない
+        let word = Parser::parse_word_text("${NEW:=1}").unwrap();
+        let env = test_env();
+        assert_eq!(expand_word(&word, &env).unwrap(), "1");
+        assert!(!env.env.contains_key("NEW"));
+    }
+
+    #[test]
+    fn test_error() {
+        let mut env = test_env();
+        assert_eq!(param("NAME:?oops", &mut env).unwrap(), "hello world");
+        assert_eq!(
+            param("EMPTY:?", &mut env).unwrap_err().to_string(),
+            "EMPTY: parameter null or not set"
+        );
+        assert_eq!(
+            param("MISSING?", &mut env).unwrap_err().to_string(),
+            "MISSING: parameter not set"
+        );
+        assert_eq!(
+            param("MISSING:?need $NAME", &mut env),
+            Err(ExpandError::Unset {
                 name: "MISSING".to_string(),
-                default: Some("default".to_string()),
-            })],
+                message: "need hello world".to_string()
+            })
+        );
+        assert!(param("EMPTY?", &mut env).is_ok());
+    }
+
+    #[test]
+    fn test_remove_prefix_and_suffix() {
+        let mut env = test_env();
+        assert_eq!(param("FILE#*/", &mut env).unwrap(), "home/me/notes.tar.gz");
+        assert_eq!(param("FILE##*/", &mut env).unwrap(), "notes.tar.gz");
+        assert_eq!(param("FILE%.*", &mut env).unwrap(), "/home/me/notes.tar");
+        assert_eq!(param("FILE%%.*", &mut env).unwrap(), "/home/me/notes");
+        assert_eq!(
+            param("FILE%.zip", &mut env).unwrap(),
+            "/home/me/notes.tar.gz"
+        );
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut env = test_env();
+        assert_eq!(param("NAME/o/0", &mut env).unwrap(), "hell0 world");
+        assert_eq!(param("NAME//o/0", &mut env).unwrap(), "hell0 w0rld");
+        assert_eq!(param("NAME//l*o/_", &mut env).unwrap(), "he_rld");
+        assert_eq!(param("NAME/#hello/bye", &mut env).unwrap(), "bye world");
+        assert_eq!(param("NAME/#world/bye", &mut env).unwrap(), "hello world");
+        assert_eq!(param("NAME/%world/there", &mut env).unwrap(), "hello there");
+        assert_eq!(param("NAME// ", &mut env).unwrap(), "helloworld");
+        assert_eq!(param("NAME/#/> ", &mut env).unwrap(), "> hello world");
+    }
+
+    #[test]
+    fn test_quoted_operands() {
+        let mut env = test_env();
+        env.env.insert("STAR".to_string(), "*.txt".to_string());
+        env.env.insert("PAT".to_string(), "*.".to_string());
+
+        // クォートされたパターンはリテラルとしてマッチする
+        assert_eq!(param("STAR#\"*\"", &mut env).unwrap(), ".txt");
+        assert_eq!(
+            param("FILE#\"*\"", &mut env).unwrap(),
+            "/home/me/notes.tar.gz"
+        );
+        assert_eq!(param("FILE#'/home'", &mut env).unwrap(), "/me/notes.tar.gz");
+        assert_eq!(
+            param("FILE%'.*'", &mut env).unwrap(),
+            "/home/me/notes.tar.gz"
+        );
+        assert_eq!(param("STAR#\\*", &mut env).unwrap(), ".txt");
+        assert_eq!(param("STAR/\"*\"/all", &mut env).unwrap(), "all.txt");
+        // クォートされていない展開結果はパターンとして扱う
+        assert_eq!(param("FILE##$PAT", &mut env).unwrap(), "gz");
+        assert_eq!(param("STAR#\"$PAT\"", &mut env).unwrap(), "txt");
+        // word のクォートは取り除く
+        assert_eq!(param("MISSING:-\"a b\"'c'", &mut env).unwrap(), "a bc");
+        assert_eq!(param("MISSING:-a b", &mut env).unwrap(), "a b");
+        assert_eq!(param("MISSING:-'}'", &mut env).unwrap(), "}");
+    }
+
+    #[test]
+    fn test_substring() {
+        let mut env = test_env();
+        assert_eq!(param("NAME:6", &mut env).unwrap(), "world");
+        assert_eq!(param("NAME:0:5", &mut env).unwrap(), "hello");
+        assert_eq!(param("NAME: -5", &mut env).unwrap(), "world");
+        assert_eq!(param("NAME:1+1:2", &mut env).unwrap(), "ll");
+        assert_eq!(param("NAME:6:-2", &mut env).unwrap(), "wor");
+        assert_eq!(param("NAME:20", &mut env).unwrap(), "");
+        assert!(param("NAME:1/0", &mut env).is_err());
+    }
+
+    #[test]
+    fn test_case_mod() {
+        let mut env = test_env();
+        assert_eq!(param("NAME^", &mut env).unwrap(), "Hello world");
+        assert_eq!(param("NAME^^", &mut env).unwrap(), "HELLO WORLD");
+        assert_eq!(param("NAME^^[lo]", &mut env).unwrap(), "heLLO wOrLd");
+        env.env.insert("UP".to_string(), "ABC".to_string());
+        assert_eq!(param("UP,", &mut env).unwrap(), "aBC");
+        assert_eq!(param("UP,,", &mut env).unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_special_params() {
+        let mut ctx = ExpandContext {
+            arg0: "script.sh".to_string(),
+            positional: vec!["a".to_string(), "b c".to_string()],
+            last_status: 2,
+            pid: 42,
+            ..Default::default()
         };
-        let env = HashMap::new();
-        assert_eq!(expand_word(&word, &env), "default");
+        let expand = |input: &str, ctx: &ExpandContext| {
+            let word = Parser::parse_word_text(input).unwrap();
+            expand_word(&word, ctx).unwrap()
+        };
+
+        assert_eq!(expand("$0 $1 $2 $3", &ctx), "script.sh a b c ");
+        assert_eq!(expand("$?:$$:$#", &ctx), "2:42:2");
+        assert_eq!(expand("[$@] [$*]", &ctx), "[a b c] [a b c]");
+        assert_eq!(expand("${!:-none}", &ctx), "none");
+        assert_eq!(expand("${#@} ${#2} ${3-unset}", &ctx), "2 3 unset");
+
+        ctx.positional = (1..=10).map(|n| n.to_string()).collect();
+        ctx.last_background_pid = Some(7);
+        assert_eq!(expand("$10 ${10} $!", &ctx), "10 10 7");
+
+        assert_eq!(
+            param("11:=x", &mut ctx),
+            Err(ExpandError::CannotAssign("11".to_string()))
+        );
+    }
+
+    fn values(fields: Vec<Field>) -> Vec<String> {
+        fields.into_iter().map(|field| field.value).collect()
     }
 
     #[test]
-    fn test_mixed() {
-        let word = ParsedWord {
-            segments: vec![
-                WordSegment::Literal("Hello, ".to_string()),
-                WordSegment::EnvVar(EnvRef {
-                    name: "USER".to_string(),
-                    default: None,
-                }),
-                WordSegment::Literal("!".to_string()),
-            ],
+    fn test_field_splitting() {
+        let mut ctx = test_env();
+        ctx.positional = vec!["a b".to_string(), "".to_string(), "c".to_string()];
+        ctx.env
+            .insert("SPACED".to_string(), "  one  two\tthree ".to_string());
+        let fields = |input: &str, ctx: &mut ExpandContext| {
+            let word = Parser::parse_word_text(input).unwrap();
+            values(expand_fields(&word, ctx).unwrap())
         };
-        let mut env = HashMap::new();
-        env.insert("USER".to_string(), "world".to_string());
-        assert_eq!(expand_word(&word, &env), "Hello, world!");
+        let quoted = |input: &str, ctx: &mut ExpandContext| {
+            let word = Parser::parse_word_text(input).unwrap().into_quoted();
+            values(expand_fields(&word, ctx).unwrap())
+        };
+
+        assert_eq!(fields("$SPACED", &mut ctx), vec!["one", "two", "three"]);
+        assert_eq!(
+            fields("x$SPACED", &mut ctx),
+            vec!["x", "one", "two", "three"]
+        );
+        assert_eq!(quoted("$SPACED", &mut ctx), vec!["  one  two\tthree "]);
+        assert_eq!(fields("$NAME!", &mut ctx), vec!["hello", "world!"]);
+
+        // 空の展開はフィールドを作らない
+        assert!(fields("$EMPTY", &mut ctx).is_empty());
+        assert!(fields("$MISSING$EMPTY", &mut ctx).is_empty());
+        assert_eq!(quoted("$EMPTY", &mut ctx), vec![""]);
+
+        assert_eq!(fields("$@", &mut ctx), vec!["a", "b", "c"]);
+        assert_eq!(quoted("$@", &mut ctx), vec!["a b", "", "c"]);
+        assert_eq!(quoted("<$@>", &mut ctx), vec!["<a b", "", "c>"]);
+        assert_eq!(quoted("$*", &mut ctx), vec!["a b  c"]);
+
+        ctx.positional.clear();
+        assert!(quoted("$@", &mut ctx).is_empty());
+        assert_eq!(quoted("$*", &mut ctx), vec![""]);
+    }
+
+    #[test]
+    fn test_custom_ifs() {
+        let mut ctx = test_env();
+        ctx.env.insert("IFS".to_string(), ": ".to_string());
+        ctx.env
+            .insert("PATHS".to_string(), "/bin::/usr/bin : /sbin:".to_string());
+        ctx.positional = vec!["x".to_string(), "y".to_string()];
+        let word = Parser::parse_word_text("$PATHS").unwrap();
+        assert_eq!(
+            values(expand_fields(&word, &mut ctx).unwrap()),
+            vec!["/bin", "", "/usr/bin", "/sbin"]
+        );
+        let word = Parser::parse_word_text("$*").unwrap().into_quoted();
+        assert_eq!(values(expand_fields(&word, &mut ctx).unwrap()), vec!["x:y"]);
+
+        // IFS が空なら分割しない
+        ctx.env.insert("IFS".to_string(), String::new());
+        let word = Parser::parse_word_text("$NAME").unwrap();
+        assert_eq!(
+            values(expand_fields(&word, &mut ctx).unwrap()),
+            vec!["hello world"]
+        );
+        let word = Parser::parse_word_text("$EMPTY").unwrap();
+        assert!(values(expand_fields(&word, &mut ctx).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn test_mixed_quoting() {
+        let mut ctx = test_env();
+        ctx.env.insert("GLOB".to_string(), "*.md".to_string());
+        let fields = |input: &str, ctx: &mut ExpandContext| {
+            let result = Parser::parse_input(&format!("echo {}", input)).unwrap();
+            let crate::ast::Command::Simple(cmd) = &result.elements[0].pipeline.commands[0] else {
+                panic!("Expected simple command");
+            };
+            expand_fields(&cmd.args[1], ctx).unwrap()
+        };
+        let field = |value: &str, pattern: Option<&str>| Field {
+            value: value.to_string(),
+            pattern: pattern.map(str::to_string),
+        };
+
+        // クォートされた部分は分割しない
+        assert_eq!(
+            fields("x\"$NAME\"$NAME", &mut ctx),
+            vec![field("xhello worldhello", None), field("world", None)]
+        );
+        // クォートされたワイルドカードはエスケープされる
+        assert_eq!(
+            fields("\"$FILE*\"*", &mut ctx),
+            vec![field(
+                "/home/me/notes.tar.gz**",
+                Some("/home/me/notes.tar.gz\\**")
+            )]
+        );
+        assert_eq!(fields("\"*.md\"", &mut ctx), vec![field("*.md", None)]);
+        assert_eq!(fields("$GLOB", &mut ctx), vec![field("*.md", Some("*.md"))]);
+        assert_eq!(fields("\"\"$EMPTY", &mut ctx), vec![field("", None)]);
     }
 }
@@ -0,0 +1,791 @@
+//! AST からシェルのソースへの整形
+//!
+//! パース結果を正規化したソースに戻す。複合コマンドの本体は 4 スペースで字下げし、
+//! `;` と改行の区別は接続子のとおりに残す。パースした結果を整形して再びパースすると
+//! 元と同じ AST になる。
+
+use crate::ast::*;
+use crate::lexer::reserved_word;
+
+/// 字下げの幅
+const INDENT: &str = "    ";
+
+/// パース結果を整形
+pub fn format(result: &ParseResult) -> String {
+    let mut formatter = Formatter::new(result);
+    formatter.list(&result.elements);
+    // 末尾のコマンドのヒアドキュメント
+    if !formatter.here_docs.is_empty() {
+        formatter.newline();
+    }
+    formatter.out
+}
+
+struct Formatter<'a> {
+    result: &'a ParseResult,
+    out: String,
+    indent: usize,
+    /// 次の改行の後に書くヒアドキュメント（区切り文字, 本文）
+    here_docs: Vec<(String, String)>,
+}
+
+impl<'a> Formatter<'a> {
+    fn new(result: &'a ParseResult) -> Self {
+        Self {
+            result,
+            out: String::new(),
+            indent: 0,
+            here_docs: Vec::new(),
+        }
+    }
+
+    /// 改行して字下げする（保留中のヒアドキュメントは改行の直後に書く）
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for (delimiter, body) in std::mem::take(&mut self.here_docs) {
+            self.out.push_str(&body);
+            if !body.is_empty() && !body.ends_with('\n') {
+                self.out.push('\n');
+            }
+            self.out.push_str(&delimiter);
+            self.out.push('\n');
+        }
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    /// コマンドリスト
+    fn list(&mut self, elements: &[ConditionalElement]) {
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                match element.connector {
+                    Connector::And => self.out.push_str(" && "),
+                    Connector::Or => self.out.push_str(" || "),
+                    // & の後の ; は書かない
+                    Connector::Sequential if elements[i - 1].background => self.out.push(' '),
+                    Connector::Sequential => self.out.push_str("; "),
+                    Connector::None => self.newline(),
+                }
+            }
+            self.pipeline(&element.pipeline);
+
+            // & は AND-OR リストの末尾に 1 つだけ書く
+            let ends_and_or = elements.get(i + 1).is_none_or(|next| {
+                matches!(next.connector, Connector::None | Connector::Sequential)
+            });
+            if element.background && ends_and_or {
+                self.out.push_str(" &");
+            }
+        }
+    }
+
+    /// then や do の前の区切り（& で終わるリストには ; を付けない）
+    fn terminate(&mut self, elements: &[ConditionalElement]) {
+        if elements.last().is_some_and(|e| e.background) {
+            self.out.push(' ');
+        } else {
+            self.out.push_str("; ");
+        }
+    }
+
+    /// 字下げした本体を書き、閉じる予約語の行に戻る
+    fn body(&mut self, elements: &[ConditionalElement]) {
+        self.indent += 1;
+        self.newline();
+        self.list(elements);
+        self.indent -= 1;
+        self.newline();
+    }
+
+    fn pipeline(&mut self, pipeline: &Pipeline) {
+        for (i, command) in pipeline.commands.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(" | ");
+            }
+            self.command(command);
+        }
+    }
+
+    fn command(&mut self, command: &Command) {
+        match command {
+            Command::Simple(cmd) => self.simple_command(cmd),
+            Command::Compound(id) => self.compound(*id),
+            Command::LoopControl(control) => {
+                self.out.push_str(match control.kind {
+                    LoopControlKind::Break => "break",
+                    LoopControlKind::Continue => "continue",
+                });
+                if control.depth != 1 {
+                    self.out.push_str(&format!(" {}", control.depth));
+                }
+            }
+            Command::FunctionDef(id) => {
+                let Some(def) = self.result.functions.get(*id as usize) else {
+                    return;
+                };
+                self.out.push_str(&def.name);
+                self.out.push_str("() ");
+                self.compound(def.body);
+            }
+        }
+    }
+
+    fn simple_command(&mut self, cmd: &SimpleCommand) {
+        let words: Vec<String> = cmd.args.iter().map(|word| self.word(word)).collect();
+        // 先頭のワードが予約語と読めるなら、リダイレクトを前に書く（<x fi など）
+        if cmd.args.first().is_some_and(is_reserved) {
+            for redirect in &cmd.redirects {
+                self.redirect(redirect);
+                self.out.push(' ');
+            }
+            self.out.push_str(&words.join(" "));
+            return;
+        }
+        self.out.push_str(&words.join(" "));
+        for (i, redirect) in cmd.redirects.iter().enumerate() {
+            if i > 0 || !words.is_empty() {
+                self.out.push(' ');
+            }
+            self.redirect(redirect);
+        }
+    }
+
+    fn compound(&mut self, id: u32) {
+        let Some(compound) = self.result.compounds.get(id as usize) else {
+            return;
+        };
+
+        match &compound.kind {
+            CompoundKind::If(clause) => {
+                for (i, branch) in clause.branches.iter().enumerate() {
+                    self.out.push_str(if i == 0 { "if " } else { "elif " });
+                    self.list(&branch.condition);
+                    self.terminate(&branch.condition);
+                    self.out.push_str("then");
+                    self.body(&branch.body);
+                }
+                if let Some(body) = &clause.else_body {
+                    self.out.push_str("else");
+                    self.body(body);
+                }
+                self.out.push_str("fi");
+            }
+            CompoundKind::Loop(clause) => {
+                self.out.push_str(match clause.kind {
+                    LoopKind::While => "while ",
+                    LoopKind::Until => "until ",
+                });
+                self.list(&clause.condition);
+                self.terminate(&clause.condition);
+                self.do_group(&clause.body);
+            }
+            CompoundKind::For(clause) => {
+                self.out.push_str("for ");
+                self.out.push_str(&clause.variable);
+                if let Some(words) = &clause.words {
+                    self.out.push_str(" in");
+                    for word in words {
+                        self.out.push(' ');
+                        self.out.push_str(&self.word(word));
+                    }
+                }
+                self.out.push_str("; ");
+                self.do_group(&clause.body);
+            }
+            CompoundKind::Case(clause) => {
+                self.out.push_str("case ");
+                self.out.push_str(&self.word(&clause.word));
+                self.out.push_str(" in");
+                self.indent += 1;
+                for arm in &clause.arms {
+                    self.newline();
+                    let patterns: Vec<String> =
+                        arm.patterns.iter().map(|word| self.word(word)).collect();
+                    self.out.push_str(&patterns.join(" | "));
+                    self.out.push(')');
+                    self.indent += 1;
+                    if !arm.body.is_empty() {
+                        self.newline();
+                        self.list(&arm.body);
+                    }
+                    self.newline();
+                    self.out.push_str(";;");
+                    self.indent -= 1;
+                }
+                self.indent -= 1;
+                self.newline();
+                self.out.push_str("esac");
+            }
+            CompoundKind::BraceGroup(body) => {
+                self.out.push('{');
+                self.body(body);
+                self.out.push('}');
+            }
+            CompoundKind::Subshell(body) => {
+                self.out.push('(');
+                self.body(body);
+                self.out.push(')');
+            }
+        }
+
+        for redirect in &compound.redirects {
+            self.out.push(' ');
+            self.redirect(redirect);
+        }
+    }
+
+    fn do_group(&mut self, body: &[ConditionalElement]) {
+        self.out.push_str("do");
+        self.body(body);
+        self.out.push_str("done");
+    }
+
+    fn redirect(&mut self, redirect: &Redirect) {
+        if redirect.fd != redirect.kind.default_fd() {
+            self.out.push_str(&redirect.fd.to_string());
+        }
+
+        let word = match &redirect.target {
+            RedirectTarget::Fd(fd) => {
+                self.out.push_str(match redirect.kind {
+                    RedirectKind::Stdin => "<&",
+                    _ => ">&",
+                });
+                self.out.push_str(&fd.to_string());
+                return;
+            }
+            RedirectTarget::Word(word) => word,
+        };
+
+        if redirect.kind == RedirectKind::HereDoc {
+            self.here_doc(word);
+            return;
+        }
+
+        self.out.push_str(match redirect.kind {
+            RedirectKind::Stdin => "< ",
+            RedirectKind::Stdout => "> ",
+            RedirectKind::StdoutAppend => ">> ",
+            RedirectKind::ReadWrite => "<> ",
+            RedirectKind::HereString => "<<< ",
+            RedirectKind::HereDoc => unreachable!(),
+        });
+        self.out.push_str(&self.word(word));
+    }
+
+    /// ヒアドキュメント（展開を含まない本文は区切り文字をクォートする）
+    /// 展開を含む本文では、リテラルの \, $, ` をエスケープする
+    fn here_doc(&mut self, word: &ParsedWord) {
+        let literal = word
+            .segments()
+            .all(|s| matches!(s, WordSegment::Literal(_)));
+        let body: String = word
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| match &part.segment {
+                WordSegment::Literal(s) if literal => s.clone(),
+                WordSegment::Literal(s) => s
+                    .chars()
+                    .flat_map(|c| match c {
+                        '\\' | '$' | '`' => vec!['\\', c],
+                        c => vec![c],
+                    })
+                    .collect(),
+                _ => self.expansion(&word.parts, i),
+            })
+            .collect();
+
+        // 本文の行と重ならない区切り文字を選ぶ
+        let mut delimiter = "EOF".to_string();
+        let mut n = 0;
+        while body.lines().any(|line| line == delimiter) {
+            n += 1;
+            delimiter = format!("EOF{}", n);
+        }
+
+        if literal {
+            self.out.push_str(&format!("<<'{}'", delimiter));
+        } else {
+            self.out.push_str(&format!("<<{}", delimiter));
+        }
+        self.here_docs.push((delimiter, body));
+    }
+
+    /// ワード（クォートされた断片はまとめてクォートする）
+    fn word(&self, word: &ParsedWord) -> String {
+        let parts = &word.parts;
+        let mut out = String::new();
+        let mut i = 0;
+
+        while i < parts.len() {
+            if parts[i].quoted {
+                let end = parts[i..]
+                    .iter()
+                    .position(|part| !part.quoted)
+                    .map_or(parts.len(), |n| i + n);
+                out.push_str(&self.quoted(parts, i, end));
+                i = end;
+                continue;
+            }
+
+            match &parts[i].segment {
+                WordSegment::Literal(s) if is_plain(s, i == 0) => out.push_str(s),
+                WordSegment::Literal(s) => out.push_str(&single_quote(s)),
+                _ => out.push_str(&self.expansion(parts, i)),
+            }
+            i += 1;
+        }
+
+        if out.is_empty() {
+            out.push_str("''");
+        }
+        out
+    }
+
+    /// クォートされた断片 parts[start..end] を 1 つのクォートにする
+    /// リテラルだけならシングルクォート、展開を含めばダブルクォート
+    fn quoted(&self, parts: &[WordPart], start: usize, end: usize) -> String {
+        let literal: Option<String> = parts[start..end]
+            .iter()
+            .map(|part| match &part.segment {
+                WordSegment::Literal(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect();
+        if let Some(s) = literal {
+            return single_quote(&s);
+        }
+
+        let mut out = String::from("\"");
+        for i in start..end {
+            match &parts[i].segment {
+                WordSegment::Literal(s) => {
+                    for c in s.chars() {
+                        if matches!(c, '"' | '\\' | '$' | '`') {
+                            out.push('\\');
+                        }
+                        out.push(c);
+                    }
+                }
+                _ => out.push_str(&self.expansion(&parts[..end], i)),
+            }
+        }
+        out.push('"');
+        out
+    }
+
+    /// 展開のセグメント parts[i] をソースに戻す
+    /// 直後に名前の続きと読める文字があれば ${...} で囲む
+    fn expansion(&self, parts: &[WordPart], i: usize) -> String {
+        let next = parts
+            .get(i + 1)
+            .filter(|next| next.quoted == parts[i].quoted)
+            .and_then(|next| match &next.segment {
+                WordSegment::Literal(s) => s.chars().next(),
+                _ => None,
+            });
+        let continues_name = next.is_some_and(|c| c.is_alphanumeric() || c == '_');
+
+        match &parts[i].segment {
+            WordSegment::Literal(s) => s.clone(),
+            WordSegment::EnvVar(EnvRef { name, op: None, .. }) if !continues_name => {
+                format!("${}", name)
+            }
+            WordSegment::EnvVar(env_ref) => format!("${{{}}}", parameter(env_ref)),
+            WordSegment::Positional(n) if *n < 10 && !next.is_some_and(|c| c.is_ascii_digit()) => {
+                format!("${}", n)
+            }
+            WordSegment::Positional(n) => format!("${{{}}}", n),
+            WordSegment::Special(param) => format!("${}", special_char(*param)),
+            WordSegment::CommandSubst(id) => {
+                let input = self
+                    .result
+                    .substitutions
+                    .iter()
+                    .find(|subst| subst.id == *id)
+                    .map_or("", |subst| subst.input.as_str());
+                format!("$({})", input)
+            }
+            WordSegment::Arith(expr) => format!("$(({}))", expr),
+            WordSegment::Tilde(prefix) => format!("~{}", prefix),
+        }
+    }
+}
+
+/// ${...} の中身
+fn parameter(env_ref: &EnvRef) -> String {
+    let name = &env_ref.name;
+    let Some(op) = &env_ref.op else {
+        return name.clone();
+    };
+    let colon = |colon: bool| if colon { ":" } else { "" };
+    let twice = |c: &str, all: bool| if all { c.repeat(2) } else { c.to_string() };
+
+    match op {
+        ParamOp::Length => format!("#{}", name),
+        ParamOp::Default { colon: c, word } => format!("{}{}-{}", name, colon(*c), word),
+        ParamOp::Assign { colon: c, word } => format!("{}{}={}", name, colon(*c), word),
+        ParamOp::Error { colon: c, word } => format!("{}{}?{}", name, colon(*c), word),
+        ParamOp::Alternative { colon: c, word } => format!("{}{}+{}", name, colon(*c), word),
+        ParamOp::RemovePrefix { pattern, longest } => {
+            format!("{}{}{}", name, twice("#", *longest), pattern)
+        }
+        ParamOp::RemoveSuffix { pattern, longest } => {
+            format!("{}{}{}", name, twice("%", *longest), pattern)
+        }
+        ParamOp::Replace {
+            pattern,
+            replacement,
+            mode,
+        } => {
+            let mode = match mode {
+                ReplaceMode::First => "",
+                ReplaceMode::All => "/",
+                ReplaceMode::Prefix => "#",
+                ReplaceMode::Suffix => "%",
+            };
+            // 置換文字列が空なら / ごと省く（${a//} は ${a/} と別の意味になる）
+            if replacement.is_empty() {
+                format!("{}/{}{}", name, mode, pattern)
+            } else {
+                format!("{}/{}{}/{}", name, mode, pattern, replacement)
+            }
+        }
+        ParamOp::Substring { offset, length } => match length {
+            Some(length) => format!("{}:{}:{}", name, offset, length),
+            None => format!("{}:{}", name, offset),
+        },
+        ParamOp::CaseMod {
+            upper,
+            all,
+            pattern,
+        } => {
+            let c = if *upper { "^" } else { "," };
+            format!("{}{}{}", name, twice(c, *all), pattern)
+        }
+    }
+}
+
+/// 特殊パラメータの名前
+fn special_char(param: SpecialParam) -> char {
+    match param {
+        SpecialParam::ExitStatus => '?',
+        SpecialParam::Pid => '$',
+        SpecialParam::LastBackgroundPid => '!',
+        SpecialParam::ArgCount => '#',
+        SpecialParam::AllArgs => '@',
+        SpecialParam::AllArgsJoined => '*',
+    }
+}
+
+/// クォートされていない予約語だけのワードか
+fn is_reserved(word: &ParsedWord) -> bool {
+    matches!(
+        &word.parts[..],
+        [WordPart { segment: WordSegment::Literal(s), quoted: false }] if reserved_word(s).is_some()
+    )
+}
+
+/// クォートせずに書けるリテラルか
+/// ワードの先頭の # はコメントになるので除く
+fn is_plain(s: &str, first: bool) -> bool {
+    if s.is_empty() || (first && s.starts_with('#')) {
+        return false;
+    }
+    !s.chars()
+        .any(|c| c.is_whitespace() || "|&;<>()'\"\\`".contains(c))
+}
+
+/// シングルクォートで囲む（' は '\'' にする）
+fn single_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    /// パースして整形
+    fn fmt(input: &str) -> String {
+        format(&Parser::parse_input(input).unwrap())
+    }
+
+    /// 整形したものを再びパースすると同じ AST になり、もう一度整形しても変わらない
+    fn assert_round_trip(input: &str) {
+        let ast = Parser::parse_input(input).unwrap();
+        let formatted = format(&ast);
+        let reparsed = Parser::parse_input(&formatted)
+            .unwrap_or_else(|e| panic!("{:?} -> {:?}: {}", input, formatted, e));
+        assert_eq!(reparsed, ast, "{:?} -> {:?}", input, formatted);
+        assert_eq!(format(&reparsed), formatted, "{:?}", input);
+    }
+
+    #[test]
+    fn test_simple_commands() {
+        assert_eq!(fmt("echo   hello    world"), "echo hello world");
+        assert_eq!(fmt("ls -la|grep foo"), "ls -la | grep foo");
+        assert_eq!(fmt("<in esac"), "< in esac");
+        assert_eq!(fmt("a&&b||c"), "a && b || c");
+        assert_eq!(fmt("a;b\nc"), "a; b\nc");
+        assert_eq!(fmt("sleep 1& echo x"), "sleep 1 & echo x");
+        assert_eq!(fmt("a && b &"), "a && b &");
+    }
+
+    #[test]
+    fn test_quoting() {
+        assert_eq!(fmt("echo \"hello world\""), "echo 'hello world'");
+        assert_eq!(fmt("echo a\\ b"), "echo a' 'b");
+        assert_eq!(fmt("echo \"it's\""), "echo 'it'\\''s'");
+        assert_eq!(fmt("echo \"$HOME/x\" $USER"), "echo \"$HOME/x\" $USER");
+        assert_eq!(fmt("echo \"${A}b\" \"a\\$b\""), "echo \"${A}b\" 'a$b'");
+        assert_eq!(fmt("echo \\# \\; '|'"), "echo '#' ';' '|'");
+        assert_eq!(fmt("echo \"\" ''"), "echo '' ''");
+        assert_eq!(fmt("echo \"\\\\ \\\" $X\""), "echo \"\\\\ \\\" $X\"");
+    }
+
+    #[test]
+    fn test_expansions() {
+        assert_eq!(
+            fmt("echo ${X:-ab} ${#X} ${X##*/} ${X//a/b} ${X:1:2} ${X^^}"),
+            "echo ${X:-ab} ${#X} ${X##*/} ${X//a/b} ${X:1:2} ${X^^}"
+        );
+        assert_eq!(
+            fmt("echo ${X}y $1 ${1}0 ${10} $? $@"),
+            "echo ${X}y $1 ${1}0 ${10} $? $@"
+        );
+        assert_eq!(
+            fmt("echo $(date  +%s) $((1+2)) ~/x"),
+            "echo $(date +%s) $((1+2)) ~/x"
+        );
+    }
+
+    #[test]
+    fn test_redirects() {
+        assert_eq!(
+            fmt("cmd >out 2>>err <in 2>&1 3<>f"),
+            "cmd > out 2>> err < in 2>&1 3<> f"
+        );
+        assert_eq!(fmt("cmd &>all"), "cmd > all 2>&1");
+        assert_eq!(fmt(">out"), "> out");
+        assert_eq!(fmt("cat <<<  \"$X\""), "cat <<< \"$X\"");
+    }
+
+    #[test]
+    fn test_here_docs() {
+        assert_eq!(
+            fmt("cat <<END | wc\nhi $USER\nEND\necho done"),
+            "cat <<EOF | wc\nhi $USER\nEOF\necho done"
+        );
+        assert_eq!(
+            fmt("cat <<'X'\n$HOME\nEOF\nX"),
+            "cat <<'EOF1'\n$HOME\nEOF\nEOF1\n"
+        );
+        assert_eq!(
+            fmt("if cat <<E; then :; fi\nbody\nE"),
+            "if cat <<'EOF'; then\nbody\nEOF\n    :\nfi"
+        );
+    }
+
+    #[test]
+    fn test_compound_commands() {
+        assert_eq!(
+            fmt("if a; then b; elif c; then d; else e; fi"),
+            "if a; then\n    b\nelif c; then\n    d\nelse\n    e\nfi"
+        );
+        assert_eq!(
+            fmt("for f in *.md; do while x; do break 2; done; done"),
+            "for f in *.md; do\n    while x; do\n        break 2\n    done\ndone"
+        );
+        assert_eq!(
+            fmt("case $x in a|b) echo ab;; *) ;; esac"),
+            "case $x in\n    a | b)\n        echo ab\n        ;;\n    *)\n        ;;\nesac"
+        );
+        assert_eq!(fmt("f() { echo hi; } > log"), "f() {\n    echo hi\n} > log");
+        assert_eq!(fmt("(cd /; ls)"), "(\n    cd /; ls\n)");
+    }
+
+    /// 再現できる乱数（xorshift）
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    const WORDS: &[&str] = &[
+        "a",
+        "b",
+        "echo",
+        "if",
+        "then",
+        "fi",
+        "do",
+        "done",
+        "in",
+        "esac",
+        "{",
+        "}",
+        "!",
+        "break",
+        "x=1",
+        "y=\"a b\"",
+        "'q r'",
+        "\"d $X\"",
+        "$X",
+        "${X}y",
+        "${X:-a b}",
+        "${X#'*'}",
+        "${X/a/$Y}",
+        "$1",
+        "${10}",
+        "$?",
+        "$@",
+        "$(a b)",
+        "\"$(c)\"",
+        "$((1 + 2))",
+        "~",
+        "~/p",
+        "A=~/a:~/b",
+        "a#b",
+        "\\#c",
+        "{a,b}",
+        "{1..3}",
+        "\\;",
+        "'if'",
+        "fi'x'",
+        "\"\"",
+        "*.md",
+        "a\\ b",
+        "$",
+        "\"\\$\"",
+        "'a'\"b\"c",
+        "\"${X:-\"q r\"}\"",
+        "${X%%.*}",
+        "${#X}",
+        "$((X + 1))",
+        "~user/x",
+        "#c",
+        "-n",
+        "a=",
+        "\"$X\"y",
+        "$X'y'",
+        "${X}_",
+        "\"a\\\"b\"",
+        "<<E\nb $X \\$ \\\\\nE\n",
+        "<<'E'\n$X\nE\n",
+        "<<-E\n\tb\n\tE\n",
+        "$(a\nb)",
+    ];
+
+    const REDIRECTS: &[&str] = &[
+        "<x", ">f", ">>g", "2>&1", "3<>rw", "<&0", "&>all", "<<<w", "2>e",
+    ];
+
+    /// ワードとリダイレクトを並べた単純コマンド
+    fn simple(rng: &mut Rng) -> String {
+        let mut items = Vec::new();
+        for _ in 0..1 + rng.below(4) {
+            items.push(if rng.below(4) == 0 {
+                rng.pick(REDIRECTS)
+            } else {
+                rng.pick(WORDS)
+            });
+        }
+        items.join(" ")
+    }
+
+    /// コマンド（複合コマンドは depth 段まで入れ子にする）
+    fn command(rng: &mut Rng, depth: usize) -> String {
+        let choice = if depth == 0 { 0 } else { rng.below(9) };
+        let list = |rng: &mut Rng| list(rng, depth - 1);
+        let cmd = match choice {
+            1 => format!(
+                "if {}; then {}; else {}; fi",
+                list(rng),
+                list(rng),
+                list(rng)
+            ),
+            2 => format!("while {}; do {}; done", list(rng), list(rng)),
+            3 => format!("for i in {}; do {}; done", rng.pick(WORDS), list(rng)),
+            4 => format!(
+                "case {} in {}) {};; esac",
+                rng.pick(WORDS),
+                rng.pick(WORDS),
+                list(rng)
+            ),
+            5 => format!("{{ {}; }}", list(rng)),
+            6 => format!("({})", list(rng)),
+            7 => format!("f() {{ {}; }}", list(rng)),
+            _ => return simple(rng),
+        };
+        if rng.below(3) == 0 {
+            format!("{} {}", cmd, rng.pick(REDIRECTS))
+        } else {
+            cmd
+        }
+    }
+
+    /// 接続子でつないだコマンドリスト
+    fn list(rng: &mut Rng, depth: usize) -> String {
+        let mut out = command(rng, depth);
+        for _ in 0..rng.below(3) {
+            out.push_str(rng.pick(&[" | ", " && ", " || ", "; ", " & ", "\n"]));
+            out.push_str(&command(rng, depth));
+        }
+        out
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for input in [
+            "echo hello world",
+            "cat <<EOF\nhi $USER\n$(date)\nEOF",
+            "cat <<'EOF'\n$HOME\nEOF",
+            "cat <<EOF\n\\$HOME \\\\ $X \\` $ a\\b\nEOF",
+            "cat <<-EOF\n\tindented\n\tEOF",
+            "case $x in\n(a|b) echo ab;;\n*.md) ;;\nesac",
+            "echo \"\\\\ \\$ \\` \\\"\"",
+            "<in esac",
+            "<x if a",
+            ">f { b",
+            "echo ${a/} ${a//} ${a/x/}",
+            "echo \"a$\" -$ \"$\"\\$",
+        ] {
+            assert_round_trip(input);
+        }
+
+        // パースできる入力を生成して確かめる
+        let mut rng = Rng(0x5eed);
+        let mut checked = 0;
+        for _ in 0..20_000 {
+            let input = list(&mut rng, 2);
+            if Parser::parse_input(&input).is_ok() {
+                assert_round_trip(&input);
+                checked += 1;
+            }
+        }
+        assert!(checked > 5_000, "only {} inputs parsed", checked);
+
+        // 記号を多く含む短い入力でも確かめる
+        // ブレース展開の結果と行継続は元の字面に戻せないので , と \ + 改行は使わない
+        let chars: Vec<char> = "ab12 '\"\\${}()[];&|<>#~*?\n=:-/%^!.\t@".chars().collect();
+        for _ in 0..100_000 {
+            let len = 1 + rng.below(14);
+            let input: String = (0..len).map(|_| chars[rng.below(chars.len())]).collect();
+            if !input.contains("\\\n") && Parser::parse_input(&input).is_ok() {
+                assert_round_trip(&input);
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! パス名展開（グロブ）
+//!
+//! `*`, `?`, `[...]` はパスの 1 要素の中でマッチし、`**` は 0 個以上のディレクトリにマッチする。
+//! `.` で始まる名前は、パターンの要素も `.` で始まる場合にのみマッチする。
+
+use crate::pattern;
+
+/// ディレクトリエントリ
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+/// パス名展開
+/// `list_dir` は絶対パスを受け取り、そのディレクトリのエントリを返す（存在しなければ None）。
+/// 結果はソート済みで、マッチしなければパターンをそのまま返す
+pub fn expand<F>(word: &str, cwd: &str, list_dir: F) -> Vec<String>
+where
+    F: Fn(&str) -> Option<Vec<DirEntry>>,
+{
+    if !pattern::has_wildcards(word) {
+        return vec![word.to_string()];
+    }
+
+    // 末尾の / はディレクトリだけにマッチさせる
+    let dir_only = word.ends_with('/');
+    let components: Vec<&str> = word.split('/').filter(|c| !c.is_empty()).collect();
+
+    // 表示用のパス（パターンと同じく相対 / 絶対）
+    let mut candidates = vec![if word.starts_with('/') {
+        "/".to_string()
+    } else {
+        String::new()
+    }];
+
+    for (i, component) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut next = Vec::new();
+
+        for candidate in &candidates {
+            let dir = resolve(cwd, candidate);
+
+            if *component == "**" {
+                if !last {
+                    next.push(candidate.clone());
+                }
+                walk(&dir, candidate, last, &list_dir, &mut next);
+                continue;
+            }
+
+            // . と .. は一覧に現れないのでそのまま辿る
+            if *component == "." || *component == ".." {
+                next.push(join(candidate, component));
+                continue;
+            }
+
+            let Some(entries) = list_dir(&dir) else {
+                continue;
+            };
+            let literal = pattern::unescape(component);
+            for entry in entries {
+                if (!last || dir_only) && !entry.is_dir {
+                    continue;
+                }
+                let matched = if pattern::has_wildcards(component) {
+                    (!entry.name.starts_with('.') || component.starts_with('.'))
+                        && pattern::matches_single(component, &entry.name)
+                } else {
+                    entry.name == literal
+                };
+                if matched {
+                    next.push(join(candidate, &entry.name));
+                }
+            }
+        }
+
+        candidates = next;
+    }
+
+    if candidates.is_empty() {
+        return vec![word.to_string()];
+    }
+
+    candidates.sort();
+    candidates.dedup();
+    if dir_only {
+        for candidate in &mut candidates {
+            candidate.push('/');
+        }
+    }
+    candidates
+}
+
+/// `**` のためにディレクトリを再帰的に辿る
+/// `include_files` が偽ならディレクトリだけを集める
+fn walk<F>(dir: &str, display: &str, include_files: bool, list_dir: &F, out: &mut Vec<String>)
+where
+    F: Fn(&str) -> Option<Vec<DirEntry>>,
+{
+    let Some(entries) = list_dir(dir) else {
+        return;
+    };
+    for entry in entries {
+        if entry.name.starts_with('.') {
+            continue;
+        }
+        let path = join(display, &entry.name);
+        if entry.is_dir {
+            out.push(path.clone());
+            walk(&join(dir, &entry.name), &path, include_files, list_dir, out);
+        } else if include_files {
+            out.push(path);
+        }
+    }
+}
+
+/// パスを連結
+fn join(base: &str, name: &str) -> String {
+    if base.is_empty() {
+        name.to_string()
+    } else if base.ends_with('/') {
+        format!("{}{}", base, name)
+    } else {
+        format!("{}/{}", base, name)
+    }
+}
+
+/// 表示用のパスを cwd からの絶対パスに正規化
+fn resolve(cwd: &str, path: &str) -> String {
+    let full = if path.starts_with('/') {
+        path.to_string()
+    } else {
+        join(cwd, path)
+    };
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in full.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// テスト用のファイルシステム
+    fn list(path: &str) -> Option<Vec<DirEntry>> {
+        let names: &[&str] = match path {
+            "/home" => &["docs/", "notes.md", "README.md", ".hidden.md", "a.txt"],
+            "/home/docs" => &["guide.md", "api/", ".git/"],
+            "/home/docs/api" => &["index.md"],
+            "/home/docs/.git" => &["config.md"],
+            "/" => &["home/", "bin/"],
+            "/bin" => &["ls", "cat"],
+            _ => return None,
+        };
+        Some(
+            names
+                .iter()
+                .map(|n| DirEntry {
+                    name: n.trim_end_matches('/').to_string(),
+                    is_dir: n.ends_with('/'),
+                })
+                .collect(),
+        )
+    }
+
+    fn glob(word: &str) -> Vec<String> {
+        expand(word, "/home", list)
+    }
+
+    #[test]
+    fn test_star() {
+        assert_eq!(glob("*.md"), vec!["README.md", "notes.md"]);
+        assert_eq!(glob("docs/*"), vec!["docs/api", "docs/guide.md"]);
+        assert_eq!(glob("/bin/*"), vec!["/bin/cat", "/bin/ls"]);
+    }
+
+    #[test]
+    fn test_question_and_bracket() {
+        assert_eq!(glob("?.txt"), vec!["a.txt"]);
+        assert_eq!(glob("[nR]*.md"), vec!["README.md", "notes.md"]);
+    }
+
+    #[test]
+    fn test_dotfiles() {
+        assert_eq!(glob(".*.md"), vec![".hidden.md"]);
+        assert!(!glob("*").contains(&".hidden.md".to_string()));
+    }
+
+    #[test]
+    fn test_directories() {
+        assert_eq!(glob("*/"), vec!["docs/"]);
+        assert_eq!(glob("*/*.md"), vec!["docs/guide.md"]);
+        assert_eq!(glob("../*"), vec!["../bin", "../home"]);
+    }
+
+    #[test]
+    fn test_globstar() {
+        assert_eq!(
+            glob("**/*.md"),
+            vec![
+                "README.md",
+                "docs/api/index.md",
+                "docs/guide.md",
+                "notes.md"
+            ]
+        );
+        assert_eq!(
+            glob("docs/**"),
+            vec!["docs/api", "docs/api/index.md", "docs/guide.md"]
+        );
+    }
+
+    #[test]
+    fn test_no_match_is_literal() {
+        assert_eq!(glob("*.rs"), vec!["*.rs"]);
+        assert_eq!(glob("missing/*"), vec!["missing/*"]);
+        assert_eq!(glob("README.md"), vec!["README.md"]);
+        assert_eq!(glob("\\*.md"), vec!["\\*.md"]);
+    }
+}
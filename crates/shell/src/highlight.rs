@@ -0,0 +1,720 @@
+//! シンタックスハイライト
+//!
+//! パーサーと同じレキサーで入力を分類する。入力途中の不完全な文字列や不正な文字列でも
+//! 読めたところまでを分類し、閉じていないクォートは末尾まで Quoted、
+//! トークン化できない部分やパースエラーの位置は Error として返す。
+
+use crate::ast::{Incomplete, Span};
+use crate::lexer::{reserved_word, scan, ReservedWord, SpannedToken, Token};
+use crate::parser::Parser;
+
+/// 分類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightKind {
+    /// コマンド名
+    Command,
+    /// 予約語（if, then, for など）
+    Keyword,
+    /// 引数
+    Argument,
+    /// - で始まる引数
+    Option,
+    /// クォートされた文字列
+    Quoted,
+    /// 変数参照 $VAR, ${VAR}
+    Variable,
+    /// コマンド置換 $(...) と算術式展開 $((...))
+    Substitution,
+    /// 制御演算子（|, &&, ;, ( など）
+    Operator,
+    /// リダイレクト
+    Redirect,
+    /// コメント
+    Comment,
+    /// エラー
+    Error,
+}
+
+/// 分類された範囲
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HighlightSpan {
+    pub kind: HighlightKind,
+    pub span: Span,
+}
+
+/// 入力を分類する
+/// 結果は開始位置順で重ならない。空白など分類のない部分は含まない
+pub fn highlight(input: &str) -> Vec<HighlightSpan> {
+    let scan = scan(input);
+
+    // トークン化できた場合だけパースして、構文エラーの位置を探す
+    let error_position = match &scan.error {
+        None => Parser::from_spanned(scan.tokens.clone())
+            .parse()
+            .err()
+            .filter(|e| e.incomplete.is_none())
+            .map(|e| e.position),
+        Some(_) => None,
+    };
+
+    let mut highlighter = Highlighter::new(input, error_position);
+    highlighter.tokens(&scan.tokens);
+
+    let mut spans = highlighter.spans;
+    spans.extend(scan.comments.iter().map(|&span| HighlightSpan {
+        kind: HighlightKind::Comment,
+        span,
+    }));
+    spans.extend(scan.here_doc_bodies.iter().map(|&span| HighlightSpan {
+        kind: HighlightKind::Quoted,
+        span,
+    }));
+
+    if let Some(error) = scan.error {
+        let kind = match error.incomplete {
+            Some(Incomplete::SingleQuote | Incomplete::DoubleQuote) => HighlightKind::Quoted,
+            Some(_) => return sorted(spans),
+            None => HighlightKind::Error,
+        };
+        spans.push(HighlightSpan {
+            kind,
+            span: Span::new(error.position, input.len() as u32),
+        });
+    }
+
+    sorted(spans)
+}
+
+/// 入力の末尾にあるワード
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CurrentWord {
+    pub kind: HighlightKind,
+    /// リダイレクト先のワードか
+    pub redirect_target: bool,
+    /// ワードの開始位置（末尾がワードの途中でなければ入力の末尾）
+    pub start: u32,
+}
+
+/// 入力の末尾にあるワードを分類する（補完に使う）
+/// 末尾がワードの途中でなければ、そこから始まるワードとして分類する。
+/// コメントの中やトークン化できない入力では None
+pub fn current_word(input: &str) -> Option<CurrentWord> {
+    let scan = scan(input);
+    let len = input.len() as u32;
+    // 閉じていないクォートはそこからワードが続いているとみなす
+    let end = match &scan.error {
+        None => len,
+        Some(e) => match e.incomplete {
+            Some(Incomplete::SingleQuote | Incomplete::DoubleQuote) => e.position,
+            _ => return None,
+        },
+    };
+    if scan.comments.last().is_some_and(|c| c.end == len) {
+        return None;
+    }
+
+    let mut highlighter = Highlighter::new(input, None);
+    highlighter.tokens(&scan.tokens);
+
+    let continues = highlighter.after_word && scan.tokens.last().is_some_and(|t| t.span.end == end);
+    match highlighter.word {
+        Some(kind) if continues => Some(CurrentWord {
+            kind,
+            redirect_target: highlighter.target,
+            start: highlighter.word_start,
+        }),
+        _ => {
+            let kind = highlighter.classify("", None);
+            Some(CurrentWord {
+                kind,
+                redirect_target: highlighter.target,
+                start: end,
+            })
+        }
+    }
+}
+
+fn sorted(mut spans: Vec<HighlightSpan>) -> Vec<HighlightSpan> {
+    spans.retain(|s| s.span.start < s.span.end);
+    spans.sort_by_key(|s| s.span.start);
+    spans
+}
+
+/// 予約語や case パターンなど、次のワードの読み方
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Expect {
+    /// 通常
+    Any,
+    /// for の変数名
+    Name,
+    /// case の対象ワード
+    Subject,
+    /// for / case の後の in（真なら case）
+    In(bool),
+    /// case のパターン
+    Pattern,
+}
+
+/// $( ... ) に入る前の状態
+#[derive(Debug, Clone, Copy)]
+struct Context {
+    command_position: bool,
+    expect: Expect,
+    word: Option<HighlightKind>,
+    word_start: u32,
+    target: bool,
+}
+
+struct Highlighter<'a> {
+    input: &'a str,
+    error_position: Option<u32>,
+    spans: Vec<HighlightSpan>,
+    /// 次のワードがコマンド名になるか
+    command_position: bool,
+    /// 次のワードがリダイレクト先か
+    redirect_target: bool,
+    expect: Expect,
+    /// 読んでいるワードの分類
+    word: Option<HighlightKind>,
+    /// 読んでいるワードの開始位置
+    word_start: u32,
+    /// 読んでいるワードがリダイレクト先か
+    target: bool,
+    /// 直前のトークンでワードの断片が終わったか
+    after_word: bool,
+    /// 開いている括弧（コマンド置換なら入る前の状態を持つ）
+    parens: Vec<Option<Context>>,
+}
+
+impl<'a> Highlighter<'a> {
+    fn new(input: &'a str, error_position: Option<u32>) -> Self {
+        Highlighter {
+            input,
+            error_position,
+            spans: Vec::new(),
+            command_position: true,
+            redirect_target: false,
+            expect: Expect::Any,
+            word: None,
+            word_start: 0,
+            target: false,
+            after_word: false,
+            parens: Vec::new(),
+        }
+    }
+
+    fn tokens(&mut self, tokens: &[SpannedToken]) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let current = &tokens[i];
+            let continues = current.joined && self.after_word;
+
+            if is_text(&current.token) {
+                // クォートの断片が分かれていても、連続した文字列はまとめて読む
+                let mut end = i + 1;
+                while end < tokens.len() && tokens[end].joined && is_text(&tokens[end].token) {
+                    end += 1;
+                }
+                let span = Span::new(current.span.start, tokens[end - 1].span.end);
+                let ends_word = tokens
+                    .get(end)
+                    .is_none_or(|next| !(next.joined && is_word_part(&next.token)));
+                let plain = match &current.token {
+                    Token::Word(s) if end == i + 1 && ends_word => Some(s.as_str()),
+                    _ => None,
+                };
+                let base = match self.word {
+                    Some(kind) if continues => kind,
+                    _ => self.start_word(span, plain),
+                };
+                if self.is_error(span) {
+                    self.push(HighlightKind::Error, span);
+                } else {
+                    self.text(span, base);
+                }
+                self.after_word = true;
+                i = end;
+                continue;
+            }
+
+            let span = current.span;
+            let kind = match &current.token {
+                Token::Arith(_) => {
+                    if !continues {
+                        self.start_word(span, None);
+                    }
+                    self.after_word = true;
+                    HighlightKind::Substitution
+                }
+                Token::SubstStart => {
+                    if !continues {
+                        self.start_word(span, None);
+                    }
+                    self.parens.push(Some(Context {
+                        command_position: self.command_position,
+                        expect: self.expect,
+                        word: self.word,
+                        word_start: self.word_start,
+                        target: self.target,
+                    }));
+                    self.enter_command();
+                    self.expect = Expect::Any;
+                    HighlightKind::Substitution
+                }
+                Token::ParenClose if self.expect == Expect::Pattern => {
+                    self.enter_command();
+                    self.expect = Expect::Any;
+                    HighlightKind::Operator
+                }
+                Token::ParenClose => match self.parens.pop().flatten() {
+                    Some(context) => {
+                        self.command_position = context.command_position;
+                        self.expect = context.expect;
+                        self.word = context.word;
+                        self.word_start = context.word_start;
+                        self.target = context.target;
+                        self.after_word = true;
+                        HighlightKind::Substitution
+                    }
+                    None => {
+                        self.end_word();
+                        self.command_position = false;
+                        HighlightKind::Operator
+                    }
+                },
+                Token::ParenOpen => {
+                    if self.expect != Expect::Pattern {
+                        self.parens.push(None);
+                    }
+                    self.enter_command();
+                    HighlightKind::Operator
+                }
+                Token::DoubleSemicolon => {
+                    self.end_word();
+                    self.expect = Expect::Pattern;
+                    HighlightKind::Operator
+                }
+                Token::Pipe
+                | Token::And
+                | Token::Or
+                | Token::Semicolon
+                | Token::Background
+                | Token::Newline => {
+                    self.enter_command();
+                    HighlightKind::Operator
+                }
+                Token::IoNumber(_) | Token::HereDoc(_) => {
+                    self.end_word();
+                    HighlightKind::Redirect
+                }
+                Token::RedirectOut
+                | Token::RedirectAppend
+                | Token::RedirectIn
+                | Token::RedirectReadWrite
+                | Token::DupOut
+                | Token::DupIn
+                | Token::RedirectAll
+                | Token::HereString => {
+                    self.end_word();
+                    self.redirect_target = true;
+                    HighlightKind::Redirect
+                }
+                Token::Word(_) | Token::SingleQuoted(_) | Token::DoubleQuoted(_) => unreachable!(),
+            };
+
+            if current.token != Token::Newline {
+                let kind = if self.is_error(span) {
+                    HighlightKind::Error
+                } else {
+                    kind
+                };
+                self.push(kind, span);
+            }
+            i += 1;
+        }
+    }
+
+    /// ワードの始まりで分類を決める
+    /// `plain` はワード全体が 1 つのクォートなしトークンの場合の文字列（予約語の判定に使う）
+    fn start_word(&mut self, span: Span, plain: Option<&str>) -> HighlightKind {
+        let source = self.source(span);
+        let kind = self.classify(source, plain);
+        self.word = Some(kind);
+        self.word_start = span.start;
+        kind
+    }
+
+    fn classify(&mut self, source: &str, plain: Option<&str>) -> HighlightKind {
+        self.target = self.redirect_target;
+        if self.redirect_target {
+            self.redirect_target = false;
+            return HighlightKind::Argument;
+        }
+
+        match self.expect {
+            Expect::Name => {
+                self.expect = Expect::In(false);
+                return HighlightKind::Variable;
+            }
+            Expect::Subject => {
+                self.expect = Expect::In(true);
+                return HighlightKind::Argument;
+            }
+            Expect::In(case) if plain == Some("in") => {
+                self.expect = if case { Expect::Pattern } else { Expect::Any };
+                return HighlightKind::Keyword;
+            }
+            Expect::In(_) => self.expect = Expect::Any,
+            Expect::Pattern if plain == Some("esac") => {
+                self.expect = Expect::Any;
+                self.command_position = false;
+                return HighlightKind::Keyword;
+            }
+            Expect::Pattern => return HighlightKind::Argument,
+            Expect::Any => {}
+        }
+
+        if self.command_position {
+            if let Some(word) = plain.and_then(reserved_word) {
+                match word {
+                    ReservedWord::For => self.expect = Expect::Name,
+                    ReservedWord::Case => self.expect = Expect::Subject,
+                    ReservedWord::Fi
+                    | ReservedWord::Done
+                    | ReservedWord::Esac
+                    | ReservedWord::RBrace
+                    | ReservedWord::In => {}
+                    _ => return HighlightKind::Keyword,
+                }
+                self.command_position = false;
+                return HighlightKind::Keyword;
+            }
+            // 代入の後もコマンド名が続く
+            if is_assignment(source) {
+                return HighlightKind::Argument;
+            }
+            self.command_position = false;
+            return HighlightKind::Command;
+        }
+
+        if source.starts_with('-') {
+            HighlightKind::Option
+        } else {
+            HighlightKind::Argument
+        }
+    }
+
+    /// 区切りの後はコマンド名の位置に戻る
+    fn enter_command(&mut self) {
+        self.end_word();
+        self.command_position = true;
+        self.redirect_target = false;
+    }
+
+    fn end_word(&mut self) {
+        self.word = None;
+        self.after_word = false;
+    }
+
+    /// ワード中の文字列を分類する
+    /// クォートの中身は Quoted、変数参照や展開は取り出し、残りは `base` にする
+    fn text(&mut self, span: Span, base: HighlightKind) {
+        let source = self.source(span);
+        let start = span.start as usize;
+        let mut double_quoted = false;
+        let mut i = 0;
+
+        while i < source.len() {
+            let rest = &source[i..];
+            let plain = if double_quoted {
+                HighlightKind::Quoted
+            } else {
+                base
+            };
+            let (kind, len) = if let Some(len) = expansion_len(rest) {
+                let kind = if rest.starts_with("${") || !rest.starts_with("$(") {
+                    HighlightKind::Variable
+                } else {
+                    HighlightKind::Substitution
+                };
+                (kind, len)
+            } else if let Some(escaped) = rest.strip_prefix('\\') {
+                (plain, 1 + escaped.chars().next().map_or(0, char::len_utf8))
+            } else if rest.starts_with('"') {
+                double_quoted = !double_quoted;
+                (HighlightKind::Quoted, 1)
+            } else if rest.starts_with('\'') && !double_quoted {
+                let len = rest[1..].find('\'').map_or(rest.len(), |end| end + 2);
+                (HighlightKind::Quoted, len)
+            } else {
+                (plain, rest.chars().next().map_or(1, char::len_utf8))
+            };
+            let from = (start + i) as u32;
+            self.push(kind, Span::new(from, from + len as u32));
+            i += len;
+        }
+    }
+
+    /// 隣接する同じ分類はまとめる
+    fn push(&mut self, kind: HighlightKind, span: Span) {
+        if let Some(last) = self.spans.last_mut() {
+            if last.kind == kind && last.span.end == span.start {
+                last.span.end = span.end;
+                return;
+            }
+        }
+        self.spans.push(HighlightSpan { kind, span });
+    }
+
+    fn is_error(&self, span: Span) -> bool {
+        self.error_position
+            .is_some_and(|pos| span.start <= pos && pos < span.end)
+    }
+
+    fn source(&self, span: Span) -> &'a str {
+        &self.input[span.start as usize..span.end as usize]
+    }
+}
+
+/// 文字列として読むトークン
+fn is_text(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Word(_) | Token::SingleQuoted(_) | Token::DoubleQuoted(_)
+    )
+}
+
+/// ワードの一部になるトークン
+fn is_word_part(token: &Token) -> bool {
+    is_text(token) || matches!(token, Token::Arith(_) | Token::SubstStart)
+}
+
+/// NAME=value 形式の代入か
+fn is_assignment(source: &str) -> bool {
+    source.split_once('=').is_some_and(|(name, _)| {
+        name.chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    })
+}
+
+/// 先頭の $ で始まる展開の長さ（展開でなければ None）
+fn expansion_len(rest: &str) -> Option<usize> {
+    let after = rest.strip_prefix('$')?;
+    if let Some(open) = after.chars().next().filter(|c| *c == '{' || *c == '(') {
+        let close = if open == '{' { '}' } else { ')' };
+        let mut depth = 0;
+        for (i, c) in after.char_indices() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 2);
+                }
+            }
+        }
+        return Some(rest.len());
+    }
+    let first = after.chars().next()?;
+    if first.is_ascii_alphabetic() || first == '_' {
+        let len = after
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(after.len());
+        return Some(len + 1);
+    }
+    if first.is_ascii_digit() || "?#@*$!-".contains(first) {
+        return Some(2);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use HighlightKind::*;
+
+    /// 分類と対応する文字列の組
+    fn kinds(input: &str) -> Vec<(HighlightKind, &str)> {
+        highlight(input)
+            .into_iter()
+            .map(|s| (s.kind, &input[s.span.start as usize..s.span.end as usize]))
+            .collect()
+    }
+
+    #[test]
+    fn test_command_and_arguments() {
+        assert_eq!(
+            kinds("ls -la docs"),
+            vec![(Command, "ls"), (Option, "-la"), (Argument, "docs")]
+        );
+        assert_eq!(
+            kinds("FOO=1 env"),
+            vec![(Argument, "FOO=1"), (Command, "env")]
+        );
+    }
+
+    #[test]
+    fn test_operators_and_redirects() {
+        assert_eq!(
+            kinds("cat a | grep b > out 2>&1 && echo ok"),
+            vec![
+                (Command, "cat"),
+                (Argument, "a"),
+                (Operator, "|"),
+                (Command, "grep"),
+                (Argument, "b"),
+                (Redirect, ">"),
+                (Argument, "out"),
+                (Redirect, "2>&"),
+                (Argument, "1"),
+                (Operator, "&&"),
+                (Command, "echo"),
+                (Argument, "ok"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quotes_and_variables() {
+        assert_eq!(
+            kinds("echo \"hi $USER\" 'x' a${B}c $?"),
+            vec![
+                (Command, "echo"),
+                (Quoted, "\"hi "),
+                (Variable, "$USER"),
+                (Quoted, "\""),
+                (Quoted, "'x'"),
+                (Argument, "a"),
+                (Variable, "${B}"),
+                (Argument, "c"),
+                (Variable, "$?"),
+            ]
+        );
+        assert_eq!(
+            kinds("echo \\$HOME"),
+            vec![(Command, "echo"), (Argument, "\\$HOME")]
+        );
+    }
+
+    #[test]
+    fn test_substitution() {
+        assert_eq!(
+            kinds("echo $(date +%Y) $((1 + 2))"),
+            vec![
+                (Command, "echo"),
+                (Substitution, "$("),
+                (Command, "date"),
+                (Argument, "+%Y"),
+                (Substitution, ")"),
+                (Substitution, "$((1 + 2))"),
+            ]
+        );
+        assert_eq!(
+            kinds("$(which ls) -l"),
+            vec![
+                (Substitution, "$("),
+                (Command, "which"),
+                (Argument, "ls"),
+                (Substitution, ")"),
+                (Option, "-l"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_keywords() {
+        assert_eq!(
+            kinds("if true; then echo if; fi"),
+            vec![
+                (Keyword, "if"),
+                (Command, "true"),
+                (Operator, ";"),
+                (Keyword, "then"),
+                (Command, "echo"),
+                (Argument, "if"),
+                (Operator, ";"),
+                (Keyword, "fi"),
+            ]
+        );
+        assert_eq!(
+            kinds("for f in a b; do cat $f; done"),
+            vec![
+                (Keyword, "for"),
+                (Variable, "f"),
+                (Keyword, "in"),
+                (Argument, "a"),
+                (Argument, "b"),
+                (Operator, ";"),
+                (Keyword, "do"),
+                (Command, "cat"),
+                (Variable, "$f"),
+                (Operator, ";"),
+                (Keyword, "done"),
+            ]
+        );
+        assert_eq!(
+            kinds("case $x in a|b) ls;; esac"),
+            vec![
+                (Keyword, "case"),
+                (Variable, "$x"),
+                (Keyword, "in"),
+                (Argument, "a"),
+                (Operator, "|"),
+                (Argument, "b"),
+                (Operator, ")"),
+                (Command, "ls"),
+                (Operator, ";;"),
+                (Keyword, "esac"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_and_here_docs() {
+        assert_eq!(
+            kinds("echo hi # note"),
+            vec![(Command, "echo"), (Argument, "hi"), (Comment, "# note")]
+        );
+        assert_eq!(
+            kinds("cat <<EOF\nbody\nEOF\n"),
+            vec![
+                (Command, "cat"),
+                (Redirect, "<<EOF"),
+                (Quoted, "body\nEOF\n")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_and_invalid() {
+        assert_eq!(
+            kinds("echo 'abc"),
+            vec![(Command, "echo"), (Quoted, "'abc")]
+        );
+        assert_eq!(
+            kinds("echo \"a $b"),
+            vec![(Command, "echo"), (Quoted, "\"a $b")]
+        );
+        assert_eq!(
+            kinds("echo a; fi"),
+            vec![
+                (Command, "echo"),
+                (Argument, "a"),
+                (Operator, ";"),
+                (Error, "fi"),
+            ]
+        );
+        // 不完全なだけの入力はエラーにしない
+        assert_eq!(
+            kinds("if true; then"),
+            vec![
+                (Keyword, "if"),
+                (Command, "true"),
+                (Operator, ";"),
+                (Keyword, "then"),
+            ]
+        );
+    }
+}
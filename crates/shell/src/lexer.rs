@@ -1,11 +1,12 @@
 //! シェルのレキサー（トークナイザー）
 
+use crate::ast::{Incomplete, ParseError, Span};
 use nom::{
     branch::alt,
     bytes::complete::{escaped_transform, is_not, tag, take_while, take_while1},
-    character::complete::{char, none_of},
-    combinator::{map, opt, value, verify},
-    sequence::delimited,
+    character::complete::{anychar, char, digit1, none_of, one_of},
+    combinator::{map, map_res, opt, peek, value, verify},
+    sequence::{delimited, terminated},
     IResult,
 };
 
@@ -30,14 +31,125 @@ pub enum Token {
     RedirectAppend,
     /// 入力リダイレクト <
     RedirectIn,
+    /// 読み書きリダイレクト <>
+    RedirectReadWrite,
+    /// 出力の複製 >&
+    DupOut,
+    /// 入力の複製 <&
+    DupIn,
+    /// 標準出力と標準エラー出力のリダイレクト &>
+    RedirectAll,
+    /// リダイレクト演算子の直前の IO 番号（2> の 2）
+    IoNumber(u32),
+    /// ヒアドキュメント <<DELIM, <<-DELIM（本文は区切り文字の次の行から読む）
+    HereDoc(HereDoc),
+    /// ヒアストリング <<<
+    HereString,
+    /// 逐次実行 ;
+    Semicolon,
+    /// case の分岐終端 ;;
+    DoubleSemicolon,
+    /// バックグラウンド実行 &
+    Background,
     /// コマンド置換開始 $(
     SubstStart,
+    /// 算術式展開 $(( ... ))（中身の式）
+    Arith(String),
+    /// 括弧開き (
+    ParenOpen,
     /// 括弧閉じ )
     ParenClose,
     /// 改行
     Newline,
 }
 
+/// 入力中の位置を付けたトークン
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+    /// 直前のトークンと空白を挟まずに続いているか
+    pub joined: bool,
+}
+
+/// ヒアドキュメント
+#[derive(Debug, Clone, PartialEq)]
+pub struct HereDoc {
+    /// 終端の区切り文字（クォートは除去済み）
+    pub delimiter: String,
+    /// 区切り文字がクォートされていた（本文を展開しない）
+    pub quoted: bool,
+    /// <<- 形式（本文と終端行の先頭のタブを除去する）
+    pub strip_tabs: bool,
+    /// 本文
+    pub body: String,
+}
+
+/// 予約語
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReservedWord {
+    If,
+    Then,
+    Elif,
+    Else,
+    Fi,
+    While,
+    Until,
+    Do,
+    Done,
+    For,
+    In,
+    Case,
+    Esac,
+    LBrace,
+    RBrace,
+}
+
+impl ReservedWord {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReservedWord::If => "if",
+            ReservedWord::Then => "then",
+            ReservedWord::Elif => "elif",
+            ReservedWord::Else => "else",
+            ReservedWord::Fi => "fi",
+            ReservedWord::While => "while",
+            ReservedWord::Until => "until",
+            ReservedWord::Do => "do",
+            ReservedWord::Done => "done",
+            ReservedWord::For => "for",
+            ReservedWord::In => "in",
+            ReservedWord::Case => "case",
+            ReservedWord::Esac => "esac",
+            ReservedWord::LBrace => "{",
+            ReservedWord::RBrace => "}",
+        }
+    }
+}
+
+/// ワードが予約語かどうか
+/// 予約語として扱うのはコマンド位置に現れた場合のみなので、判定はパーサーが行う
+pub fn reserved_word(s: &str) -> Option<ReservedWord> {
+    match s {
+        "if" => Some(ReservedWord::If),
+        "then" => Some(ReservedWord::Then),
+        "elif" => Some(ReservedWord::Elif),
+        "else" => Some(ReservedWord::Else),
+        "fi" => Some(ReservedWord::Fi),
+        "while" => Some(ReservedWord::While),
+        "until" => Some(ReservedWord::Until),
+        "do" => Some(ReservedWord::Do),
+        "done" => Some(ReservedWord::Done),
+        "for" => Some(ReservedWord::For),
+        "in" => Some(ReservedWord::In),
+        "case" => Some(ReservedWord::Case),
+        "esac" => Some(ReservedWord::Esac),
+        "{" => Some(ReservedWord::LBrace),
+        "}" => Some(ReservedWord::RBrace),
+        _ => None,
+    }
+}
+
 /// シェルのメタ文字かどうか
 /// $は環境変数のプレフィックスなのでメタ文字から除外
 /// $( はコマンド置換として別途処理する
@@ -48,10 +160,16 @@ fn is_meta(c: char) -> bool {
     )
 }
 
-/// 空白をスキップ
+/// 空白と行継続（\ の直後の改行）をスキップ
 pub fn skip_whitespace(input: &str) -> IResult<&str, ()> {
-    let (input, _) = take_while(|c| c == ' ' || c == '\t')(input)?;
-    Ok((input, ()))
+    let mut input = input;
+    loop {
+        let (rest, _) = take_while(|c| c == ' ' || c == '\t')(input)?;
+        match rest.strip_prefix("\\\n") {
+            Some(rest) => input = rest,
+            None => return Ok((rest, ())),
+        }
+    }
 }
 
 /// 通常のワードをパース
@@ -70,48 +188,207 @@ fn single_quoted(input: &str) -> IResult<&str, Token> {
     Ok((input, Token::SingleQuoted(s)))
 }
 
-/// ダブルクォート文字列をパース（エスケープ処理あり）
-fn double_quoted(input: &str) -> IResult<&str, Token> {
-    let (input, _) = char('"')(input)?;
+/// ダブルクォート文字列をパース
+/// \ は $, `, ", \ と改行の前でのみエスケープになり、それ以外ではそのまま残る。
+/// エスケープされた $ と ` は展開されないよう、前後を分けたシングルクォートのトークンにする。
+/// ${...} の中は閉じる } までそのまま残す。
+/// 各トークンには開始の " からの終了位置を添える
+pub fn double_quoted(input: &str) -> IResult<&str, Vec<(Token, usize)>> {
+    let (body, _) = char('"')(input)?;
+    let offset = |rest: &str| input.len() - rest.len();
 
+    let mut tokens = Vec::new();
     let mut result = String::new();
-    let mut remaining = input;
+    let mut remaining = body;
     let mut closed = false;
 
-    while !remaining.is_empty() {
-        if remaining.starts_with('"') {
+    while let Some(c) = remaining.chars().next() {
+        if c == '"' {
             remaining = &remaining[1..];
             closed = true;
             break;
-        } else if remaining.starts_with('\\') && remaining.len() > 1 {
-            let next = remaining.chars().nth(1).unwrap();
-            match next {
-                '\\' => result.push('\\'),
-                '"' => result.push('"'),
-                '$' => result.push('$'),
-                'n' => result.push('\n'),
-                't' => result.push('\t'),
-                _ => {
-                    result.push('\\');
+        }
+        if c == '\\' {
+            match remaining[1..].chars().next() {
+                Some(next @ ('$' | '`')) => {
+                    if !result.is_empty() {
+                        let end = offset(remaining);
+                        tokens.push((Token::DoubleQuoted(std::mem::take(&mut result)), end));
+                    }
+                    remaining = &remaining[2..];
+                    tokens.push((Token::SingleQuoted(next.to_string()), offset(remaining)));
+                    continue;
+                }
+                Some(next @ ('"' | '\\')) => {
                     result.push(next);
+                    remaining = &remaining[2..];
+                    continue;
+                }
+                Some('\n') => {
+                    remaining = &remaining[2..];
+                    continue;
                 }
+                _ => {}
             }
-            remaining = &remaining[2..];
-        } else {
-            let c = remaining.chars().next().unwrap();
-            result.push(c);
-            remaining = &remaining[c.len_utf8()..];
         }
+        // $$ の後ろの { は ${ の始まりではない
+        if let Some(rest) = remaining.strip_prefix("$$") {
+            result.push_str("$$");
+            remaining = rest;
+            continue;
+        }
+        if let Some(end) = remaining.strip_prefix("${").and_then(param_end) {
+            result.push_str(&remaining[..end + 3]);
+            remaining = &remaining[end + 3..];
+            continue;
+        }
+        result.push(c);
+        remaining = &remaining[c.len_utf8()..];
     }
 
     if !closed {
         return Err(nom::Err::Error(nom::error::Error::new(
-            input,
+            body,
             nom::error::ErrorKind::Tag,
         )));
     }
 
-    Ok((remaining, Token::DoubleQuoted(result)))
+    // 閉じる " は最後のトークンに含める
+    let end = offset(remaining);
+    match tokens.last_mut() {
+        Some((_, last)) if result.is_empty() => *last = end,
+        _ => tokens.push((Token::DoubleQuoted(result), end)),
+    }
+    Ok((remaining, tokens))
+}
+
+/// クォートの外の \ による 1 文字のエスケープをパース
+/// エスケープされた文字はシングルクォートと同じく展開されない
+fn escaped(input: &str) -> IResult<&str, Token> {
+    let (input, _) = char('\\')(input)?;
+    let (input, c) = anychar(input)?;
+    Ok((input, Token::SingleQuoted(c.to_string())))
+}
+
+/// ヒアドキュメントの開始（<<DELIM, <<-DELIM）をパース
+/// 本文はまだ読まれていないので、tokenize_spanned が次の改行の後で埋める
+fn here_doc(input: &str) -> IResult<&str, Token> {
+    let (input, _) = tag("<<")(input)?;
+    let (input, strip_tabs) = map(opt(char('-')), |c| c.is_some())(input)?;
+    let (input, _) = skip_whitespace(input)?;
+
+    let mut delimiter = String::new();
+    let mut quoted = false;
+    let mut remaining = input;
+
+    while let Some(c) = remaining.chars().next() {
+        match c {
+            '\'' | '"' => {
+                let end = remaining[1..].find(c).ok_or_else(|| {
+                    nom::Err::Error(nom::error::Error::new(
+                        remaining,
+                        nom::error::ErrorKind::Tag,
+                    ))
+                })?;
+                delimiter.push_str(&remaining[1..1 + end]);
+                remaining = &remaining[end + 2..];
+                quoted = true;
+            }
+            '\\' => {
+                remaining = &remaining[1..];
+                if let Some(next) = remaining.chars().next() {
+                    delimiter.push(next);
+                    remaining = &remaining[next.len_utf8()..];
+                }
+                quoted = true;
+            }
+            _ if is_meta(c) => break,
+            _ => {
+                delimiter.push(c);
+                remaining = &remaining[c.len_utf8()..];
+            }
+        }
+    }
+
+    if delimiter.is_empty() && !quoted {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::TakeWhile1,
+        )));
+    }
+
+    Ok((
+        remaining,
+        Token::HereDoc(HereDoc {
+            delimiter,
+            quoted,
+            strip_tabs,
+            body: String::new(),
+        }),
+    ))
+}
+
+/// ヒアドキュメントの本文を区切り文字の行まで読む
+/// 区切り文字が見つからない場合は None
+fn here_doc_body<'a>(input: &'a str, doc: &mut HereDoc) -> Option<&'a str> {
+    let mut remaining = input;
+
+    while !remaining.is_empty() {
+        let (line, rest) = match remaining.find('\n') {
+            Some(i) => (&remaining[..i], &remaining[i + 1..]),
+            None => (remaining, ""),
+        };
+        remaining = rest;
+
+        let line = if doc.strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            line
+        };
+        if line == doc.delimiter {
+            return Some(remaining);
+        }
+        doc.body.push_str(line);
+        doc.body.push('\n');
+    }
+
+    None
+}
+
+/// ヒアドキュメントの終端行がないエラー
+fn unterminated_here_doc(doc: &HereDoc, span: Span) -> ParseError {
+    ParseError::incomplete(
+        format!(
+            "syntax error: unexpected end of input (expected here-document delimiter `{}`)",
+            doc.delimiter
+        ),
+        span.start,
+        Incomplete::HereDoc(doc.delimiter.clone()),
+    )
+}
+
+/// 算術式展開 $(( ... )) をパース
+/// 対応する )) が見つからなければ $( ( ... ) ) としてコマンド置換に任せる
+fn arith_expansion(input: &str) -> IResult<&str, Token> {
+    let (rest, _) = tag("$((")(input)?;
+    let end = arith_end(rest).ok_or_else(|| {
+        nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag))
+    })?;
+    Ok((&rest[end + 2..], Token::Arith(rest[..end].to_string())))
+}
+
+/// $(( の後ろから、式を閉じる )) の位置を探す
+pub fn arith_end(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' if depth > 0 => depth -= 1,
+            ')' => return s[i + 1..].starts_with(')').then_some(i),
+            _ => {}
+        }
+    }
+    None
 }
 
 /// 演算子をパース
@@ -119,17 +396,97 @@ fn operator(input: &str) -> IResult<&str, Token> {
     alt((
         value(Token::And, tag("&&")),
         value(Token::Or, tag("||")),
+        value(Token::DoubleSemicolon, tag(";;")),
+        value(Token::RedirectAll, tag("&>")),
         value(Token::RedirectAppend, tag(">>")),
+        value(Token::DupOut, tag(">&")),
+        value(Token::HereString, tag("<<<")),
+        here_doc,
+        value(Token::DupIn, tag("<&")),
+        value(Token::RedirectReadWrite, tag("<>")),
+        arith_expansion,
         value(Token::SubstStart, tag("$(")),
         value(Token::Pipe, char('|')),
+        value(Token::Background, char('&')),
+        value(Token::Semicolon, char(';')),
         value(Token::RedirectOut, char('>')),
         value(Token::RedirectIn, char('<')),
+        value(Token::ParenOpen, char('(')),
         value(Token::ParenClose, char(')')),
     ))(input)
 }
 
+/// IO 番号をパース
+/// 数字だけのワードでも、直後にリダイレクト演算子が続く場合に限る
+fn io_number(input: &str) -> IResult<&str, Token> {
+    map(
+        terminated(map_res(digit1, str::parse), peek(one_of("<>"))),
+        Token::IoNumber,
+    )(input)
+}
+
 /// 環境変数を含む可能性のあるワードをパース
+/// ${ の直後から対応する } の位置を返す（閉じていなければ None）
+/// クォートとエスケープの中、入れ子の ${...} と $(...) の中の } は対応させない
+pub fn param_end(s: &str) -> Option<usize> {
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        if c == '}' {
+            return Some(i);
+        }
+        i = skip_unit(s, i)?;
+    }
+    None
+}
+
+/// s[i..] の先頭の 1 文字を読み飛ばした位置を返す
+/// クォート、エスケープ、${...}、$(...) はまとめて読み飛ばす（閉じていなければ None）
+pub fn skip_unit(s: &str, i: usize) -> Option<usize> {
+    let rest = &s[i..];
+    let c = rest.chars().next()?;
+    Some(match c {
+        '\\' => i + 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+        '\'' => i + 1 + rest[1..].find('\'')? + 1,
+        '"' => {
+            let mut j = i + 1;
+            loop {
+                match s[j..].chars().next()? {
+                    '"' => break j + 1,
+                    '\\' | '$' => j = skip_unit(s, j)?,
+                    c => j += c.len_utf8(),
+                }
+            }
+        }
+        '$' if rest[1..].starts_with('$') => i + 2,
+        '$' if rest[1..].starts_with('{') => i + 2 + param_end(&rest[2..])? + 1,
+        '$' if rest[1..].starts_with('(') => {
+            let mut depth = 0;
+            let mut j = i + 1;
+            loop {
+                match s[j..].chars().next()? {
+                    '(' => {
+                        depth += 1;
+                        j += 1;
+                    }
+                    ')' => {
+                        depth -= 1;
+                        j += 1;
+                        if depth == 0 {
+                            break j;
+                        }
+                    }
+                    '\\' | '\'' | '"' | '$' => j = skip_unit(s, j)?,
+                    c => j += c.len_utf8(),
+                }
+            }
+        }
+        c => i + c.len_utf8(),
+    })
+}
+
 /// $VAR, ${VAR}, ${VAR:-default} を検出
+/// \ の手前で止め、エスケープは `escaped` に任せる
+/// 行継続は取り除き、${...} は空白やクォートを含んでいても閉じる } までそのまま残す
 fn word_with_vars(input: &str) -> IResult<&str, Token> {
     let mut result = String::new();
     let mut remaining = input;
@@ -147,12 +504,32 @@ fn word_with_vars(input: &str) -> IResult<&str, Token> {
             break;
         }
 
+        if let Some(rest) = remaining.strip_prefix("\\\n") {
+            remaining = rest;
+            continue;
+        }
+
         let c = remaining.chars().next().unwrap();
 
         if is_meta(c) {
             break;
         }
 
+        if c == '\\' {
+            break;
+        }
+        // $$ の後ろの { は ${ の始まりではない
+        if let Some(rest) = remaining.strip_prefix("$$") {
+            result.push_str("$$");
+            remaining = rest;
+            continue;
+        }
+        if let Some(end) = remaining.strip_prefix("${").and_then(param_end) {
+            result.push_str(&remaining[..end + 3]);
+            remaining = &remaining[end + 3..];
+            continue;
+        }
+
         result.push(c);
         remaining = &remaining[c.len_utf8()..];
     }
@@ -178,48 +555,222 @@ pub fn token(input: &str) -> IResult<&str, Token> {
         )));
     }
 
-    alt((operator, single_quoted, double_quoted, word_with_vars))(input)
+    alt((io_number, operator, single_quoted, escaped, word_with_vars))(input)
 }
 
-/// 入力文字列をトークン列にパース
-pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
-    let mut tokens = Vec::new();
-    let mut remaining = input;
+/// ワードの途中に続くトークンをパース（IO 番号はワードの先頭でのみ認識する）
+fn word_piece(input: &str) -> IResult<&str, Token> {
+    alt((operator, single_quoted, escaped, word_with_vars))(input)
+}
+
+/// 入力を位置付きのトークン列に変換
+/// `joined` は `a"b"` のような連続した断片を 1 つのワードにまとめるために使う
+pub fn tokenize_spanned(input: &str) -> Result<Vec<SpannedToken>, ParseError> {
+    let mut scan = Scan::default();
+    scan_into(input, &mut scan)?;
+    Ok(scan.tokens)
+}
+
+/// トークン化の結果（エラーがあっても途中までを返す）
+#[derive(Debug, Default)]
+pub struct Scan {
+    pub tokens: Vec<SpannedToken>,
+    /// コメントの範囲
+    pub comments: Vec<Span>,
+    /// ヒアドキュメントの本文の範囲
+    pub here_doc_bodies: Vec<Span>,
+    /// トークン化を止めたエラー
+    pub error: Option<ParseError>,
+}
+
+/// 不正な入力も途中までトークン化する（シンタックスハイライト用）
+pub fn scan(input: &str) -> Scan {
+    let mut scan = Scan::default();
+    scan.error = scan_into(input, &mut scan).err();
+    scan
+}
+
+fn scan_into(input: &str, scan: &mut Scan) -> Result<(), ParseError> {
+    let mut end = 0;
+    while end < input.len() {
+        end = scan_line(input, scan, end)?;
+    }
+    Ok(())
+}
+
+/// input[start..] の 1 行（続くヒアドキュメントの本文を含む）をトークン化し、読み終えた位置を返す
+/// クォートの中の改行では止まらない
+pub fn scan_line(input: &str, scan: &mut Scan, start: usize) -> Result<usize, ParseError> {
+    let tokens = &mut scan.tokens;
+    let mut remaining = &input[start..];
+    // 本文を読み待ちのヒアドキュメントのトークン位置
+    let mut pending_here_docs = Vec::new();
+    let offset = |rest: &str| (input.len() - rest.len()) as u32;
 
     loop {
-        // 改行以外の空白をスキップ
-        let (rest, _) = skip_whitespace(remaining).map_err(|e| format!("Parse error: {:?}", e))?;
+        // 改行以外の空白をスキップ（行継続だけを挟む場合は続いているものとする）
+        let (rest, _) = skip_whitespace(remaining)
+            .map_err(|e| ParseError::new(format!("Parse error: {:?}", e), offset(remaining)))?;
+        let skipped = &remaining[..remaining.len() - rest.len()];
+        let joined = !tokens.is_empty() && skipped.split("\\\n").all(str::is_empty);
         remaining = rest;
 
         if remaining.is_empty() {
             break;
         }
 
+        // ワードの先頭の # から行末まではコメント
+        if remaining.starts_with('#') && !(joined && continues_word(tokens)) {
+            let start = offset(remaining);
+            remaining = &remaining[remaining.find('\n').unwrap_or(remaining.len())..];
+            scan.comments.push(Span::new(start, offset(remaining)));
+            continue;
+        }
+
         // 改行を検出
         if remaining.starts_with('\n') {
-            tokens.push(Token::Newline);
+            let start = offset(remaining);
+            tokens.push(SpannedToken {
+                token: Token::Newline,
+                span: Span::new(start, start + 1),
+                joined,
+            });
             remaining = &remaining[1..];
-            continue;
+
+            // 改行の後にヒアドキュメントの本文が続く
+            for i in pending_here_docs.drain(..) {
+                if let Some(SpannedToken {
+                    token: Token::HereDoc(doc),
+                    span,
+                    ..
+                }) = tokens.get_mut(i)
+                {
+                    let start = offset(remaining);
+                    remaining = here_doc_body(remaining, doc)
+                        .ok_or_else(|| unterminated_here_doc(doc, *span))?;
+                    scan.here_doc_bodies
+                        .push(Span::new(start, offset(remaining)));
+                }
+            }
+            break;
         }
 
-        match token(remaining) {
-            Ok((rest, tok)) => {
-                tokens.push(tok);
+        // ダブルクォート文字列はエスケープの前後で複数のトークンになる
+        // a"b"2>x の 2 はワードの一部で、IO 番号ではない
+        let in_word = joined
+            && matches!(
+                tokens.last().map(|tok| &tok.token),
+                Some(
+                    Token::Word(_)
+                        | Token::SingleQuoted(_)
+                        | Token::DoubleQuoted(_)
+                        | Token::Arith(_)
+                )
+            );
+        let result = if remaining.starts_with('"') {
+            double_quoted(remaining)
+        } else {
+            let parse = if in_word { word_piece } else { token };
+            parse(remaining).map(|(rest, tok)| (rest, vec![(tok, remaining.len() - rest.len())]))
+        };
+
+        let start = offset(remaining);
+        match result {
+            Ok((rest, toks)) => {
+                let mut piece_start = start;
+                for (i, (tok, end)) in toks.into_iter().enumerate() {
+                    if matches!(tok, Token::HereDoc(_)) {
+                        pending_here_docs.push(tokens.len());
+                    }
+                    let end = start + end as u32;
+                    tokens.push(SpannedToken {
+                        token: tok,
+                        span: Span::new(piece_start, end),
+                        joined: joined || i > 0,
+                    });
+                    piece_start = end;
+                }
                 remaining = rest;
             }
+            // 閉じていないクォートと行末の \ は続きの入力を待てる
+            Err(_) if remaining.starts_with('\'') => {
+                return Err(ParseError::incomplete(
+                    "syntax error: unexpected end of input (expected `'`)",
+                    start,
+                    Incomplete::SingleQuote,
+                ));
+            }
+            Err(_) if remaining.starts_with('"') => {
+                return Err(ParseError::incomplete(
+                    "syntax error: unexpected end of input (expected `\"`)",
+                    start,
+                    Incomplete::DoubleQuote,
+                ));
+            }
+            Err(_) if remaining == "\\" => {
+                return Err(ParseError::incomplete(
+                    "syntax error: unexpected end of input after `\\`",
+                    start,
+                    Incomplete::LineContinuation,
+                ));
+            }
             Err(e) => {
-                return Err(format!("Tokenize error at '{}': {:?}", remaining, e));
+                return Err(ParseError::new(
+                    format!("Tokenize error at '{}': {:?}", remaining, e),
+                    start,
+                ));
             }
         }
     }
 
-    Ok(tokens)
+    // 改行がないまま終わったヒアドキュメント
+    if let Some(&i) = pending_here_docs.first() {
+        if let SpannedToken {
+            token: Token::HereDoc(doc),
+            span,
+            ..
+        } = &tokens[i]
+        {
+            return Err(unterminated_here_doc(doc, *span));
+        }
+    }
+
+    Ok(offset(remaining) as usize)
+}
+
+/// 直後に空白なしで続く文字が同じワードの一部になるか
+fn continues_word(tokens: &[SpannedToken]) -> bool {
+    matches!(
+        tokens.last().map(|tok| &tok.token),
+        Some(
+            Token::Word(_)
+                | Token::SingleQuoted(_)
+                | Token::DoubleQuoted(_)
+                | Token::Arith(_)
+                | Token::ParenClose
+        )
+    )
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+        Ok(tokenize_spanned(input)?
+            .into_iter()
+            .map(|tok| tok.token)
+            .collect())
+    }
+
+    /// トークンと直前のトークンに続いているかの組
+    fn tokenize_joined(input: &str) -> Result<Vec<(Token, bool)>, ParseError> {
+        Ok(tokenize_spanned(input)?
+            .into_iter()
+            .map(|tok| (tok.token, tok.joined))
+            .collect())
+    }
+
     #[test]
     fn test_simple_word() {
         let tokens = tokenize("hello").unwrap();
@@ -238,6 +789,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_joined() {
+        let tokens = tokenize_joined("echo a\"b\"'c' $X$(d) e").unwrap();
+        let joined: Vec<bool> = tokens.iter().map(|(_, joined)| *joined).collect();
+        assert_eq!(
+            joined,
+            vec![false, false, true, true, false, true, true, true, false]
+        );
+    }
+
+    #[test]
+    fn test_spans() {
+        let spans: Vec<(u32, u32)> = tokenize_spanned("ls  -a|\n \"x\\$y\"")
+            .unwrap()
+            .iter()
+            .map(|tok| (tok.span.start, tok.span.end))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![(0, 2), (4, 6), (6, 7), (7, 8), (9, 11), (11, 13), (13, 15)]
+        );
+
+        let err = tokenize_spanned("echo 'abc").unwrap_err();
+        assert_eq!(err.position, 5);
+    }
+
     #[test]
     fn test_single_quoted() {
         let tokens = tokenize("'hello world'").unwrap();
@@ -250,6 +827,122 @@ mod tests {
         assert_eq!(tokens, vec![Token::DoubleQuoted("hello world".to_string())]);
     }
 
+    #[test]
+    fn test_backslash_escape() {
+        let word = |s: &str| Token::Word(s.to_string());
+        let quoted = |s: &str| Token::SingleQuoted(s.to_string());
+        let tokens = tokenize_joined(r"echo a\ b \$HOME \;x").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (word("echo"), false),
+                (word("a"), false),
+                (quoted(" "), true),
+                (word("b"), true),
+                (quoted("$"), false),
+                (word("HOME"), true),
+                (quoted(";"), false),
+                (word("x"), true),
+            ]
+        );
+
+        // ${...} の中はパラメータ展開が解釈する
+        assert_eq!(tokenize(r"${X:-a\}b}").unwrap(), vec![word(r"${X:-a\}b}")]);
+        assert_eq!(
+            tokenize(r#"echo ${X:-a b} ${X#"*"}x ${X:-'}'}"#).unwrap(),
+            vec![
+                word("echo"),
+                word("${X:-a b}"),
+                word(r#"${X#"*"}x"#),
+                word("${X:-'}'}")
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""${X:-"a b"}" ${X:-$(echo })}"#).unwrap(),
+            vec![
+                Token::DoubleQuoted(r#"${X:-"a b"}"#.to_string()),
+                word("${X:-$(echo })}")
+            ]
+        );
+        // 末尾の \ は続きの行を待つ
+        let err = tokenize(r"echo a\").unwrap_err();
+        assert_eq!(err.incomplete, Some(Incomplete::LineContinuation));
+        assert_eq!(err.position, 6);
+    }
+
+    #[test]
+    fn test_double_quoted_escape() {
+        let tokens = tokenize_joined(r#""a\"b\\c\$d\n""#).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::DoubleQuoted(r#"a"b\c"#.to_string()), false),
+                (Token::SingleQuoted("$".to_string()), true),
+                (Token::DoubleQuoted(r"d\n".to_string()), true),
+            ]
+        );
+        assert_eq!(
+            tokenize(r#""\$""#).unwrap(),
+            vec![Token::SingleQuoted("$".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_line_continuation() {
+        let tokens = tokenize_joined("echo a\\\nb \\\n  c \"d\\\ne\"\\\nf").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Word("echo".to_string()), false),
+                (Token::Word("ab".to_string()), false),
+                (Token::Word("c".to_string()), false),
+                (Token::DoubleQuoted("de".to_string()), false),
+                (Token::Word("f".to_string()), true),
+            ]
+        );
+        assert_eq!(
+            tokenize("ls \\\n| cat").unwrap(),
+            vec![
+                Token::Word("ls".to_string()),
+                Token::Pipe,
+                Token::Word("cat".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comment() {
+        let word = |s: &str| Token::Word(s.to_string());
+        assert_eq!(
+            tokenize("ls # list files\necho a#b '#' \\#c").unwrap(),
+            vec![
+                word("ls"),
+                Token::Newline,
+                word("echo"),
+                word("a#b"),
+                Token::SingleQuoted("#".to_string()),
+                Token::SingleQuoted("#".to_string()),
+                word("c"),
+            ]
+        );
+        assert_eq!(
+            tokenize("#!/bin/sh\necho $# x;#y").unwrap(),
+            vec![
+                Token::Newline,
+                word("echo"),
+                word("$#"),
+                word("x"),
+                Token::Semicolon
+            ]
+        );
+        // コメントの後でもヒアドキュメントの本文は次の行から
+        let tokens = tokenize("cat <<EOF # body\n# not a comment\nEOF").unwrap();
+        let Token::HereDoc(doc) = &tokens[1] else {
+            panic!("Expected here-doc");
+        };
+        assert_eq!(doc.body, "# not a comment\n");
+    }
+
     #[test]
     fn test_pipe() {
         let tokens = tokenize("ls | cat").unwrap();
@@ -278,6 +971,204 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_semicolon_and_background() {
+        let tokens = tokenize("echo a; sleep 5 &").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("echo".to_string()),
+                Token::Word("a".to_string()),
+                Token::Semicolon,
+                Token::Word("sleep".to_string()),
+                Token::Word("5".to_string()),
+                Token::Background
+            ]
+        );
+    }
+
+    #[test]
+    fn test_double_semicolon() {
+        let tokens = tokenize("a) echo;; b;").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("a".to_string()),
+                Token::ParenClose,
+                Token::Word("echo".to_string()),
+                Token::DoubleSemicolon,
+                Token::Word("b".to_string()),
+                Token::Semicolon
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_definition() {
+        let tokens = tokenize("greet() { echo hi; }").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("greet".to_string()),
+                Token::ParenOpen,
+                Token::ParenClose,
+                Token::Word("{".to_string()),
+                Token::Word("echo".to_string()),
+                Token::Word("hi".to_string()),
+                Token::Semicolon,
+                Token::Word("}".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_here_doc() {
+        let tokens = tokenize("cat <<EOF > out\nhello $USER\n  world\nEOF\necho done").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("cat".to_string()),
+                Token::HereDoc(HereDoc {
+                    delimiter: "EOF".to_string(),
+                    quoted: false,
+                    strip_tabs: false,
+                    body: "hello $USER\n  world\n".to_string(),
+                }),
+                Token::RedirectOut,
+                Token::Word("out".to_string()),
+                Token::Newline,
+                Token::Word("echo".to_string()),
+                Token::Word("done".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_here_doc_forms() {
+        // <<- はタブを除去し、クォートされた区切り文字は quoted になる
+        let tokens = tokenize("cat <<-'END'\n\tone\n\t\ttwo\n\tEND\n").unwrap();
+        assert_eq!(
+            tokens[1],
+            Token::HereDoc(HereDoc {
+                delimiter: "END".to_string(),
+                quoted: true,
+                strip_tabs: true,
+                body: "one\ntwo\n".to_string(),
+            })
+        );
+
+        // 同じ行に複数ある場合は順に本文を読む
+        let tokens = tokenize("cat <<A <<\"B\"\na\nA\nb\nB").unwrap();
+        let bodies: Vec<_> = tokens
+            .iter()
+            .filter_map(|t| match t {
+                Token::HereDoc(doc) => Some(doc.body.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bodies, vec!["a\n", "b\n"]);
+    }
+
+    #[test]
+    fn test_here_string() {
+        let tokens = tokenize("cat <<< 'hi there'").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("cat".to_string()),
+                Token::HereString,
+                Token::SingleQuoted("hi there".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fd_redirects() {
+        let tokens = tokenize("cmd 2>err 2>&1 &>all >&2 <>rw 3<&0").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("cmd".to_string()),
+                Token::IoNumber(2),
+                Token::RedirectOut,
+                Token::Word("err".to_string()),
+                Token::IoNumber(2),
+                Token::DupOut,
+                Token::Word("1".to_string()),
+                Token::RedirectAll,
+                Token::Word("all".to_string()),
+                Token::DupOut,
+                Token::Word("2".to_string()),
+                Token::RedirectReadWrite,
+                Token::Word("rw".to_string()),
+                Token::IoNumber(3),
+                Token::DupIn,
+                Token::Word("0".to_string())
+            ]
+        );
+
+        // 演算子が離れている数字や、数字以外を含むワードは IO 番号ではない
+        let tokens = tokenize("echo 2 >x a2>y").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("echo".to_string()),
+                Token::Word("2".to_string()),
+                Token::RedirectOut,
+                Token::Word("x".to_string()),
+                Token::Word("a2".to_string()),
+                Token::RedirectOut,
+                Token::Word("y".to_string())
+            ]
+        );
+
+        // クォートされた断片の直後の数字もワードの一部
+        let tokens = tokenize("a\"b\"2>x 'c'3<y").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("a".to_string()),
+                Token::DoubleQuoted("b".to_string()),
+                Token::Word("2".to_string()),
+                Token::RedirectOut,
+                Token::Word("x".to_string()),
+                Token::SingleQuoted("c".to_string()),
+                Token::Word("3".to_string()),
+                Token::RedirectIn,
+                Token::Word("y".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arith() {
+        let tokens = tokenize("echo $(( (1 + 2) * 3 )) $((echo a); echo b)").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("echo".to_string()),
+                Token::Arith(" (1 + 2) * 3 ".to_string()),
+                Token::SubstStart,
+                Token::ParenOpen,
+                Token::Word("echo".to_string()),
+                Token::Word("a".to_string()),
+                Token::ParenClose,
+                Token::Semicolon,
+                Token::Word("echo".to_string()),
+                Token::Word("b".to_string()),
+                Token::ParenClose
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reserved_word() {
+        assert_eq!(reserved_word("if"), Some(ReservedWord::If));
+        assert_eq!(reserved_word("fi"), Some(ReservedWord::Fi));
+        assert_eq!(reserved_word("iff"), None);
+        assert_eq!(ReservedWord::Elif.as_str(), "elif");
+    }
+
     #[test]
     fn test_redirect() {
         let tokens = tokenize("echo hello > file").unwrap();
@@ -1,37 +1,211 @@
 //! シェルパーサー WASM コンポーネント
 
+mod arith;
 mod ast;
+mod brace;
+mod completer;
 mod expander;
+mod formatter;
+mod glob;
+mod highlight;
 mod lexer;
 mod parser;
+mod pattern;
 
 use std::collections::HashMap;
 
 wit_bindgen::generate!({
     world: "shell",
+    generate_all,
 });
 
+use uzimaru::filesystem::filesystem;
+use uzimaru::shell::completion_source;
 use uzimaru::shell::types;
 
 struct ShellImpl;
 
 impl exports::uzimaru::shell::parser::Guest for ShellImpl {
-    fn parse(input: String) -> Result<types::ParseResult, types::ParseError> {
-        match parser::Parser::parse_input(&input) {
-            Ok(result) => Ok(convert_parse_result(result)),
-            Err(e) => Err(types::ParseError {
-                message: e.message,
-                position: e.position,
-            }),
-        }
+    fn parse(
+        input: String,
+        aliases: Vec<(String, String)>,
+    ) -> Result<types::ParseResult, types::ParseFailure> {
+        parser::Parser::parse_input_with_aliases(&input, aliases.into_iter().collect())
+            .map(convert_parse_result)
+            .map_err(convert_parse_failure)
+    }
+
+    fn parse_next(
+        input: String,
+        aliases: Vec<(String, String)>,
+    ) -> Result<types::NextCommand, types::ParseFailure> {
+        parser::Parser::parse_next_with_aliases(&input, aliases.into_iter().collect())
+            .map(|(result, rest)| types::NextCommand {
+                parsed: convert_parse_result(result),
+                rest: rest.to_string(),
+            })
+            .map_err(convert_parse_failure)
+    }
+}
+
+fn convert_parse_failure(e: ast::ParseError) -> types::ParseFailure {
+    let error = types::ParseError {
+        message: e.message,
+        position: e.position,
+    };
+    match e.incomplete {
+        Some(reason) => types::ParseFailure::Incomplete(types::IncompleteInput {
+            reason: convert_incomplete(reason),
+            error,
+        }),
+        None => types::ParseFailure::Error(error),
     }
 }
 
 impl exports::uzimaru::shell::expander::Guest for ShellImpl {
-    fn expand_word(word: types::ParsedWord, env: Vec<(String, String)>) -> String {
+    fn expand_word(
+        word: types::ParsedWord,
+        ctx: types::ExpandContext,
+    ) -> Result<types::WordResult, String> {
+        let rust_word = convert_from_wit_word(word);
+        let mut ctx = convert_expand_context(ctx);
+        let original = ctx.env.clone();
+        let value = expander::expand_segments(&rust_word, &mut ctx).map_err(|e| e.to_string())?;
+        Ok(types::WordResult {
+            value,
+            assignments: changed_vars(&original, ctx.env),
+        })
+    }
+
+    fn expand_pattern(
+        word: types::ParsedWord,
+        ctx: types::ExpandContext,
+    ) -> Result<types::WordResult, String> {
         let rust_word = convert_from_wit_word(word);
-        let env_map: HashMap<String, String> = env.into_iter().collect();
-        expander::expand_word(&rust_word, &env_map)
+        let mut ctx = convert_expand_context(ctx);
+        let original = ctx.env.clone();
+        let value = expander::expand_pattern(&rust_word, &mut ctx).map_err(|e| e.to_string())?;
+        Ok(types::WordResult {
+            value,
+            assignments: changed_vars(&original, ctx.env),
+        })
+    }
+
+    fn expand_words(
+        word: types::ParsedWord,
+        ctx: types::ExpandContext,
+    ) -> Result<types::FieldsResult, String> {
+        let rust_word = convert_from_wit_word(word);
+        let mut ctx = convert_expand_context(ctx);
+        let original = ctx.env.clone();
+        let fields = expander::expand_fields(&rust_word, &mut ctx).map_err(|e| e.to_string())?;
+        Ok(types::FieldsResult {
+            fields: fields
+                .into_iter()
+                .map(|field| types::Field {
+                    value: field.value,
+                    pattern: field.pattern,
+                })
+                .collect(),
+            assignments: changed_vars(&original, ctx.env),
+        })
+    }
+}
+
+/// 評価中に代入された変数（名前順）
+fn changed_vars(
+    original: &HashMap<String, String>,
+    vars: HashMap<String, String>,
+) -> Vec<(String, String)> {
+    let mut assignments: Vec<(String, String)> = vars
+        .into_iter()
+        .filter(|(name, value)| original.get(name) != Some(value))
+        .collect();
+    assignments.sort();
+    assignments
+}
+
+impl exports::uzimaru::shell::pattern::Guest for ShellImpl {
+    fn match_pattern(pattern: String, text: String) -> bool {
+        pattern::matches(&pattern, &text)
+    }
+}
+
+impl exports::uzimaru::shell::glob::Guest for ShellImpl {
+    fn expand_glob(pattern: String, cwd: String) -> Vec<String> {
+        glob::expand(&pattern, &cwd, |path| {
+            filesystem::list_dir(path).ok().map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| glob::DirEntry {
+                        name: entry.name,
+                        is_dir: entry.is_dir,
+                    })
+                    .collect()
+            })
+        })
+    }
+}
+
+impl exports::uzimaru::shell::formatter::Guest for ShellImpl {
+    fn format(ast: types::ParseResult) -> String {
+        formatter::format(&convert_from_wit_parse_result(ast))
+    }
+}
+
+impl exports::uzimaru::shell::highlighter::Guest for ShellImpl {
+    fn highlight(input: String) -> Vec<types::HighlightSpan> {
+        highlight::highlight(&input)
+            .into_iter()
+            .map(|s| types::HighlightSpan {
+                kind: convert_highlight_kind(s.kind),
+                span: convert_span(s.span),
+            })
+            .collect()
+    }
+}
+
+impl exports::uzimaru::shell::completer::Guest for ShellImpl {
+    fn complete(line: String, cursor: u32) -> types::Completion {
+        let completion = completer::complete(&line, cursor as usize, &HostCompletionSource);
+        types::Completion {
+            replace: convert_span(completion.replace),
+            candidates: completion
+                .candidates
+                .into_iter()
+                .map(|c| types::Candidate {
+                    value: c.value,
+                    kind: convert_candidate_kind(c.kind),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// ホストの completion-source から候補を取得する
+struct HostCompletionSource;
+
+impl completer::CompletionSource for HostCompletionSource {
+    fn commands(&self) -> Vec<String> {
+        completion_source::commands()
+    }
+
+    fn list_dir(&self, dir: &str) -> Vec<glob::DirEntry> {
+        completion_source::list_dir(dir)
+            .into_iter()
+            .map(|entry| glob::DirEntry {
+                name: entry.name,
+                is_dir: entry.is_dir,
+            })
+            .collect()
+    }
+
+    fn env_names(&self) -> Vec<String> {
+        completion_source::env_names()
+    }
+
+    fn aliases(&self) -> Vec<String> {
+        completion_source::aliases()
     }
 }
 
@@ -49,13 +223,46 @@ fn convert_parse_result(result: ast::ParseResult) -> types::ParseResult {
             .into_iter()
             .map(convert_command_substitution)
             .collect(),
+        compounds: result
+            .compounds
+            .into_iter()
+            .map(convert_compound_command)
+            .collect(),
+        functions: result
+            .functions
+            .into_iter()
+            .map(|def| types::FunctionDef {
+                name: def.name,
+                body: def.body,
+                span: convert_span(def.span),
+            })
+            .collect(),
+    }
+}
+
+fn convert_incomplete(reason: ast::Incomplete) -> types::IncompleteReason {
+    match reason {
+        ast::Incomplete::SingleQuote => types::IncompleteReason::SingleQuote,
+        ast::Incomplete::DoubleQuote => types::IncompleteReason::DoubleQuote,
+        ast::Incomplete::LineContinuation => types::IncompleteReason::LineContinuation,
+        ast::Incomplete::HereDoc(delimiter) => types::IncompleteReason::HereDoc(delimiter),
+        ast::Incomplete::CommandSubstitution => types::IncompleteReason::CommandSubstitution,
+        ast::Incomplete::Pipe => types::IncompleteReason::Pipe,
+        ast::Incomplete::AndOr => types::IncompleteReason::AndOr,
+        ast::Incomplete::Compound(closer) => types::IncompleteReason::Compound(closer),
     }
 }
 
+fn convert_list(list: Vec<ast::ConditionalElement>) -> Vec<types::ConditionalElement> {
+    list.into_iter().map(convert_conditional_element).collect()
+}
+
 fn convert_conditional_element(elem: ast::ConditionalElement) -> types::ConditionalElement {
     types::ConditionalElement {
         connector: convert_connector(elem.connector),
         pipeline: convert_pipeline(elem.pipeline),
+        background: elem.background,
+        span: convert_span(elem.span),
     }
 }
 
@@ -64,16 +271,81 @@ fn convert_connector(conn: ast::Connector) -> types::Connector {
         ast::Connector::None => types::Connector::None,
         ast::Connector::And => types::Connector::And,
         ast::Connector::Or => types::Connector::Or,
+        ast::Connector::Sequential => types::Connector::Sequential,
     }
 }
 
 fn convert_pipeline(pipeline: ast::Pipeline) -> types::Pipeline {
     types::Pipeline {
-        commands: pipeline
-            .commands
-            .into_iter()
-            .map(convert_simple_command)
-            .collect(),
+        commands: pipeline.commands.into_iter().map(convert_command).collect(),
+        span: convert_span(pipeline.span),
+    }
+}
+
+fn convert_command(cmd: ast::Command) -> types::Command {
+    match cmd {
+        ast::Command::Simple(cmd) => types::Command::Simple(convert_simple_command(cmd)),
+        ast::Command::Compound(id) => types::Command::Compound(id),
+        ast::Command::LoopControl(control) => types::Command::LoopControl(types::LoopControl {
+            kind: match control.kind {
+                ast::LoopControlKind::Break => types::LoopControlKind::Break,
+                ast::LoopControlKind::Continue => types::LoopControlKind::Continue,
+            },
+            depth: control.depth,
+        }),
+        ast::Command::FunctionDef(id) => types::Command::FunctionDef(id),
+    }
+}
+
+fn convert_compound_command(cmd: ast::CompoundCommand) -> types::CompoundCommand {
+    types::CompoundCommand {
+        kind: convert_compound_kind(cmd.kind),
+        redirects: cmd.redirects.into_iter().map(convert_redirect).collect(),
+        span: convert_span(cmd.span),
+    }
+}
+
+fn convert_compound_kind(kind: ast::CompoundKind) -> types::CompoundKind {
+    match kind {
+        ast::CompoundKind::If(clause) => types::CompoundKind::IfClause(types::IfClause {
+            branches: clause
+                .branches
+                .into_iter()
+                .map(|branch| types::IfBranch {
+                    condition: convert_list(branch.condition),
+                    body: convert_list(branch.body),
+                })
+                .collect(),
+            else_body: clause.else_body.map(convert_list),
+        }),
+        ast::CompoundKind::Loop(clause) => types::CompoundKind::LoopClause(types::LoopClause {
+            kind: match clause.kind {
+                ast::LoopKind::While => types::LoopKind::While,
+                ast::LoopKind::Until => types::LoopKind::Until,
+            },
+            condition: convert_list(clause.condition),
+            body: convert_list(clause.body),
+        }),
+        ast::CompoundKind::For(clause) => types::CompoundKind::ForClause(types::ForClause {
+            variable: clause.variable,
+            words: clause
+                .words
+                .map(|words| words.into_iter().map(convert_parsed_word).collect()),
+            body: convert_list(clause.body),
+        }),
+        ast::CompoundKind::Case(clause) => types::CompoundKind::CaseClause(types::CaseClause {
+            word: convert_parsed_word(clause.word),
+            arms: clause
+                .arms
+                .into_iter()
+                .map(|arm| types::CaseArm {
+                    patterns: arm.patterns.into_iter().map(convert_parsed_word).collect(),
+                    body: convert_list(arm.body),
+                })
+                .collect(),
+        }),
+        ast::CompoundKind::BraceGroup(body) => types::CompoundKind::BraceGroup(convert_list(body)),
+        ast::CompoundKind::Subshell(body) => types::CompoundKind::Subshell(convert_list(body)),
     }
 }
 
@@ -81,16 +353,54 @@ fn convert_simple_command(cmd: ast::SimpleCommand) -> types::SimpleCommand {
     types::SimpleCommand {
         args: cmd.args.into_iter().map(convert_parsed_word).collect(),
         redirects: cmd.redirects.into_iter().map(convert_redirect).collect(),
+        span: convert_span(cmd.span),
     }
 }
 
 fn convert_parsed_word(word: ast::ParsedWord) -> types::ParsedWord {
     types::ParsedWord {
-        segments: word
-            .segments
+        parts: word
+            .parts
             .into_iter()
-            .map(convert_word_segment)
+            .map(|part| types::WordPart {
+                segment: convert_word_segment(part.segment),
+                quoted: part.quoted,
+            })
             .collect(),
+        span: convert_span(word.span),
+    }
+}
+
+fn convert_highlight_kind(kind: highlight::HighlightKind) -> types::HighlightKind {
+    match kind {
+        highlight::HighlightKind::Command => types::HighlightKind::Command,
+        highlight::HighlightKind::Keyword => types::HighlightKind::Keyword,
+        highlight::HighlightKind::Argument => types::HighlightKind::Argument,
+        highlight::HighlightKind::Option => types::HighlightKind::Option,
+        highlight::HighlightKind::Quoted => types::HighlightKind::Quoted,
+        highlight::HighlightKind::Variable => types::HighlightKind::Variable,
+        highlight::HighlightKind::Substitution => types::HighlightKind::Substitution,
+        highlight::HighlightKind::Operator => types::HighlightKind::Operator,
+        highlight::HighlightKind::Redirect => types::HighlightKind::Redirect,
+        highlight::HighlightKind::Comment => types::HighlightKind::Comment,
+        highlight::HighlightKind::Error => types::HighlightKind::Error,
+    }
+}
+
+fn convert_candidate_kind(kind: completer::CandidateKind) -> types::CandidateKind {
+    match kind {
+        completer::CandidateKind::Command => types::CandidateKind::Command,
+        completer::CandidateKind::Alias => types::CandidateKind::Alias,
+        completer::CandidateKind::File => types::CandidateKind::File,
+        completer::CandidateKind::Directory => types::CandidateKind::Directory,
+        completer::CandidateKind::Variable => types::CandidateKind::Variable,
+    }
+}
+
+fn convert_span(span: ast::Span) -> types::Span {
+    types::Span {
+        start: span.start,
+        end: span.end,
     }
 }
 
@@ -99,16 +409,78 @@ fn convert_word_segment(seg: ast::WordSegment) -> types::WordSegment {
         ast::WordSegment::Literal(s) => types::WordSegment::Literal(s),
         ast::WordSegment::EnvVar(env_ref) => types::WordSegment::EnvVar(types::EnvRef {
             name: env_ref.name,
-            default_value: env_ref.default,
+            op: env_ref.op.map(convert_param_op),
+            substitutions: env_ref.substitutions,
         }),
         ast::WordSegment::CommandSubst(id) => types::WordSegment::CommandSubst(id),
+        ast::WordSegment::Positional(n) => types::WordSegment::Positional(n),
+        ast::WordSegment::Special(param) => types::WordSegment::Special(match param {
+            ast::SpecialParam::ExitStatus => types::SpecialParam::ExitStatus,
+            ast::SpecialParam::Pid => types::SpecialParam::Pid,
+            ast::SpecialParam::LastBackgroundPid => types::SpecialParam::LastBackgroundPid,
+            ast::SpecialParam::ArgCount => types::SpecialParam::ArgCount,
+            ast::SpecialParam::AllArgs => types::SpecialParam::AllArgs,
+            ast::SpecialParam::AllArgsJoined => types::SpecialParam::AllArgsJoined,
+        }),
+        ast::WordSegment::Tilde(prefix) => types::WordSegment::Tilde(prefix),
+        ast::WordSegment::Arith(expr) => types::WordSegment::Arith(expr),
+    }
+}
+
+fn convert_param_op(op: ast::ParamOp) -> types::ParamOp {
+    let word = |colon, word| types::ParamWord { colon, word };
+    match op {
+        ast::ParamOp::Length => types::ParamOp::Length,
+        ast::ParamOp::Default { colon, word: w } => types::ParamOp::Default(word(colon, w)),
+        ast::ParamOp::Assign { colon, word: w } => types::ParamOp::Assign(word(colon, w)),
+        ast::ParamOp::Error { colon, word: w } => types::ParamOp::Error(word(colon, w)),
+        ast::ParamOp::Alternative { colon, word: w } => types::ParamOp::Alternative(word(colon, w)),
+        ast::ParamOp::RemovePrefix { pattern, longest } => {
+            types::ParamOp::RemovePrefix(types::ParamTrim { pattern, longest })
+        }
+        ast::ParamOp::RemoveSuffix { pattern, longest } => {
+            types::ParamOp::RemoveSuffix(types::ParamTrim { pattern, longest })
+        }
+        ast::ParamOp::Replace {
+            pattern,
+            replacement,
+            mode,
+        } => types::ParamOp::Replace(types::ParamReplace {
+            pattern,
+            replacement,
+            mode: match mode {
+                ast::ReplaceMode::First => types::ReplaceMode::First,
+                ast::ReplaceMode::All => types::ReplaceMode::All,
+                ast::ReplaceMode::Prefix => types::ReplaceMode::Prefix,
+                ast::ReplaceMode::Suffix => types::ReplaceMode::Suffix,
+            },
+        }),
+        ast::ParamOp::Substring { offset, length } => {
+            types::ParamOp::Substring(types::ParamSubstring { offset, length })
+        }
+        ast::ParamOp::CaseMod {
+            upper,
+            all,
+            pattern,
+        } => types::ParamOp::CaseMod(types::ParamCase {
+            upper,
+            all,
+            pattern,
+        }),
     }
 }
 
 fn convert_redirect(redirect: ast::Redirect) -> types::Redirect {
     types::Redirect {
+        fd: redirect.fd,
         kind: convert_redirect_kind(redirect.kind),
-        target: convert_parsed_word(redirect.target),
+        target: match redirect.target {
+            ast::RedirectTarget::Word(word) => {
+                types::RedirectTarget::Word(convert_parsed_word(word))
+            }
+            ast::RedirectTarget::Fd(fd) => types::RedirectTarget::Fd(fd),
+        },
+        span: convert_span(redirect.span),
     }
 }
 
@@ -117,6 +489,9 @@ fn convert_redirect_kind(kind: ast::RedirectKind) -> types::RedirectKind {
         ast::RedirectKind::Stdin => types::RedirectKind::Stdin,
         ast::RedirectKind::Stdout => types::RedirectKind::Stdout,
         ast::RedirectKind::StdoutAppend => types::RedirectKind::StdoutAppend,
+        ast::RedirectKind::HereDoc => types::RedirectKind::HereDoc,
+        ast::RedirectKind::HereString => types::RedirectKind::HereString,
+        ast::RedirectKind::ReadWrite => types::RedirectKind::ReadWrite,
     }
 }
 
@@ -129,24 +504,268 @@ fn convert_command_substitution(subst: ast::CommandSubstitution) -> types::Comma
 
 // WIT 型から Rust 型への変換
 
+fn convert_from_wit_parse_result(result: types::ParseResult) -> ast::ParseResult {
+    ast::ParseResult {
+        elements: convert_from_wit_list(result.elements),
+        substitutions: result
+            .substitutions
+            .into_iter()
+            .map(|subst| ast::CommandSubstitution {
+                id: subst.id,
+                input: subst.input,
+            })
+            .collect(),
+        compounds: result
+            .compounds
+            .into_iter()
+            .map(convert_from_wit_compound_command)
+            .collect(),
+        functions: result
+            .functions
+            .into_iter()
+            .map(|def| ast::FunctionDef {
+                name: def.name,
+                body: def.body,
+                span: convert_from_wit_span(def.span),
+            })
+            .collect(),
+    }
+}
+
+fn convert_from_wit_list(list: Vec<types::ConditionalElement>) -> Vec<ast::ConditionalElement> {
+    list.into_iter()
+        .map(|elem| ast::ConditionalElement {
+            connector: match elem.connector {
+                types::Connector::None => ast::Connector::None,
+                types::Connector::And => ast::Connector::And,
+                types::Connector::Or => ast::Connector::Or,
+                types::Connector::Sequential => ast::Connector::Sequential,
+            },
+            pipeline: ast::Pipeline {
+                commands: elem
+                    .pipeline
+                    .commands
+                    .into_iter()
+                    .map(convert_from_wit_command)
+                    .collect(),
+                span: convert_from_wit_span(elem.pipeline.span),
+            },
+            background: elem.background,
+            span: convert_from_wit_span(elem.span),
+        })
+        .collect()
+}
+
+fn convert_from_wit_command(cmd: types::Command) -> ast::Command {
+    match cmd {
+        types::Command::Simple(cmd) => ast::Command::Simple(ast::SimpleCommand {
+            args: cmd.args.into_iter().map(convert_from_wit_word).collect(),
+            redirects: cmd
+                .redirects
+                .into_iter()
+                .map(convert_from_wit_redirect)
+                .collect(),
+            span: convert_from_wit_span(cmd.span),
+        }),
+        types::Command::Compound(id) => ast::Command::Compound(id),
+        types::Command::LoopControl(control) => ast::Command::LoopControl(ast::LoopControl {
+            kind: match control.kind {
+                types::LoopControlKind::Break => ast::LoopControlKind::Break,
+                types::LoopControlKind::Continue => ast::LoopControlKind::Continue,
+            },
+            depth: control.depth,
+        }),
+        types::Command::FunctionDef(id) => ast::Command::FunctionDef(id),
+    }
+}
+
+fn convert_from_wit_compound_command(cmd: types::CompoundCommand) -> ast::CompoundCommand {
+    let kind = match cmd.kind {
+        types::CompoundKind::IfClause(clause) => ast::CompoundKind::If(ast::IfClause {
+            branches: clause
+                .branches
+                .into_iter()
+                .map(|branch| ast::IfBranch {
+                    condition: convert_from_wit_list(branch.condition),
+                    body: convert_from_wit_list(branch.body),
+                })
+                .collect(),
+            else_body: clause.else_body.map(convert_from_wit_list),
+        }),
+        types::CompoundKind::LoopClause(clause) => ast::CompoundKind::Loop(ast::LoopClause {
+            kind: match clause.kind {
+                types::LoopKind::While => ast::LoopKind::While,
+                types::LoopKind::Until => ast::LoopKind::Until,
+            },
+            condition: convert_from_wit_list(clause.condition),
+            body: convert_from_wit_list(clause.body),
+        }),
+        types::CompoundKind::ForClause(clause) => ast::CompoundKind::For(ast::ForClause {
+            variable: clause.variable,
+            words: clause
+                .words
+                .map(|words| words.into_iter().map(convert_from_wit_word).collect()),
+            body: convert_from_wit_list(clause.body),
+        }),
+        types::CompoundKind::CaseClause(clause) => ast::CompoundKind::Case(ast::CaseClause {
+            word: convert_from_wit_word(clause.word),
+            arms: clause
+                .arms
+                .into_iter()
+                .map(|arm| ast::CaseArm {
+                    patterns: arm
+                        .patterns
+                        .into_iter()
+                        .map(convert_from_wit_word)
+                        .collect(),
+                    body: convert_from_wit_list(arm.body),
+                })
+                .collect(),
+        }),
+        types::CompoundKind::BraceGroup(body) => {
+            ast::CompoundKind::BraceGroup(convert_from_wit_list(body))
+        }
+        types::CompoundKind::Subshell(body) => {
+            ast::CompoundKind::Subshell(convert_from_wit_list(body))
+        }
+    };
+    ast::CompoundCommand {
+        kind,
+        redirects: cmd
+            .redirects
+            .into_iter()
+            .map(convert_from_wit_redirect)
+            .collect(),
+        span: convert_from_wit_span(cmd.span),
+    }
+}
+
+fn convert_from_wit_redirect(redirect: types::Redirect) -> ast::Redirect {
+    ast::Redirect {
+        fd: redirect.fd,
+        kind: match redirect.kind {
+            types::RedirectKind::Stdin => ast::RedirectKind::Stdin,
+            types::RedirectKind::Stdout => ast::RedirectKind::Stdout,
+            types::RedirectKind::StdoutAppend => ast::RedirectKind::StdoutAppend,
+            types::RedirectKind::HereDoc => ast::RedirectKind::HereDoc,
+            types::RedirectKind::HereString => ast::RedirectKind::HereString,
+            types::RedirectKind::ReadWrite => ast::RedirectKind::ReadWrite,
+        },
+        target: match redirect.target {
+            types::RedirectTarget::Word(word) => {
+                ast::RedirectTarget::Word(convert_from_wit_word(word))
+            }
+            types::RedirectTarget::Fd(fd) => ast::RedirectTarget::Fd(fd),
+        },
+        span: convert_from_wit_span(redirect.span),
+    }
+}
+
+fn convert_from_wit_span(span: types::Span) -> ast::Span {
+    ast::Span::new(span.start, span.end)
+}
+
 fn convert_from_wit_word(word: types::ParsedWord) -> ast::ParsedWord {
     ast::ParsedWord {
-        segments: word
-            .segments
+        parts: word
+            .parts
             .into_iter()
-            .map(convert_from_wit_segment)
+            .map(|part| ast::WordPart {
+                segment: convert_from_wit_segment(part.segment),
+                quoted: part.quoted,
+            })
             .collect(),
+        span: convert_from_wit_span(word.span),
     }
 }
 
 fn convert_from_wit_segment(seg: types::WordSegment) -> ast::WordSegment {
     match seg {
         types::WordSegment::Literal(s) => ast::WordSegment::Literal(s),
-        types::WordSegment::EnvVar(env_ref) => ast::WordSegment::EnvVar(ast::EnvRef {
-            name: env_ref.name,
-            default: env_ref.default_value,
-        }),
+        types::WordSegment::EnvVar(env_ref) => {
+            ast::WordSegment::EnvVar(convert_from_wit_env_ref(env_ref))
+        }
         types::WordSegment::CommandSubst(id) => ast::WordSegment::CommandSubst(id),
+        types::WordSegment::Positional(n) => ast::WordSegment::Positional(n),
+        types::WordSegment::Special(param) => ast::WordSegment::Special(match param {
+            types::SpecialParam::ExitStatus => ast::SpecialParam::ExitStatus,
+            types::SpecialParam::Pid => ast::SpecialParam::Pid,
+            types::SpecialParam::LastBackgroundPid => ast::SpecialParam::LastBackgroundPid,
+            types::SpecialParam::ArgCount => ast::SpecialParam::ArgCount,
+            types::SpecialParam::AllArgs => ast::SpecialParam::AllArgs,
+            types::SpecialParam::AllArgsJoined => ast::SpecialParam::AllArgsJoined,
+        }),
+        types::WordSegment::Tilde(prefix) => ast::WordSegment::Tilde(prefix),
+        types::WordSegment::Arith(expr) => ast::WordSegment::Arith(expr),
+    }
+}
+
+fn convert_expand_context(ctx: types::ExpandContext) -> expander::ExpandContext {
+    expander::ExpandContext {
+        env: ctx.env.into_iter().collect(),
+        arg0: ctx.arg0,
+        positional: ctx.positional,
+        last_status: ctx.last_status,
+        pid: ctx.pid,
+        last_background_pid: ctx.last_background_pid,
+        substitutions: ctx.substitutions.into_iter().collect(),
+    }
+}
+
+fn convert_from_wit_env_ref(env_ref: types::EnvRef) -> ast::EnvRef {
+    ast::EnvRef {
+        name: env_ref.name,
+        op: env_ref.op.map(convert_from_wit_param_op),
+        substitutions: env_ref.substitutions,
+    }
+}
+
+fn convert_from_wit_param_op(op: types::ParamOp) -> ast::ParamOp {
+    match op {
+        types::ParamOp::Length => ast::ParamOp::Length,
+        types::ParamOp::Default(p) => ast::ParamOp::Default {
+            colon: p.colon,
+            word: p.word,
+        },
+        types::ParamOp::Assign(p) => ast::ParamOp::Assign {
+            colon: p.colon,
+            word: p.word,
+        },
+        types::ParamOp::Error(p) => ast::ParamOp::Error {
+            colon: p.colon,
+            word: p.word,
+        },
+        types::ParamOp::Alternative(p) => ast::ParamOp::Alternative {
+            colon: p.colon,
+            word: p.word,
+        },
+        types::ParamOp::RemovePrefix(p) => ast::ParamOp::RemovePrefix {
+            pattern: p.pattern,
+            longest: p.longest,
+        },
+        types::ParamOp::RemoveSuffix(p) => ast::ParamOp::RemoveSuffix {
+            pattern: p.pattern,
+            longest: p.longest,
+        },
+        types::ParamOp::Replace(p) => ast::ParamOp::Replace {
+            pattern: p.pattern,
+            replacement: p.replacement,
+            mode: match p.mode {
+                types::ReplaceMode::First => ast::ReplaceMode::First,
+                types::ReplaceMode::All => ast::ReplaceMode::All,
+                types::ReplaceMode::Prefix => ast::ReplaceMode::Prefix,
+                types::ReplaceMode::Suffix => ast::ReplaceMode::Suffix,
+            },
+        },
+        types::ParamOp::Substring(p) => ast::ParamOp::Substring {
+            offset: p.offset,
+            length: p.length,
+        },
+        types::ParamOp::CaseMod(p) => ast::ParamOp::CaseMod {
+            upper: p.upper,
+            all: p.all,
+            pattern: p.pattern,
+        },
     }
 }
 
@@ -1,152 +1,832 @@
 //! シェルのパーサー
 
+use std::collections::HashMap;
+
+use crate::arith;
 use crate::ast::*;
-use crate::lexer::{tokenize, Token};
+use crate::brace;
+use crate::lexer::{
+    arith_end, double_quoted, param_end, reserved_word, scan_line, skip_unit, tokenize_spanned,
+    HereDoc, ReservedWord, Scan, SpannedToken, Token,
+};
 
 /// パーサー
 pub struct Parser {
     tokens: Vec<Token>,
+    /// 各トークンが直前のトークンと空白を挟まずに続いているか
+    joined: Vec<bool>,
+    /// 各トークンの入力中の範囲
+    spans: Vec<Span>,
     pos: usize,
     substitutions: Vec<CommandSubstitution>,
     next_subst_id: u32,
+    compounds: Vec<CompoundCommand>,
+    functions: Vec<FunctionDef>,
+    /// パース中の複合コマンドを閉じる予約語（内側が末尾）
+    open: Vec<&'static str>,
+    /// エイリアス（名前 → 値）
+    aliases: HashMap<String, String>,
+    /// 展開中のエイリアスと、その値のトークンの終わりの位置（再帰展開の防止）
+    expanding: Vec<(String, usize)>,
+    /// 値が空白で終わるエイリアスの直後のワードの位置（このワードもエイリアス展開する）
+    alias_next: Option<usize>,
+    /// トップレベルの改行でパースを止める（1 コマンドずつ実行する場合）
+    single_line: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self {
-            tokens,
+        Self::from_spanned(
+            tokens
+                .into_iter()
+                .map(|token| SpannedToken {
+                    token,
+                    span: Span::default(),
+                    joined: false,
+                })
+                .collect(),
+        )
+    }
+
+    /// tokenize_spanned の結果からパーサーを作成
+    pub fn from_spanned(tokens: Vec<SpannedToken>) -> Self {
+        let mut parser = Self {
+            tokens: Vec::with_capacity(tokens.len()),
+            joined: Vec::with_capacity(tokens.len()),
+            spans: Vec::with_capacity(tokens.len()),
             pos: 0,
             substitutions: Vec::new(),
             next_subst_id: 0,
+            compounds: Vec::new(),
+            functions: Vec::new(),
+            open: Vec::new(),
+            aliases: HashMap::new(),
+            expanding: Vec::new(),
+            alias_next: None,
+            single_line: false,
+        };
+        for tok in tokens {
+            parser.tokens.push(tok.token);
+            parser.joined.push(tok.joined);
+            parser.spans.push(tok.span);
         }
+        parser
     }
 
-    /// 入力文字列をパース
+    /// エイリアスなしで入力文字列をパース
+    #[cfg(test)]
     pub fn parse_input(input: &str) -> Result<ParseResult, ParseError> {
-        let tokens = tokenize(input).map_err(|e| ParseError::new(e, 0))?;
-        let mut parser = Parser::new(tokens);
+        Self::parse_input_with_aliases(input, HashMap::new())
+    }
+
+    /// コマンド位置のエイリアスを展開しながら入力文字列をパース
+    pub fn parse_input_with_aliases(
+        input: &str,
+        aliases: HashMap<String, String>,
+    ) -> Result<ParseResult, ParseError> {
+        let tokens = tokenize_spanned(input)?;
+        let mut parser = Parser::from_spanned(tokens);
+        parser.aliases = aliases;
         parser.parse()
     }
 
+    /// 入力の先頭の完全なコマンド（トップレベルの 1 行）だけをパースし、残りの入力とともに返す
+    /// 前の行で定義したエイリアスを次の行に反映するため、スクリプトは 1 行ずつパースして実行する
+    /// トークン化はコマンドが閉じるまで行単位で進める（後ろの行のエラーは先頭のコマンドを妨げない）
+    pub fn parse_next_with_aliases(
+        input: &str,
+        aliases: HashMap<String, String>,
+    ) -> Result<(ParseResult, &str), ParseError> {
+        let mut scan = Scan::default();
+        let mut end = 0;
+        // 複合コマンドなどが閉じていなければ、読む行数を倍にしてパースし直す
+        let mut lines = 1;
+        loop {
+            let mut error = None;
+            for _ in 0..lines {
+                if end == input.len() {
+                    break;
+                }
+                let count = scan.tokens.len();
+                match scan_line(input, &mut scan, end) {
+                    Ok(next) => end = next,
+                    Err(e) => {
+                        scan.tokens.truncate(count);
+                        error = Some(e);
+                        break;
+                    }
+                }
+            }
+
+            let mut parser = Parser::from_spanned(scan.tokens.clone());
+            parser.aliases = aliases.clone();
+            parser.single_line = true;
+            let result = match parser.parse() {
+                Err(e) if e.incomplete.is_some() => match error {
+                    Some(error) => return Err(error),
+                    None if end < input.len() => {
+                        lines *= 2;
+                        continue;
+                    }
+                    None => return Err(e),
+                },
+                result => result?,
+            };
+
+            // ヒアドキュメントの本文は改行の後にあるため、次のトークンの位置から再開する
+            let rest = match parser.spans.get(parser.pos) {
+                Some(span) => &input[span.start as usize..],
+                // 空行とコメントだけなら次の行に進む
+                None if scan.tokens.iter().all(|tok| tok.token == Token::Newline) => match error {
+                    Some(error) => return Err(error),
+                    None if end < input.len() => {
+                        scan.tokens.clear();
+                        continue;
+                    }
+                    None => "",
+                },
+                None => &input[end..],
+            };
+            return Ok((result, rest));
+        }
+    }
+
+    /// 展開演算子のオペランドなど、単独のワードをパース
+    #[cfg(test)]
+    pub fn parse_word_text(word: &str) -> Result<ParsedWord, ParseError> {
+        Parser::new(Vec::new()).parse_operand(word)
+    }
+
+    /// 展開演算子のオペランドを出現順にパース
+    /// コマンド置換にはパース時と同じ ID（env_ref.substitutions の先頭からの連番）を振る
+    pub fn parse_operands(env_ref: &EnvRef) -> Result<Vec<ParsedWord>, ParseError> {
+        let mut parser = Parser::new(Vec::new());
+        parser.next_subst_id = env_ref.substitutions.first().copied().unwrap_or(0);
+        env_ref
+            .op
+            .iter()
+            .flat_map(ParamOp::operands)
+            .map(|word| parser.parse_operand(word))
+            .collect()
+    }
+
+    /// 単独のワードをパース
+    /// クォートと \ によるエスケープはクォートされた断片になる
+    fn parse_operand(&mut self, word: &str) -> Result<ParsedWord, ParseError> {
+        let mut result = ParsedWord::unquoted(Vec::new());
+        let mut rest = word;
+
+        while let Some(c) = rest.chars().next() {
+            let len = match c {
+                '\'' => {
+                    let end = rest[1..].find('\'').ok_or_else(|| {
+                        ParseError::new("syntax error: unexpected end of input (expected `'`)", 0)
+                    })?;
+                    result.append(ParsedWord::literal(&rest[1..end + 1]).into_quoted());
+                    end + 2
+                }
+                '"' => {
+                    let (after, tokens) = double_quoted(rest).map_err(|_| {
+                        ParseError::new("syntax error: unexpected end of input (expected `\"`)", 0)
+                    })?;
+                    for (tok, _) in tokens {
+                        // エスケープされた $ と ` はシングルクォートのトークンになる
+                        let word = match tok {
+                            Token::DoubleQuoted(s) => self.parse_word(s)?,
+                            Token::SingleQuoted(s) => ParsedWord::literal(s),
+                            _ => continue,
+                        };
+                        result.append(word.into_quoted());
+                    }
+                    rest.len() - after.len()
+                }
+                '\\' => {
+                    let len = rest[1..].chars().next().map_or(0, char::len_utf8);
+                    result.append(ParsedWord::literal(&rest[1..1 + len]).into_quoted());
+                    1 + len
+                }
+                // クォートされていない部分（${...} や $(...) の中のクォートは含める）
+                _ => {
+                    let mut end = 0;
+                    while let Some(c) = rest[end..].chars().next() {
+                        if matches!(c, '\'' | '"' | '\\') {
+                            break;
+                        }
+                        end = skip_unit(rest, end).unwrap_or(rest.len());
+                    }
+                    result.append(self.parse_word(rest[..end].to_string())?);
+                    end
+                }
+            };
+            rest = &rest[len..];
+        }
+
+        if result.parts.is_empty() {
+            return Ok(ParsedWord::literal(""));
+        }
+        Ok(result)
+    }
+
     /// パース実行
     pub fn parse(&mut self) -> Result<ParseResult, ParseError> {
         let mut result = ParseResult::new();
+        result.elements = self.parse_list()?;
+
+        // 1 行だけパースする場合は改行で止まる
+        // それ以外でリストの途中で止まった場合（対応しない fi など）はエラー
+        if self.single_line && self.check(&Token::Newline) {
+            while self.check(&Token::Newline) {
+                self.advance();
+            }
+        } else if let Some(tok) = self.peek() {
+            return Err(self.unexpected_token(tok));
+        }
+
+        result.substitutions = std::mem::take(&mut self.substitutions);
+        result.compounds = std::mem::take(&mut self.compounds);
+        result.functions = std::mem::take(&mut self.functions);
+
+        Ok(result)
+    }
+
+    /// コマンドリストをパース
+    /// 入力の終端、またはコマンド位置に現れた終端予約語（then, fi など）で止まる
+    fn parse_list(&mut self) -> Result<Vec<ConditionalElement>, ParseError> {
+        let mut elements: Vec<ConditionalElement> = Vec::new();
         let mut connector = Connector::None;
+        // 現在の AND-OR リストの先頭要素のインデックス
+        let mut and_or_start = 0;
 
         loop {
+            // 1 行だけパースする場合は、トップレベルのコマンドの後の改行で止まる
+            if self.single_line
+                && self.open.is_empty()
+                && !elements.is_empty()
+                && !matches!(connector, Connector::And | Connector::Or)
+                && self.check(&Token::Newline)
+            {
+                break;
+            }
+
             // 先頭の改行をスキップ
             while self.check(&Token::Newline) {
                 self.advance();
             }
 
-            // 空入力の場合
-            if self.is_at_end() {
+            // 空入力、またはリストの終端
+            if self.is_at_end() || self.at_list_terminator() {
+                // && / || の後にはコマンドが必要
+                if matches!(connector, Connector::And | Connector::Or) {
+                    return Err(self.unexpected_current());
+                }
                 break;
             }
 
             let pipeline = self.parse_pipeline()?;
 
-            // 空のパイプラインはスキップ
-            if pipeline.commands.is_empty() || pipeline.commands.iter().all(|c| c.is_empty()) {
-                continue;
+            if matches!(connector, Connector::None | Connector::Sequential) {
+                and_or_start = elements.len();
             }
 
-            result.elements.push(ConditionalElement {
+            elements.push(ConditionalElement {
                 connector,
+                span: pipeline.span,
                 pipeline,
+                background: false,
             });
 
-            // 接続子または改行をチェック
-            if self.check(&Token::And) {
-                self.advance();
-                connector = Connector::And;
-            } else if self.check(&Token::Or) {
-                self.advance();
-                connector = Connector::Or;
-            } else if self.check(&Token::Newline) {
-                // 改行の場合は次のコマンドへ（接続子なし）
-                connector = Connector::None;
-                // 改行は次のループでスキップされる
-            } else {
-                break;
+            // 接続子または区切り文字をチェック
+            match self.peek() {
+                Some(Token::And) => {
+                    self.advance();
+                    connector = Connector::And;
+                }
+                Some(Token::Or) => {
+                    self.advance();
+                    connector = Connector::Or;
+                }
+                Some(Token::Semicolon) => {
+                    self.advance();
+                    connector = Connector::Sequential;
+                }
+                Some(Token::Background) => {
+                    self.advance();
+                    // & は直前の AND-OR リスト全体に掛かる
+                    for elem in &mut elements[and_or_start..] {
+                        elem.background = true;
+                    }
+                    if let Some(last) = elements.last_mut() {
+                        last.span = self.span_from(last.span.start);
+                    }
+                    connector = Connector::Sequential;
+                }
+                Some(Token::Newline) => {
+                    // 改行の場合は次のコマンドへ（接続子なし）
+                    connector = Connector::None;
+                    // 改行は次のループでスキップされる
+                }
+                // それ以外のトークンの扱いは呼び出し元に任せる
+                _ => break,
             }
         }
 
-        result.substitutions = std::mem::take(&mut self.substitutions);
+        Ok(elements)
+    }
 
-        Ok(result)
+    /// 複合コマンド内のコマンドリストをパース（空は許可しない）
+    fn parse_compound_list(&mut self) -> Result<Vec<ConditionalElement>, ParseError> {
+        let list = self.parse_list()?;
+        if list.is_empty() {
+            return Err(self.unexpected_current());
+        }
+        Ok(list)
     }
 
     /// パイプラインをパース
     fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let start = self.position();
         let mut commands = Vec::new();
 
         loop {
-            let cmd = self.parse_simple_command()?;
+            let cmd = self.parse_command()?;
             commands.push(cmd);
 
             if self.check(&Token::Pipe) {
                 self.advance();
+                // | の後は改行を挟んでもよい
+                while self.check(&Token::Newline) {
+                    self.advance();
+                }
             } else {
                 break;
             }
         }
 
-        Ok(Pipeline { commands })
+        Ok(Pipeline {
+            commands,
+            span: self.span_from(start),
+        })
+    }
+
+    /// コマンド（単純コマンドまたは複合コマンド）をパース
+    fn parse_command(&mut self) -> Result<Command, ParseError> {
+        // 値が予約語で始まるエイリアスもあるので、複合コマンドより先に展開する
+        let aliased = self.expand_alias()?;
+
+        if let Some(compound) = self.parse_compound_command()? {
+            return Ok(Command::Compound(self.push_compound(compound)));
+        }
+
+        // 複合コマンドを開始しない予約語（fi など）はここでは使えない
+        if self.peek_reserved().is_some() {
+            return Err(self.unexpected_current());
+        }
+
+        if self.at_function_definition() {
+            return self.parse_function_definition();
+        }
+
+        let cmd = self.parse_simple_command()?;
+        // 値が空のエイリアスだけのコマンドは何もしない
+        if cmd.is_empty() && cmd.redirects.is_empty() && !aliased {
+            return Err(self.unexpected_current());
+        }
+        if let Some(control) = self.loop_control(&cmd)? {
+            return Ok(Command::LoopControl(control));
+        }
+        Ok(Command::Simple(cmd))
+    }
+
+    /// 現在位置から始まる複合コマンドを、後続のリダイレクトも含めてパース
+    fn parse_compound_command(&mut self) -> Result<Option<CompoundCommand>, ParseError> {
+        let start = self.position();
+        let kind = if self.check(&Token::ParenOpen) {
+            self.enclosed(")", Self::parse_subshell)?
+        } else {
+            match self.peek_reserved() {
+                Some(ReservedWord::If) => self.enclosed("fi", Self::parse_if)?,
+                Some(ReservedWord::While) => {
+                    self.enclosed("done", |p| p.parse_loop(LoopKind::While))?
+                }
+                Some(ReservedWord::Until) => {
+                    self.enclosed("done", |p| p.parse_loop(LoopKind::Until))?
+                }
+                Some(ReservedWord::For) => self.enclosed("done", Self::parse_for)?,
+                Some(ReservedWord::Case) => self.enclosed("esac", Self::parse_case)?,
+                Some(ReservedWord::LBrace) => self.enclosed("}", Self::parse_brace_group)?,
+                _ => return Ok(None),
+            }
+        };
+
+        // 複合コマンドに付いたリダイレクトは全体に適用される
+        let mut redirects = Vec::new();
+        while let Some(parsed) = self.parse_redirect()? {
+            redirects.extend(parsed);
+        }
+
+        Ok(Some(CompoundCommand {
+            kind,
+            redirects,
+            span: self.span_from(start),
+        }))
+    }
+
+    /// closer で閉じる複合コマンドの中身をパース
+    fn enclosed<T>(
+        &mut self,
+        closer: &'static str,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<T, ParseError> {
+        self.open.push(closer);
+        let result = parse(self);
+        self.open.pop();
+        result
+    }
+
+    fn push_compound(&mut self, compound: CompoundCommand) -> u32 {
+        let id = self.compounds.len() as u32;
+        self.compounds.push(compound);
+        id
+    }
+
+    /// name ( ) の形かどうか
+    fn at_function_definition(&self) -> bool {
+        matches!(
+            (
+                self.peek(),
+                self.tokens.get(self.pos + 1),
+                self.tokens.get(self.pos + 2),
+            ),
+            (
+                Some(Token::Word(_)),
+                Some(Token::ParenOpen),
+                Some(Token::ParenClose)
+            )
+        )
+    }
+
+    /// 関数定義 name() compound-command をパース
+    fn parse_function_definition(&mut self) -> Result<Command, ParseError> {
+        let start = self.position();
+        let name = match self.peek() {
+            Some(Token::Word(name)) if is_valid_name(name) => name.clone(),
+            Some(Token::Word(name)) => {
+                return Err(ParseError::new(
+                    format!("`{}': not a valid identifier", name),
+                    self.position(),
+                ))
+            }
+            _ => return Err(self.unexpected_current()),
+        };
+        self.advance(); // name
+        self.advance(); // (
+        self.advance(); // )
+        self.skip_newlines();
+
+        let body = match self.parse_compound_command()? {
+            Some(compound) => self.push_compound(compound),
+            // 本体は次の行から始まってもよい
+            None if self.is_at_end() => {
+                return Err(self.end_of_input(
+                    "syntax error: unexpected end of input (expected function body)".to_string(),
+                    Some("{"),
+                ))
+            }
+            None => return Err(self.unexpected_current()),
+        };
+
+        let id = self.functions.len() as u32;
+        self.functions.push(FunctionDef {
+            name,
+            body,
+            span: self.span_from(start),
+        });
+        Ok(Command::FunctionDef(id))
+    }
+
+    /// { list; } をパース
+    fn parse_brace_group(&mut self) -> Result<CompoundKind, ParseError> {
+        self.expect_reserved(ReservedWord::LBrace)?;
+        let body = self.parse_compound_list()?;
+        self.expect_reserved(ReservedWord::RBrace)?;
+        Ok(CompoundKind::BraceGroup(body))
+    }
+
+    /// ( list ) をパース
+    fn parse_subshell(&mut self) -> Result<CompoundKind, ParseError> {
+        self.advance(); // ( を消費
+        let body = self.parse_compound_list()?;
+        if !self.check(&Token::ParenClose) {
+            return Err(self.unexpected_current());
+        }
+        self.advance();
+        Ok(CompoundKind::Subshell(body))
+    }
+
+    /// if 文をパース
+    fn parse_if(&mut self) -> Result<CompoundKind, ParseError> {
+        self.expect_reserved(ReservedWord::If)?;
+
+        let mut branches = Vec::new();
+        let mut else_body = None;
+
+        loop {
+            let condition = self.parse_compound_list()?;
+            self.expect_reserved(ReservedWord::Then)?;
+            let body = self.parse_compound_list()?;
+            branches.push(IfBranch { condition, body });
+
+            match self.peek_reserved() {
+                Some(ReservedWord::Elif) => {
+                    self.advance();
+                }
+                Some(ReservedWord::Else) => {
+                    self.advance();
+                    else_body = Some(self.parse_compound_list()?);
+                    self.expect_reserved(ReservedWord::Fi)?;
+                    break;
+                }
+                _ => {
+                    self.expect_reserved(ReservedWord::Fi)?;
+                    break;
+                }
+            }
+        }
+
+        Ok(CompoundKind::If(IfClause {
+            branches,
+            else_body,
+        }))
     }
 
     /// 単純コマンドをパース
     fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let start = self.position();
         let mut cmd = SimpleCommand::new();
+        // 代入の後のワードもコマンド名になる
+        let mut command_position = true;
 
         while !self.is_at_end() {
-            match self.peek() {
-                // ワード（引数）
+            if command_position || self.alias_next == Some(self.pos) {
+                self.expand_alias()?;
+            }
+
+            // ワード（引数）
+            let assignment = matches!(self.peek(), Some(Token::Word(s)) if is_assignment(s));
+            // コマンド名より前の代入（A={x,y}）はブレース展開しない
+            let words = if command_position && assignment {
+                self.parse_argument()?.map(|word| vec![word])
+            } else {
+                self.parse_expanded_argument()?
+            };
+            if let Some(words) = words {
+                cmd.args.extend(words);
+                command_position = command_position && assignment;
+                continue;
+            }
+
+            // リダイレクト
+            if let Some(redirects) = self.parse_redirect()? {
+                cmd.redirects.extend(redirects);
+                continue;
+            }
+
+            // それ以外は終了
+            break;
+        }
+
+        cmd.span = self.span_from(start);
+        Ok(cmd)
+    }
+
+    /// 現在のワードがエイリアスであれば値のトークンに置き換える
+    /// 置き換えた先頭のワードも（展開中のエイリアスを除いて）繰り返し展開する
+    /// 戻り値は 1 つ以上展開したかどうか
+    fn expand_alias(&mut self) -> Result<bool, ParseError> {
+        let mut expanded = false;
+        loop {
+            // 値を読み終えたエイリアスは再び展開できる
+            let pos = self.pos;
+            self.expanding.retain(|(_, end)| *end > pos);
+
+            // クォートや展開が続かない単独のワードだけが対象（name() は関数定義）
+            let joined = self.joined.get(pos + 1).copied().unwrap_or(false);
+            let name = match (self.peek(), self.tokens.get(pos + 1)) {
+                (
+                    _,
+                    Some(
+                        Token::Word(_)
+                        | Token::SingleQuoted(_)
+                        | Token::DoubleQuoted(_)
+                        | Token::Arith(_)
+                        | Token::SubstStart,
+                    ),
+                ) if joined => return Ok(expanded),
+                (Some(Token::Word(s)), next)
+                    if reserved_word(s).is_none() && !matches!(next, Some(Token::ParenOpen)) =>
+                {
+                    s.clone()
+                }
+                _ => return Ok(expanded),
+            };
+            if self.expanding.iter().any(|(active, _)| *active == name) {
+                return Ok(expanded);
+            }
+            let Some(value) = self.aliases.get(&name).cloned() else {
+                return Ok(expanded);
+            };
+
+            let span = self.spans[pos];
+            let tokens = tokenize_spanned(&value).map_err(|e| {
+                ParseError::new(format!("alias {}: {}", name, e.message), span.start)
+            })?;
+            let count = tokens.len();
+            let joined = self.joined[pos];
+            let (tokens, mut joins): (Vec<_>, Vec<_>) = tokens
+                .into_iter()
+                .map(|tok| (tok.token, tok.joined))
+                .unzip();
+            if let Some(first) = joins.first_mut() {
+                *first = joined;
+            }
+            // 値のトークンはエイリアスを書いた位置を指す
+            self.tokens.splice(pos..pos + 1, tokens);
+            self.joined.splice(pos..pos + 1, joins);
+            self.spans
+                .splice(pos..pos + 1, std::iter::repeat_n(span, count));
+
+            // 後ろの位置を置き換えた分だけずらす
+            for (_, end) in &mut self.expanding {
+                *end = *end + count - 1;
+            }
+            self.alias_next = match self.alias_next {
+                Some(next) if next > pos => Some(next + count - 1),
+                _ => None,
+            };
+            if value.ends_with([' ', '\t']) {
+                self.alias_next = Some(pos + count);
+            }
+            self.expanding.push((name, pos + count));
+            expanded = true;
+        }
+    }
+
+    /// 現在のトークンがリダイレクト演算子であればパースして返す
+    /// &> は >file 2>&1 に展開されるため、複数のリダイレクトを返すことがある
+    fn parse_redirect(&mut self) -> Result<Option<Vec<Redirect>>, ParseError> {
+        let start = self.position();
+        let io_number = match self.peek() {
+            Some(Token::IoNumber(n)) => Some(*n),
+            _ => None,
+        };
+        let op_pos = self.pos + io_number.is_some() as usize;
+
+        let (kind, dup) = match self.tokens.get(op_pos) {
+            // 出力リダイレクト
+            Some(Token::RedirectOut) => (RedirectKind::Stdout, false),
+            // 追記リダイレクト
+            Some(Token::RedirectAppend) => (RedirectKind::StdoutAppend, false),
+            // 入力リダイレクト
+            Some(Token::RedirectIn) => (RedirectKind::Stdin, false),
+            // 読み書きリダイレクト
+            Some(Token::RedirectReadWrite) => (RedirectKind::ReadWrite, false),
+            // ヒアストリング
+            Some(Token::HereString) => (RedirectKind::HereString, false),
+            // ファイルディスクリプタの複製
+            Some(Token::DupOut) => (RedirectKind::Stdout, true),
+            Some(Token::DupIn) => (RedirectKind::Stdin, true),
+            // 標準出力と標準エラー出力の両方
+            Some(Token::RedirectAll) if io_number.is_none() => {
+                self.advance();
+                let target = self.parse_redirect_target()?;
+                let span = self.span_from(start);
+                return Ok(Some(vec![
+                    Redirect {
+                        fd: 1,
+                        kind: RedirectKind::Stdout,
+                        target: RedirectTarget::Word(target),
+                        span,
+                    },
+                    Redirect {
+                        fd: 2,
+                        kind: RedirectKind::Stdout,
+                        target: RedirectTarget::Fd(1),
+                        span,
+                    },
+                ]));
+            }
+            // ヒアドキュメント（区切り文字がクォートされていれば本文を展開しない）
+            Some(Token::HereDoc(doc)) => {
+                let doc = doc.clone();
+                let mut target = if doc.quoted {
+                    ParsedWord::literal(doc.body)
+                } else {
+                    self.parse_here_doc_body(&doc.body)?
+                };
+                // 本文の位置は持たないので <<DELIM を指す
+                target.span = self.spans[op_pos];
+                self.pos = op_pos + 1;
+                return Ok(Some(vec![Redirect {
+                    fd: io_number.unwrap_or(0),
+                    kind: RedirectKind::HereDoc,
+                    target: RedirectTarget::Word(target),
+                    span: self.span_from(start),
+                }]));
+            }
+            _ => return Ok(None),
+        };
+        self.pos = op_pos + 1;
+
+        let fd = io_number.unwrap_or_else(|| kind.default_fd());
+        let target = if dup {
+            RedirectTarget::Fd(self.parse_fd()?)
+        } else {
+            RedirectTarget::Word(self.parse_redirect_target()?)
+        };
+        Ok(Some(vec![Redirect {
+            fd,
+            kind,
+            target,
+            span: self.span_from(start),
+        }]))
+    }
+
+    /// 複製元のファイルディスクリプタをパース
+    fn parse_fd(&mut self) -> Result<u32, ParseError> {
+        match self.peek() {
+            Some(Token::Word(s)) if s.chars().all(|c| c.is_ascii_digit()) => {
+                let fd = s.parse().map_err(|_| {
+                    ParseError::new(format!("{}: bad file descriptor", s), self.position())
+                })?;
+                self.advance();
+                Ok(fd)
+            }
+            _ => Err(ParseError::new("Expected file descriptor", self.position())),
+        }
+    }
+
+    /// 現在のトークンがワードであれば、ブレース展開してからパースして返す
+    fn parse_expanded_argument(&mut self) -> Result<Option<Vec<ParsedWord>>, ParseError> {
+        self.parse_word_parts(true)
+    }
+
+    /// 現在のトークンがワードであればパースして返す
+    fn parse_argument(&mut self) -> Result<Option<ParsedWord>, ParseError> {
+        Ok(self
+            .parse_word_parts(false)?
+            .and_then(|words| words.into_iter().next()))
+    }
+
+    /// 空白を挟まずに続くワードの断片を 1 つのワードとしてパース
+    /// `brace` が真ならクォートされていない断片をブレース展開し、組み合わせをすべて返す
+    fn parse_word_parts(&mut self, brace: bool) -> Result<Option<Vec<ParsedWord>>, ParseError> {
+        let start = self.position();
+        let mut words: Vec<ParsedWord> = Vec::new();
+        let mut first = true;
+
+        while first || self.is_joined() {
+            let alternatives = match self.peek() {
+                // 通常のワード（チルダ展開はワードの先頭でのみ行う）
                 Some(Token::Word(s)) => {
-                    let word = self.parse_word(s.clone())?;
-                    cmd.args.push(word);
+                    let s = s.clone();
+                    let texts = if brace {
+                        brace::expand(&s).map_err(|e| ParseError::new(e.to_string(), start))?
+                    } else {
+                        vec![s]
+                    };
+                    let words = texts
+                        .into_iter()
+                        .map(|text| {
+                            if first {
+                                self.parse_unquoted_word(text)
+                            } else {
+                                self.parse_word(text)
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
                     self.advance();
+                    words
                 }
                 // シングルクォート（展開なし）
                 Some(Token::SingleQuoted(s)) => {
-                    cmd.args.push(ParsedWord::literal(s.clone()));
+                    let word = ParsedWord::literal(s.clone()).into_quoted();
                     self.advance();
+                    vec![word]
                 }
                 // ダブルクォート（展開あり）
                 Some(Token::DoubleQuoted(s)) => {
-                    let word = self.parse_word(s.clone())?;
-                    cmd.args.push(word);
-                    self.advance();
-                }
-                // 出力リダイレクト
-                Some(Token::RedirectOut) => {
-                    self.advance();
-                    let target = self.parse_redirect_target()?;
-                    cmd.redirects.push(Redirect {
-                        kind: RedirectKind::Stdout,
-                        target,
-                    });
-                }
-                // 追記リダイレクト
-                Some(Token::RedirectAppend) => {
+                    let word = self.parse_word(s.clone())?.into_quoted();
                     self.advance();
-                    let target = self.parse_redirect_target()?;
-                    cmd.redirects.push(Redirect {
-                        kind: RedirectKind::StdoutAppend,
-                        target,
-                    });
+                    vec![word]
                 }
-                // 入力リダイレクト
-                Some(Token::RedirectIn) => {
+                // 算術式展開
+                Some(Token::Arith(expr)) => {
+                    let expr = expr.clone();
+                    self.check_arith(&expr)?;
                     self.advance();
-                    let target = self.parse_redirect_target()?;
-                    cmd.redirects.push(Redirect {
-                        kind: RedirectKind::Stdin,
-                        target,
-                    });
+                    vec![ParsedWord::unquoted(vec![WordSegment::Arith(expr)])]
                 }
-                // コマンド置換開始
-                // レキサーは空白でトークンを分割するため、$( は常に新しいワードとして扱う
+                // コマンド置換
                 Some(Token::SubstStart) => {
                     self.advance();
                     let subst_input = self.parse_command_substitution()?;
@@ -156,84 +836,324 @@ impl Parser {
                         id,
                         input: subst_input,
                     });
-
-                    // 置換を新しいワードとして追加
-                    cmd.args.push(ParsedWord {
-                        segments: vec![WordSegment::CommandSubst(id)],
-                    });
+                    vec![ParsedWord::unquoted(vec![WordSegment::CommandSubst(id)])]
                 }
-                // 改行は終了
-                Some(Token::Newline) => break,
-                // それ以外は終了
                 _ => break,
-            }
+            };
+
+            words = if first {
+                alternatives
+            } else if words.len() * alternatives.len() > brace::MAX_WORDS {
+                return Err(ParseError::new(brace::TooManyWords.to_string(), start));
+            } else {
+                words
+                    .iter()
+                    .flat_map(|word| {
+                        alternatives.iter().map(move |alt| {
+                            let mut word = word.clone();
+                            word.append(alt.clone());
+                            word
+                        })
+                    })
+                    .collect()
+            };
+            first = false;
         }
 
-        Ok(cmd)
+        // ブレース展開で空になったクォートなしのワードは取り除く
+        if brace {
+            words.retain(|word| {
+                !word.parts.iter().all(|part| {
+                    !part.quoted && matches!(&part.segment, WordSegment::Literal(s) if s.is_empty())
+                })
+            });
+        }
+
+        let span = self.span_from(start);
+        for word in &mut words {
+            word.span = span;
+        }
+        Ok((!first).then_some(words))
     }
 
-    /// リダイレクトのターゲットをパース
-    fn parse_redirect_target(&mut self) -> Result<ParsedWord, ParseError> {
-        match self.peek() {
-            Some(Token::Word(s)) => {
-                let word = self.parse_word(s.clone())?;
-                self.advance();
-                Ok(word)
+    /// break / continue であればループ制御に変換
+    fn loop_control(&self, cmd: &SimpleCommand) -> Result<Option<LoopControl>, ParseError> {
+        let name = match cmd.args[..] {
+            [ParsedWord { ref parts, .. }, ..] if parts.len() == 1 => &parts[0].segment,
+            _ => return Ok(None),
+        };
+        let kind = match name {
+            WordSegment::Literal(s) if s == "break" => LoopControlKind::Break,
+            WordSegment::Literal(s) if s == "continue" => LoopControlKind::Continue,
+            _ => return Ok(None),
+        };
+        let builtin = if kind == LoopControlKind::Break {
+            "break"
+        } else {
+            "continue"
+        };
+
+        let depth = match &cmd.args[1..] {
+            [] => 1,
+            [ParsedWord { parts, .. }] => match &parts[..] {
+                [WordPart {
+                    segment: WordSegment::Literal(n),
+                    ..
+                }] => match n.parse::<u32>() {
+                    Ok(depth) if depth > 0 => depth,
+                    _ => {
+                        return Err(ParseError::new(
+                            format!("{}: {}: loop count out of range", builtin, n),
+                            cmd.args[1].span.start,
+                        ))
+                    }
+                },
+                _ => {
+                    return Err(ParseError::new(
+                        format!("{}: loop count must be a literal number", builtin),
+                        cmd.args[1].span.start,
+                    ))
+                }
+            },
+            _ => {
+                return Err(ParseError::new(
+                    format!("{}: too many arguments", builtin),
+                    cmd.args[2].span.start,
+                ))
+            }
+        };
+
+        Ok(Some(LoopControl { kind, depth }))
+    }
+
+    /// while / until ループをパース
+    fn parse_loop(&mut self, kind: LoopKind) -> Result<CompoundKind, ParseError> {
+        self.advance(); // while / until を消費
+        let condition = self.parse_compound_list()?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundKind::Loop(LoopClause {
+            kind,
+            condition,
+            body,
+        }))
+    }
+
+    /// for ループをパース
+    fn parse_for(&mut self) -> Result<CompoundKind, ParseError> {
+        self.advance(); // for を消費
+
+        let variable = match self.peek() {
+            Some(Token::Word(name)) if is_valid_name(name) => name.clone(),
+            Some(Token::Word(name)) => {
+                return Err(ParseError::new(
+                    format!("for: `{}': not a valid identifier", name),
+                    self.position(),
+                ))
+            }
+            _ => return Err(self.unexpected_current()),
+        };
+        self.advance();
+
+        // in の前には改行を置ける
+        let newline_seen = self.check(&Token::Newline);
+        self.skip_newlines();
+
+        let words = if self.peek_reserved() == Some(ReservedWord::In) {
+            self.advance();
+            let mut words = Vec::new();
+            while let Some(expanded) = self.parse_expanded_argument()? {
+                words.extend(expanded);
             }
-            Some(Token::SingleQuoted(s)) => {
-                let word = ParsedWord::literal(s.clone());
+            // ワードリストは ; または改行で終わる
+            if self.check(&Token::Semicolon) || self.check(&Token::Newline) {
                 self.advance();
-                Ok(word)
+            } else {
+                return Err(self.unexpected_current());
             }
-            Some(Token::DoubleQuoted(s)) => {
-                let word = self.parse_word(s.clone())?;
+            Some(words)
+        } else {
+            // for name; do ... の形式
+            if !newline_seen && self.check(&Token::Semicolon) {
                 self.advance();
-                Ok(word)
             }
-            _ => Err(ParseError::new(
-                "Expected redirect target",
-                self.pos as u32,
-            )),
-        }
+            None
+        };
+
+        self.skip_newlines();
+
+        let body = self.parse_do_group()?;
+        Ok(CompoundKind::For(ForClause {
+            variable,
+            words,
+            body,
+        }))
     }
 
-    /// ワード内の環境変数参照をパース
-    fn parse_word(&mut self, s: String) -> Result<ParsedWord, ParseError> {
-        let mut segments = Vec::new();
-        let mut chars = s.chars().peekable();
-        let mut current_literal = String::new();
+    /// case 文をパース
+    fn parse_case(&mut self) -> Result<CompoundKind, ParseError> {
+        self.advance(); // case を消費
 
-        while let Some(c) = chars.next() {
-            if c == '$' {
-                // リテラルをフラッシュ
-                if !current_literal.is_empty() {
-                    segments.push(WordSegment::Literal(std::mem::take(&mut current_literal)));
+        let word = match self.parse_argument()? {
+            Some(word) => word,
+            None => return Err(self.unexpected_current()),
+        };
+        self.skip_newlines();
+        self.expect_reserved(ReservedWord::In)?;
+
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.peek_reserved() == Some(ReservedWord::Esac) {
+                self.advance();
+                break;
+            }
+
+            // [(] pattern | pattern ... )
+            if self.check(&Token::ParenOpen) {
+                self.advance();
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.parse_argument()? {
+                    Some(pattern) => patterns.push(pattern),
+                    None => return Err(self.unexpected_current()),
+                }
+                if self.check(&Token::Pipe) {
+                    self.advance();
+                } else {
+                    break;
                 }
+            }
+            if !self.check(&Token::ParenClose) {
+                return Err(self.unexpected_current());
+            }
+            self.advance();
 
-                match chars.peek() {
-                    // コマンド置換 $(
-                    Some('(') => {
-                        chars.next(); // '(' を消費
-                        let mut depth = 1;
-                        let mut content = String::new();
+            // 本体は空でもよい
+            let body = self.parse_list()?;
+            arms.push(CaseArm { patterns, body });
 
-                        while let Some(c) = chars.next() {
-                            match c {
-                                '(' => {
-                                    depth += 1;
-                                    content.push(c);
-                                }
-                                ')' => {
-                                    depth -= 1;
-                                    if depth == 0 {
-                                        break;
-                                    }
+            if self.check(&Token::DoubleSemicolon) {
+                self.advance();
+            } else {
+                // ;; を省略できるのは最後の分岐のみ
+                self.expect_reserved(ReservedWord::Esac)?;
+                break;
+            }
+        }
+
+        Ok(CompoundKind::Case(CaseClause { word, arms }))
+    }
+
+    /// do ... done をパース
+    fn parse_do_group(&mut self) -> Result<Vec<ConditionalElement>, ParseError> {
+        self.expect_reserved(ReservedWord::Do)?;
+        let body = self.parse_compound_list()?;
+        self.expect_reserved(ReservedWord::Done)?;
+        Ok(body)
+    }
+
+    /// リダイレクトのターゲットをパース
+    fn parse_redirect_target(&mut self) -> Result<ParsedWord, ParseError> {
+        match self.parse_argument()? {
+            Some(word) => Ok(word),
+            None => Err(ParseError::new("Expected redirect target", self.position())),
+        }
+    }
+
+    /// クォートされていないワードをパース（チルダ展開の対象を切り出す）
+    /// チルダはワードの先頭と、代入の = の直後および : の直後でのみ展開する
+    fn parse_unquoted_word(&mut self, s: String) -> Result<ParsedWord, ParseError> {
+        if !s.contains('~') {
+            return self.parse_word(s);
+        }
+
+        let assignment = is_assignment(&s);
+        let mut segments = Vec::new();
+        for (chunk, expandable) in tilde_chunks(&s, assignment) {
+            let mut rest = chunk;
+            if let Some(tail) = chunk.strip_prefix('~').filter(|_| expandable) {
+                let end = tail
+                    .find(|c| c == '/' || (assignment && c == ':'))
+                    .unwrap_or(tail.len());
+                if is_tilde_prefix(&tail[..end]) {
+                    segments.push(WordSegment::Tilde(tail[..end].to_string()));
+                    rest = &tail[end..];
+                }
+            }
+            if rest.is_empty() {
+                continue;
+            }
+            for part in self.parse_word(rest.to_string())?.parts {
+                // 隣り合うリテラルはまとめる
+                match (segments.last_mut(), part.segment) {
+                    (Some(WordSegment::Literal(prev)), WordSegment::Literal(s)) => {
+                        prev.push_str(&s)
+                    }
+                    (_, segment) => segments.push(segment),
+                }
+            }
+        }
+
+        Ok(ParsedWord::unquoted(segments))
+    }
+
+    /// ワード内の環境変数参照をパース
+    fn parse_word(&mut self, s: String) -> Result<ParsedWord, ParseError> {
+        let mut segments = Vec::new();
+        let mut chars = s.chars().peekable();
+        let mut current_literal = String::new();
+
+        while let Some(c) = chars.next() {
+            if c == '$' {
+                // リテラルをフラッシュ
+                if !current_literal.is_empty() {
+                    segments.push(WordSegment::Literal(std::mem::take(&mut current_literal)));
+                }
+
+                // 算術式展開 $((
+                let rest: String = chars.clone().collect();
+                if let Some(end) = rest.strip_prefix("((").and_then(arith_end) {
+                    let expr = &rest[2..end + 2];
+                    self.check_arith(expr)?;
+                    segments.push(WordSegment::Arith(expr.to_string()));
+                    for _ in rest[..end + 4].chars() {
+                        chars.next();
+                    }
+                    continue;
+                }
+
+                match chars.peek() {
+                    // コマンド置換 $(
+                    Some('(') => {
+                        chars.next(); // '(' を消費
+                        let mut depth = 1;
+                        let mut content = String::new();
+
+                        while let Some(c) = chars.next() {
+                            match c {
+                                '(' => {
+                                    depth += 1;
+                                    content.push(c);
+                                }
+                                ')' => {
+                                    depth -= 1;
+                                    if depth == 0 {
+                                        break;
+                                    }
                                     content.push(c);
                                 }
                                 _ => content.push(c),
                             }
                         }
 
+                        if depth != 0 {
+                            return Err(ParseError::new(
+                                format!("$({}: missing ')'", content),
+                                self.position(),
+                            ));
+                        }
+
                         let id = self.next_subst_id;
                         self.next_subst_id += 1;
                         self.substitutions.push(CommandSubstitution {
@@ -242,42 +1162,40 @@ impl Parser {
                         });
                         segments.push(WordSegment::CommandSubst(id));
                     }
-                    // ${VAR} または ${VAR:-default}
+                    // ${VAR} と ${VAR:-default} などの展開演算子
                     Some('{') => {
-                        chars.next(); // '{' を消費
-                        let mut name = String::new();
-                        let mut default = None;
-
-                        // 変数名を取得
-                        while let Some(&c) = chars.peek() {
-                            if c == '}' || c == ':' {
-                                break;
-                            }
-                            name.push(chars.next().unwrap());
-                        }
-
-                        // デフォルト値をチェック
-                        if chars.peek() == Some(&':') {
-                            chars.next(); // ':' を消費
-                            if chars.peek() == Some(&'-') {
-                                chars.next(); // '-' を消費
-                                let mut def = String::new();
-                                while let Some(&c) = chars.peek() {
-                                    if c == '}' {
-                                        break;
-                                    }
-                                    def.push(chars.next().unwrap());
-                                }
-                                default = Some(def);
-                            }
-                        }
-
-                        // '}' を消費
-                        if chars.peek() == Some(&'}') {
+                        // クォートや入れ子を考慮して対応する } を探す
+                        let Some(end) = param_end(&rest[1..]) else {
+                            return Err(ParseError::new(
+                                format!("${}: bad substitution", rest),
+                                self.position(),
+                            ));
+                        };
+                        let content = rest[1..end + 1].to_string();
+                        for _ in rest[..end + 2].chars() {
                             chars.next();
                         }
 
-                        segments.push(WordSegment::EnvVar(EnvRef { name, default }));
+                        let mut env_ref = parse_parameter(&content).ok_or_else(|| {
+                            ParseError::new(
+                                format!("${{{}}}: bad substitution", content),
+                                self.position(),
+                            )
+                        })?;
+                        self.register_operands(&mut env_ref)?;
+                        segments.push(parameter_segment(env_ref));
+                    }
+                    // 位置パラメータ $0 - $9（$10 は ${1}0 と同じ）
+                    Some(c @ '0'..='9') => {
+                        let n = c.to_digit(10).unwrap();
+                        chars.next();
+                        segments.push(WordSegment::Positional(n));
+                    }
+                    // 特殊パラメータ $?, $$, $!, $#, $@, $*
+                    Some(&c @ ('?' | '$' | '!' | '#' | '@' | '*')) => {
+                        chars.next();
+                        let param = SpecialParam::from_char(c).unwrap();
+                        segments.push(WordSegment::Special(param));
                     }
                     // $VAR
                     Some(c) if c.is_alphanumeric() || *c == '_' => {
@@ -289,13 +1207,14 @@ impl Parser {
                                 break;
                             }
                         }
-                        segments.push(WordSegment::EnvVar(EnvRef {
-                            name,
-                            default: None,
-                        }));
+                        segments.push(WordSegment::EnvVar(EnvRef::new(name)));
                     }
-                    // リテラル $
+                    // リテラル $（フラッシュしたリテラルに続ける）
                     _ => {
+                        if let Some(WordSegment::Literal(prev)) = segments.last_mut() {
+                            current_literal = std::mem::take(prev);
+                            segments.pop();
+                        }
                         current_literal.push('$');
                     }
                 }
@@ -314,35 +1233,113 @@ impl Parser {
             segments.push(WordSegment::Literal(String::new()));
         }
 
-        Ok(ParsedWord { segments })
+        Ok(ParsedWord::unquoted(segments))
+    }
+
+    /// 区切り文字がクォートされていないヒアドキュメントの本文をパース
+    /// \ は $, `, \ の前でのみエスケープになり、\ と改行の組は取り除く。それ以外の \ はそのまま残る
+    fn parse_here_doc_body(&mut self, body: &str) -> Result<ParsedWord, ParseError> {
+        let mut word = ParsedWord::unquoted(Vec::new());
+        let mut chunk = String::new();
+        let mut chars = body.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                chunk.push(c);
+                continue;
+            }
+            match chars.clone().next() {
+                Some(next @ ('$' | '`' | '\\')) => {
+                    chars.next();
+                    if !chunk.is_empty() {
+                        word.append(self.parse_word(std::mem::take(&mut chunk))?);
+                    }
+                    word.append(ParsedWord::literal(next));
+                }
+                Some('\n') => {
+                    chars.next();
+                }
+                _ => chunk.push(c),
+            }
+        }
+        if !chunk.is_empty() || word.parts.is_empty() {
+            word.append(self.parse_word(chunk)?);
+        }
+        Ok(word)
+    }
+
+    /// 算術式の構文を検査する
+    /// 展開演算子のオペランドをパースし、中のコマンド置換をこのパーサーの置換表に登録する
+    /// 展開時は Parser::parse_operands が同じ順に同じ ID を振り直す
+    fn register_operands(&mut self, env_ref: &mut EnvRef) -> Result<(), ParseError> {
+        let first = self.next_subst_id;
+        if let Some(op) = &env_ref.op {
+            for word in op.operands() {
+                self.parse_operand(word)?;
+            }
+        }
+        env_ref.substitutions = (first..self.next_subst_id).collect();
+        Ok(())
+    }
+
+    fn check_arith(&self, expr: &str) -> Result<(), ParseError> {
+        arith::check(expr)
+            .map_err(|e| ParseError::new(format!("$(({})): {}", expr, e), self.position()))
     }
 
     /// コマンド置換の内容をパース
     fn parse_command_substitution(&mut self) -> Result<String, ParseError> {
         let mut content = String::new();
         let mut depth = 1;
+        // 次の改行の後に書き戻すヒアドキュメントの本文
+        let mut here_docs: Vec<HereDoc> = Vec::new();
 
-        while !self.is_at_end() && depth > 0 {
-            match self.peek() {
-                Some(Token::SubstStart) => {
+        loop {
+            let Some(tok) = self.peek() else {
+                return Err(ParseError::incomplete(
+                    "syntax error: unexpected end of input (expected `)`)",
+                    self.position(),
+                    Incomplete::CommandSubstitution,
+                ));
+            };
+            let text = match tok {
+                Token::SubstStart => {
                     depth += 1;
-                    content.push_str("$(");
-                    self.advance();
+                    "$(".to_string()
                 }
-                Some(Token::ParenClose) => {
+                Token::ParenOpen => {
+                    depth += 1;
+                    "(".to_string()
+                }
+                Token::ParenClose => {
                     depth -= 1;
-                    if depth > 0 {
-                        content.push(')');
+                    if depth == 0 {
+                        self.advance();
+                        break;
                     }
-                    self.advance();
+                    ")".to_string()
                 }
-                Some(tok) => {
-                    content.push_str(&token_to_string(tok));
-                    content.push(' ');
-                    self.advance();
+                Token::HereDoc(doc) => {
+                    here_docs.push(doc.clone());
+                    token_to_string(tok)
+                }
+                Token::Newline => {
+                    let mut text = "\n".to_string();
+                    for doc in here_docs.drain(..) {
+                        text.push_str(&doc.body);
+                        text.push_str(&doc.delimiter);
+                        text.push('\n');
+                    }
+                    text
                 }
-                None => break,
+                tok => token_to_string(tok),
+            };
+            // 空白を挟まずに続くトークンは元のとおりつなげる
+            if !content.is_empty() && !self.is_joined() {
+                content.push(' ');
             }
+            content.push_str(&text);
+            self.advance();
         }
 
         Ok(content.trim().to_string())
@@ -350,10 +1347,133 @@ impl Parser {
 
     // ヘルパーメソッド
 
+    fn unexpected_token(&self, tok: &Token) -> ParseError {
+        ParseError::new(
+            format!(
+                "syntax error near unexpected token `{}`",
+                token_to_string(tok)
+            ),
+            self.position(),
+        )
+    }
+
+    /// 現在位置のトークンに対する構文エラー
+    fn unexpected_current(&self) -> ParseError {
+        match self.peek() {
+            Some(tok) => self.unexpected_token(tok),
+            None => self.end_of_input("syntax error: unexpected end of input".to_string(), None),
+        }
+    }
+
+    /// 入力の終わりでの構文エラー
+    /// | や && の後、または複合コマンドの途中であれば続きの入力を待てる
+    fn end_of_input(&self, message: String, expected: Option<&str>) -> ParseError {
+        let last = self.tokens.iter().rev().find(|tok| **tok != Token::Newline);
+        let reason = match last {
+            Some(Token::Pipe) => Some(Incomplete::Pipe),
+            Some(Token::And | Token::Or) => Some(Incomplete::AndOr),
+            _ => expected
+                .or(self.open.last().copied())
+                .map(|closer| Incomplete::Compound(closer.to_string())),
+        };
+        match reason {
+            Some(reason) => ParseError::incomplete(message, self.position(), reason),
+            None => ParseError::new(message, self.position()),
+        }
+    }
+
+    /// 現在のトークンが予約語であれば返す（コマンド位置でのみ呼ぶこと）
+    /// fi'x' のように断片が続くワードは予約語ではない
+    fn peek_reserved(&self) -> Option<ReservedWord> {
+        match self.peek() {
+            Some(Token::Word(s)) if !self.word_continues() => reserved_word(s),
+            _ => None,
+        }
+    }
+
+    /// 現在のトークンの直後に、同じワードの断片が空白を挟まずに続いているか
+    fn word_continues(&self) -> bool {
+        self.joined.get(self.pos + 1).copied().unwrap_or(false)
+            && matches!(
+                self.tokens.get(self.pos + 1),
+                Some(
+                    Token::Word(_)
+                        | Token::SingleQuoted(_)
+                        | Token::DoubleQuoted(_)
+                        | Token::Arith(_)
+                        | Token::SubstStart
+                )
+            )
+    }
+
+    fn skip_newlines(&mut self) {
+        while self.check(&Token::Newline) {
+            self.advance();
+        }
+    }
+
+    /// コマンドリストの終端（終端予約語、;; または )）かどうか
+    fn at_list_terminator(&self) -> bool {
+        self.check(&Token::DoubleSemicolon)
+            || self.check(&Token::ParenClose)
+            || matches!(
+                self.peek_reserved(),
+                Some(
+                    ReservedWord::Then
+                        | ReservedWord::Elif
+                        | ReservedWord::Else
+                        | ReservedWord::Fi
+                        | ReservedWord::Do
+                        | ReservedWord::Done
+                        | ReservedWord::Esac
+                        | ReservedWord::RBrace
+                )
+            )
+    }
+
+    /// 指定した予約語を消費する
+    fn expect_reserved(&mut self, expected: ReservedWord) -> Result<(), ParseError> {
+        if self.peek_reserved() == Some(expected) {
+            self.advance();
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(tok) => ParseError::new(
+                format!(
+                    "syntax error near unexpected token `{}` (expected `{}`)",
+                    token_to_string(tok),
+                    expected.as_str()
+                ),
+                self.position(),
+            ),
+            None => self.end_of_input(
+                format!(
+                    "syntax error: unexpected end of input (expected `{}`)",
+                    expected.as_str()
+                ),
+                Some(expected.as_str()),
+            ),
+        })
+    }
+
     fn peek(&self) -> Option<&Token> {
         self.tokens.get(self.pos)
     }
 
+    /// 現在のトークンの開始位置（入力の終わりでは最後のトークンの終了位置）
+    fn position(&self) -> u32 {
+        match self.spans.get(self.pos) {
+            Some(span) => span.start,
+            None => self.spans.last().map_or(0, |span| span.end),
+        }
+    }
+
+    /// start から直前に消費したトークンの終わりまでの範囲
+    fn span_from(&self, start: u32) -> Span {
+        let end = self.pos.checked_sub(1).map_or(start, |i| self.spans[i].end);
+        Span::new(start, end.max(start))
+    }
+
     fn check(&self, expected: &Token) -> bool {
         self.peek().map_or(false, |t| std::mem::discriminant(t) == std::mem::discriminant(expected))
     }
@@ -365,24 +1485,222 @@ impl Parser {
         self.tokens.get(self.pos - 1)
     }
 
+    /// 現在のトークンが直前のトークンと空白を挟まずに続いているか
+    fn is_joined(&self) -> bool {
+        self.joined.get(self.pos).copied().unwrap_or(false)
+    }
+
     fn is_at_end(&self) -> bool {
         self.pos >= self.tokens.len()
     }
 }
 
+/// 変数名として有効かどうか
+fn is_valid_name(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// ${...} の中身をパース（不正な場合は None）
+fn parse_parameter(content: &str) -> Option<EnvRef> {
+    // ${#VAR} は長さ
+    if let Some(name) = content.strip_prefix('#') {
+        if !name.is_empty() && parameter_name_len(name) == name.len() {
+            return Some(EnvRef {
+                op: Some(ParamOp::Length),
+                ..EnvRef::new(name)
+            });
+        }
+    }
+
+    let len = parameter_name_len(content);
+    if len == 0 {
+        return None;
+    }
+    let (name, rest) = content.split_at(len);
+    let name = name.to_string();
+    if rest.is_empty() {
+        return Some(EnvRef::new(name));
+    }
+
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let mut chars = rest.chars();
+    let first = chars.next();
+    let word = chars.as_str().to_string();
+
+    let op = match first {
+        Some('-') => ParamOp::Default { colon, word },
+        Some('=') => ParamOp::Assign { colon, word },
+        Some('?') => ParamOp::Error { colon, word },
+        Some('+') => ParamOp::Alternative { colon, word },
+        // ${VAR:offset:length}
+        _ if colon => {
+            let (offset, length) = match split_unnested(rest, ':') {
+                Some((offset, length)) => (offset, Some(length.to_string())),
+                None => (rest, None),
+            };
+            ParamOp::Substring {
+                offset: offset.to_string(),
+                length,
+            }
+        }
+        Some(c @ ('#' | '%')) => {
+            let longest = word.starts_with(c);
+            let pattern = if longest { &word[1..] } else { &word[..] }.to_string();
+            if c == '#' {
+                ParamOp::RemovePrefix { pattern, longest }
+            } else {
+                ParamOp::RemoveSuffix { pattern, longest }
+            }
+        }
+        Some('/') => {
+            let (mode, body) = match word.chars().next() {
+                Some('/') => (ReplaceMode::All, &word[1..]),
+                Some('#') => (ReplaceMode::Prefix, &word[1..]),
+                Some('%') => (ReplaceMode::Suffix, &word[1..]),
+                _ => (ReplaceMode::First, &word[..]),
+            };
+            let (pattern, replacement) = split_unnested(body, '/').unwrap_or((body, ""));
+            ParamOp::Replace {
+                pattern: pattern.to_string(),
+                replacement: replacement.to_string(),
+                mode,
+            }
+        }
+        Some(c @ ('^' | ',')) => {
+            let all = word.starts_with(c);
+            ParamOp::CaseMod {
+                upper: c == '^',
+                all,
+                pattern: if all { &word[1..] } else { &word[..] }.to_string(),
+            }
+        }
+        _ => return None,
+    };
+
+    Some(EnvRef {
+        op: Some(op),
+        ..EnvRef::new(name)
+    })
+}
+
+/// 演算子のない ${1} や ${?} は専用のセグメントにする
+fn parameter_segment(env_ref: EnvRef) -> WordSegment {
+    if env_ref.op.is_some() {
+        return WordSegment::EnvVar(env_ref);
+    }
+    if let Ok(n) = env_ref.name.parse() {
+        return WordSegment::Positional(n);
+    }
+    let mut chars = env_ref.name.chars();
+    match (chars.next().and_then(SpecialParam::from_char), chars.next()) {
+        (Some(param), None) => WordSegment::Special(param),
+        _ => WordSegment::EnvVar(env_ref),
+    }
+}
+
+/// 先頭のパラメータ名の長さ（変数名、数字列、または特殊パラメータ 1 文字）
+fn parameter_name_len(s: &str) -> usize {
+    let ident = s
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(s.len());
+    match s.chars().next() {
+        Some(c) if c.is_ascii_digit() => s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()),
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => ident,
+        Some('@' | '*' | '#' | '?' | '$' | '!') => 1,
+        _ => 0,
+    }
+}
+
+/// クォートやエスケープ、${...} や $(...) の外にある最初の区切り文字で分割
+fn split_unnested(s: &str, sep: char) -> Option<(&str, &str)> {
+    let mut i = 0;
+    while let Some(c) = s[i..].chars().next() {
+        if c == sep {
+            return Some((&s[..i], &s[i + c.len_utf8()..]));
+        }
+        i = skip_unit(s, i)?;
+    }
+    None
+}
+
+/// NAME=value 形式の代入ワードかどうか
+fn is_assignment(s: &str) -> bool {
+    s.find('=').is_some_and(|i| is_valid_name(&s[..i]))
+}
+
+/// チルダ展開できる位置でワードを区切る
+/// 戻り値は (区間, 先頭でチルダ展開できるか)。代入では = の後と、${...} の外の : の後で区切る
+fn tilde_chunks(s: &str, assignment: bool) -> Vec<(&str, bool)> {
+    if !assignment {
+        return vec![(s, true)];
+    }
+
+    let eq = s.find('=').unwrap() + 1;
+    let mut chunks = vec![(&s[..eq], false)];
+    let mut start = eq;
+    let mut depth = 0;
+    let mut prev = '\0';
+    for (i, c) in s[eq..].char_indices().map(|(i, c)| (i + eq, c)) {
+        match c {
+            '{' | '(' if prev == '$' => depth += 1,
+            '}' | ')' if depth > 0 => depth -= 1,
+            ':' if depth == 0 => {
+                chunks.push((&s[start..=i], true));
+                start = i + 1;
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    chunks.push((&s[start..], true));
+    chunks
+}
+
+/// チルダ接頭辞（~ の後ろ）として有効か: 空、+、-、またはユーザー名
+fn is_tilde_prefix(s: &str) -> bool {
+    matches!(s, "" | "+" | "-")
+        || s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'))
+}
+
 /// トークンを文字列に変換
 fn token_to_string(tok: &Token) -> String {
     match tok {
         Token::Word(s) => s.clone(),
-        Token::SingleQuoted(s) => format!("'{}'", s),
-        Token::DoubleQuoted(s) => format!("\"{}\"", s),
+        Token::SingleQuoted(s) => format!("'{}'", s.replace('\'', "'\\''")),
+        Token::DoubleQuoted(s) => format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\"")),
         Token::Pipe => "|".to_string(),
         Token::And => "&&".to_string(),
         Token::Or => "||".to_string(),
         Token::RedirectOut => ">".to_string(),
         Token::RedirectAppend => ">>".to_string(),
         Token::RedirectIn => "<".to_string(),
+        Token::HereDoc(doc) => format!(
+            "<<{}{}",
+            if doc.strip_tabs { "-" } else { "" },
+            if doc.quoted {
+                format!("'{}'", doc.delimiter.replace('\'', "'\\''"))
+            } else {
+                doc.delimiter.clone()
+            }
+        ),
+        Token::HereString => "<<<".to_string(),
+        Token::RedirectReadWrite => "<>".to_string(),
+        Token::DupOut => ">&".to_string(),
+        Token::DupIn => "<&".to_string(),
+        Token::RedirectAll => "&>".to_string(),
+        Token::IoNumber(n) => n.to_string(),
+        Token::Arith(expr) => format!("$(({}))", expr),
+        Token::Semicolon => ";".to_string(),
+        Token::DoubleSemicolon => ";;".to_string(),
+        Token::Background => "&".to_string(),
         Token::SubstStart => "$(".to_string(),
+        Token::ParenOpen => "(".to_string(),
         Token::ParenClose => ")".to_string(),
         Token::Newline => "\n".to_string(),
     }
@@ -392,23 +1710,36 @@ fn token_to_string(tok: &Token) -> String {
 mod tests {
     use super::*;
 
+    /// ワードのセグメント一覧
+    fn segments(word: &ParsedWord) -> Vec<WordSegment> {
+        word.segments().cloned().collect()
+    }
+
+    /// 先頭要素の単純コマンドを取り出す
+    fn first_simple(result: &ParseResult) -> &SimpleCommand {
+        match &result.elements[0].pipeline.commands[0] {
+            Command::Simple(cmd) => cmd,
+            other => panic!("Expected simple command, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_simple_command() {
         let result = Parser::parse_input("echo hello").unwrap();
         assert_eq!(result.elements.len(), 1);
         assert_eq!(result.elements[0].pipeline.commands.len(), 1);
-        assert_eq!(result.elements[0].pipeline.commands[0].args.len(), 2);
+        assert_eq!(first_simple(&result).args.len(), 2);
     }
 
     #[test]
     fn test_env_var() {
         let result = Parser::parse_input("echo $HOME").unwrap();
-        let cmd = &result.elements[0].pipeline.commands[0];
+        let cmd = first_simple(&result);
         assert_eq!(cmd.args.len(), 2);
 
-        if let WordSegment::EnvVar(env) = &cmd.args[1].segments[0] {
+        if let WordSegment::EnvVar(env) = &segments(&cmd.args[1])[0] {
             assert_eq!(env.name, "HOME");
-            assert_eq!(env.default, None);
+            assert_eq!(env.op, None);
         } else {
             panic!("Expected EnvVar");
         }
@@ -417,16 +1748,446 @@ mod tests {
     #[test]
     fn test_env_var_with_default() {
         let result = Parser::parse_input("echo ${FOO:-default}").unwrap();
-        let cmd = &result.elements[0].pipeline.commands[0];
+        let cmd = first_simple(&result);
 
-        if let WordSegment::EnvVar(env) = &cmd.args[1].segments[0] {
+        if let WordSegment::EnvVar(env) = &segments(&cmd.args[1])[0] {
             assert_eq!(env.name, "FOO");
-            assert_eq!(env.default, Some("default".to_string()));
+            assert_eq!(
+                env.op,
+                Some(ParamOp::Default {
+                    colon: true,
+                    word: "default".to_string()
+                })
+            );
         } else {
             panic!("Expected EnvVar");
         }
     }
 
+    #[test]
+    fn test_quoted_words() {
+        let result = Parser::parse_input("ls *.md \"*.md\" '*' \"$DIR\"").unwrap();
+        let quoted: Vec<_> = first_simple(&result)
+            .args
+            .iter()
+            .map(|w| w.parts.iter().all(|part| part.quoted))
+            .collect();
+        assert_eq!(quoted, vec![false, false, true, true, true]);
+    }
+
+    #[test]
+    fn test_spans() {
+        let result = Parser::parse_input("echo a\"b\" 2>err | wc -l").unwrap();
+        let pipeline = &result.elements[0].pipeline;
+        assert_eq!(pipeline.span, Span::new(0, 23));
+        let cmd = first_simple(&result);
+        assert_eq!(cmd.span, Span::new(0, 15));
+        assert_eq!(cmd.args[1].span, Span::new(5, 9));
+        assert_eq!(cmd.redirects[0].span, Span::new(10, 15));
+        let Command::Simple(wc) = &pipeline.commands[1] else {
+            panic!("Expected simple command");
+        };
+        assert_eq!(wc.span, Span::new(18, 23));
+
+        let result = Parser::parse_input("f() { a; } >log\nwhile b; do c; done & d").unwrap();
+        assert_eq!(result.functions[0].span, Span::new(0, 15));
+        assert_eq!(result.compounds[0].span, Span::new(4, 15));
+        assert_eq!(result.compounds[1].span, Span::new(16, 35));
+        assert_eq!(result.elements[0].span, Span::new(0, 15));
+        // & で終わる要素は & を含む
+        assert_eq!(result.elements[1].span, Span::new(16, 37));
+        assert_eq!(result.elements[1].pipeline.span, Span::new(16, 35));
+        assert_eq!(result.elements[2].span, Span::new(38, 39));
+
+        // エラーは入力中の位置を指す
+        assert_eq!(Parser::parse_input("echo a; fi").unwrap_err().position, 8);
+        assert_eq!(Parser::parse_input("echo a |").unwrap_err().position, 8);
+        assert_eq!(Parser::parse_input("echo ${a").unwrap_err().position, 5);
+    }
+
+    #[test]
+    fn test_incomplete_input() {
+        let incomplete = |input: &str| Parser::parse_input(input).unwrap_err().incomplete;
+        let compound = |closer: &str| Some(Incomplete::Compound(closer.to_string()));
+
+        assert_eq!(incomplete("echo 'abc"), Some(Incomplete::SingleQuote));
+        assert_eq!(incomplete("echo \"a\nb"), Some(Incomplete::DoubleQuote));
+        assert_eq!(incomplete("echo a \\"), Some(Incomplete::LineContinuation));
+        assert_eq!(incomplete("ls |"), Some(Incomplete::Pipe));
+        assert_eq!(incomplete("ls |\n"), Some(Incomplete::Pipe));
+        assert_eq!(incomplete("true &&"), Some(Incomplete::AndOr));
+        assert_eq!(incomplete("false ||\n"), Some(Incomplete::AndOr));
+        assert_eq!(
+            incomplete("echo $(date"),
+            Some(Incomplete::CommandSubstitution)
+        );
+        assert_eq!(
+            incomplete("cat <<EOF\nhello"),
+            Some(Incomplete::HereDoc("EOF".to_string()))
+        );
+        assert_eq!(
+            incomplete("cat <<EOF"),
+            Some(Incomplete::HereDoc("EOF".to_string()))
+        );
+        assert_eq!(incomplete("if true; then"), compound("fi"));
+        assert_eq!(incomplete("if true"), compound("then"));
+        assert_eq!(incomplete("for x in a b\n"), compound("do"));
+        assert_eq!(incomplete("while true; do echo |"), Some(Incomplete::Pipe));
+        assert_eq!(incomplete("case x in\n a) echo;;"), compound("esac"));
+        assert_eq!(incomplete("f() {\n echo"), compound("}"));
+        assert_eq!(incomplete("f()\n"), compound("{"));
+        assert_eq!(incomplete("(ls"), compound(")"));
+
+        // 続きがあっても直らないエラー
+        assert_eq!(incomplete("echo a; fi"), None);
+        assert_eq!(incomplete("true && fi"), None);
+        assert_eq!(incomplete("ls >"), None);
+    }
+
+    #[test]
+    fn test_substitution_keeps_words() {
+        let result = Parser::parse_input("echo $(echo a\"b c\" d\\ e)").unwrap();
+        assert_eq!(result.substitutions[0].input, "echo a\"b c\" d' 'e");
+    }
+
+    #[test]
+    fn test_comments() {
+        let result = Parser::parse_input("#!/bin/sh\n# setup\nls -a # list files\n").unwrap();
+        assert_eq!(result.elements.len(), 1);
+        let cmd = first_simple(&result);
+        assert_eq!(
+            cmd.args,
+            vec![ParsedWord::literal("ls"), ParsedWord::literal("-a")]
+        );
+    }
+
+    #[test]
+    fn test_word_concatenation() {
+        let result = Parser::parse_input("echo a\"b\"c foo\"bar\"'baz'$X \"\"").unwrap();
+        let args = &first_simple(&result).args;
+        assert_eq!(args.len(), 4);
+
+        let part = |segment: WordSegment, quoted: bool| WordPart { segment, quoted };
+        let literal = |s: &str| WordSegment::Literal(s.to_string());
+        assert_eq!(
+            args[1].parts,
+            vec![
+                part(literal("a"), false),
+                part(literal("b"), true),
+                part(literal("c"), false),
+            ]
+        );
+        assert_eq!(
+            args[2].parts,
+            vec![
+                part(literal("foo"), false),
+                part(literal("barbaz"), true),
+                part(WordSegment::EnvVar(EnvRef::new("X")), false),
+            ]
+        );
+        assert_eq!(args[3], ParsedWord::literal("").into_quoted());
+
+        // コマンド置換や算術式展開も連結する
+        let result = Parser::parse_input("echo v=$(date)! $((1+1))px").unwrap();
+        let args = &first_simple(&result).args;
+        assert_eq!(
+            segments(&args[1]),
+            vec![literal("v="), WordSegment::CommandSubst(0), literal("!")]
+        );
+        assert_eq!(
+            segments(&args[2]),
+            vec![WordSegment::Arith("1+1".to_string()), literal("px")]
+        );
+
+        // リダイレクト先も 1 つのワードになる
+        let result = Parser::parse_input("echo hi >\"$DIR\"/out.txt").unwrap();
+        let target = &first_simple(&result).redirects[0].target;
+        let RedirectTarget::Word(word) = target else {
+            panic!("Expected word target");
+        };
+        assert_eq!(word.parts.len(), 2);
+    }
+
+    #[test]
+    fn test_brace_expansion_with_quotes() {
+        let result = Parser::parse_input("echo {a,b}\"-x\"").unwrap();
+        let args: Vec<_> = first_simple(&result).args[1..]
+            .iter()
+            .map(|arg| arg.parts.iter().map(|part| part.quoted).collect::<Vec<_>>())
+            .collect();
+        assert_eq!(args, vec![vec![false, true], vec![false, true]]);
+    }
+
+    #[test]
+    fn test_brace_expansion() {
+        let result = Parser::parse_input("mkdir -p notes/{2025,2026} '{a,b}'").unwrap();
+        assert_eq!(
+            first_simple(&result).args,
+            vec![
+                ParsedWord::literal("mkdir"),
+                ParsedWord::literal("-p"),
+                ParsedWord::literal("notes/2025"),
+                ParsedWord::literal("notes/2026"),
+                ParsedWord::literal("{a,b}").into_quoted(),
+            ]
+        );
+
+        // コマンド名より前の代入は展開しない
+        let result = Parser::parse_input("A={x,y} echo B={1,2}").unwrap();
+        assert_eq!(
+            first_simple(&result).args,
+            vec![
+                ParsedWord::literal("A={x,y}"),
+                ParsedWord::literal("echo"),
+                ParsedWord::literal("B=1"),
+                ParsedWord::literal("B=2"),
+            ]
+        );
+
+        // 空になったワードは取り除く
+        let result = Parser::parse_input("echo {,} a{,}").unwrap();
+        assert_eq!(
+            first_simple(&result).args,
+            vec![
+                ParsedWord::literal("echo"),
+                ParsedWord::literal("a"),
+                ParsedWord::literal("a"),
+            ]
+        );
+
+        // 展開後のワード数には上限がある
+        assert!(Parser::parse_input("echo {1..100000000}").is_err());
+        assert!(Parser::parse_input("echo {1..1000}'x'{1..1000}").is_err());
+
+        // 変数展開より前に行われる
+        let result = Parser::parse_input("for f in $DIR/{a,b}.md; do cat $f; done").unwrap();
+        let CompoundKind::For(clause) = &result.compounds[0].kind else {
+            panic!("Expected for clause");
+        };
+        let words = clause.words.as_ref().unwrap();
+        assert_eq!(words.len(), 2);
+        assert_eq!(
+            segments(&words[1])[1],
+            WordSegment::Literal("/b.md".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tilde() {
+        let tilde = |s: &str| WordSegment::Tilde(s.to_string());
+        let result = Parser::parse_input("ls ~ ~/notes ~alice/x ~+ ~- a~ '~' \"~\" ~$X").unwrap();
+        let args: Vec<_> = first_simple(&result).args.iter().map(segments).collect();
+        assert_eq!(args[1], vec![tilde("")]);
+        assert_eq!(
+            args[2],
+            vec![tilde(""), WordSegment::Literal("/notes".to_string())]
+        );
+        assert_eq!(
+            args[3],
+            vec![tilde("alice"), WordSegment::Literal("/x".to_string())]
+        );
+        assert_eq!(args[4], vec![tilde("+")]);
+        assert_eq!(args[5], vec![tilde("-")]);
+        for arg in &args[6..] {
+            assert!(!arg.contains(&tilde("")));
+        }
+    }
+
+    #[test]
+    fn test_tilde_in_assignment() {
+        let result = Parser::parse_input("export PATH=~/bin:${X:-~}:~+/y a=b:~").unwrap();
+        let args = &first_simple(&result).args;
+        assert_eq!(
+            segments(&args[1]),
+            vec![
+                WordSegment::Literal("PATH=".to_string()),
+                WordSegment::Tilde(String::new()),
+                WordSegment::Literal("/bin:".to_string()),
+                WordSegment::EnvVar(EnvRef {
+                    name: "X".to_string(),
+                    op: Some(ParamOp::Default {
+                        colon: true,
+                        word: "~".to_string(),
+                    }),
+                    substitutions: Vec::new(),
+                }),
+                WordSegment::Literal(":".to_string()),
+                WordSegment::Tilde("+".to_string()),
+                WordSegment::Literal("/y".to_string()),
+            ]
+        );
+        assert_eq!(segments(&args[2])[1], WordSegment::Tilde(String::new()));
+
+        // 代入でなければ : の後は展開しない
+        let result = Parser::parse_input("echo a:~").unwrap();
+        assert_eq!(first_simple(&result).args[1], ParsedWord::literal("a:~"));
+    }
+
+    #[test]
+    fn test_arith() {
+        let result = Parser::parse_input("echo $((1 + (2 * 3))) \"n=$((n+1))\"").unwrap();
+        let args = &first_simple(&result).args;
+        assert_eq!(
+            segments(&args[1]),
+            vec![WordSegment::Arith("1 + (2 * 3)".to_string())]
+        );
+        assert_eq!(
+            segments(&args[2]),
+            vec![
+                WordSegment::Literal("n=".to_string()),
+                WordSegment::Arith("n+1".to_string()),
+            ]
+        );
+        assert!(result.substitutions.is_empty());
+
+        // $( ( ... ) ) はコマンド置換内のサブシェル
+        let result = Parser::parse_input("echo $((echo a); echo b)").unwrap();
+        assert_eq!(result.substitutions.len(), 1);
+
+        assert!(Parser::parse_input("echo $((1 +))").is_err());
+        assert!(Parser::parse_input("echo \"$((2 2))\"").is_err());
+    }
+
+    #[test]
+    fn test_parameter_operand_quoting() {
+        let result = Parser::parse_input(r#"echo ${X:-a b} "${X#"*"}" ${X/'}'/-}"#).unwrap();
+        let args = &first_simple(&result).args;
+        assert_eq!(args.len(), 4);
+
+        let env_ref = |word: &ParsedWord| match &word.parts[..] {
+            [WordPart {
+                segment: WordSegment::EnvVar(env_ref),
+                ..
+            }] => env_ref.clone(),
+            parts => panic!("Expected parameter expansion, got {:?}", parts),
+        };
+        // オペランドはクォートを残したまま保持する
+        assert_eq!(
+            env_ref(&args[1]).op,
+            Some(ParamOp::Default {
+                colon: true,
+                word: "a b".to_string()
+            })
+        );
+        assert_eq!(
+            env_ref(&args[2]).op,
+            Some(ParamOp::RemovePrefix {
+                pattern: "\"*\"".to_string(),
+                longest: false
+            })
+        );
+        assert_eq!(
+            env_ref(&args[3]).op,
+            Some(ParamOp::Replace {
+                pattern: "'}'".to_string(),
+                replacement: "-".to_string(),
+                mode: ReplaceMode::First
+            })
+        );
+    }
+
+    #[test]
+    fn test_parameter_operators() {
+        let op = |content: &str| parse_parameter(content).unwrap().op.unwrap();
+        let word = |w: &str| w.to_string();
+
+        assert_eq!(op("#PATH"), ParamOp::Length);
+        assert_eq!(
+            op("X-a b"),
+            ParamOp::Default {
+                colon: false,
+                word: word("a b")
+            }
+        );
+        assert_eq!(
+            op("X:=init"),
+            ParamOp::Assign {
+                colon: true,
+                word: word("init")
+            }
+        );
+        assert_eq!(
+            op("X:?not set"),
+            ParamOp::Error {
+                colon: true,
+                word: word("not set")
+            }
+        );
+        assert_eq!(
+            op("X+alt"),
+            ParamOp::Alternative {
+                colon: false,
+                word: word("alt")
+            }
+        );
+        assert_eq!(
+            op("file##*/"),
+            ParamOp::RemovePrefix {
+                pattern: word("*/"),
+                longest: true
+            }
+        );
+        assert_eq!(
+            op("file%.*"),
+            ParamOp::RemoveSuffix {
+                pattern: word(".*"),
+                longest: false
+            }
+        );
+        assert_eq!(
+            op("path//\\//-"),
+            ParamOp::Replace {
+                pattern: word("\\/"),
+                replacement: word("-"),
+                mode: ReplaceMode::All
+            }
+        );
+        assert_eq!(
+            op("X/#a"),
+            ParamOp::Replace {
+                pattern: word("a"),
+                replacement: word(""),
+                mode: ReplaceMode::Prefix
+            }
+        );
+        assert_eq!(
+            op("X: -3:2"),
+            ParamOp::Substring {
+                offset: word(" -3"),
+                length: Some(word("2"))
+            }
+        );
+        assert_eq!(
+            op("X:1"),
+            ParamOp::Substring {
+                offset: word("1"),
+                length: None
+            }
+        );
+        assert_eq!(
+            op("name^^"),
+            ParamOp::CaseMod {
+                upper: true,
+                all: true,
+                pattern: word("")
+            }
+        );
+        assert_eq!(
+            op("name,[A-M]"),
+            ParamOp::CaseMod {
+                upper: false,
+                all: false,
+                pattern: word("[A-M]")
+            }
+        );
+
+        assert_eq!(parse_parameter("#").unwrap().name, "#");
+        assert!(parse_parameter("").is_none());
+        assert!(parse_parameter("X&").is_none());
+        assert!(Parser::parse_input("echo ${}").is_err());
+        assert!(Parser::parse_input("echo ${X").is_err());
+    }
+
     #[test]
     fn test_pipeline() {
         let result = Parser::parse_input("ls | cat").unwrap();
@@ -443,10 +2204,580 @@ mod tests {
         assert_eq!(result.elements[2].connector, Connector::Or);
     }
 
+    #[test]
+    fn test_sequential() {
+        let result = Parser::parse_input("echo a; echo b").unwrap();
+        assert_eq!(result.elements.len(), 2);
+        assert_eq!(result.elements[0].connector, Connector::None);
+        assert_eq!(result.elements[1].connector, Connector::Sequential);
+        assert!(!result.elements[0].background);
+    }
+
+    #[test]
+    fn test_background() {
+        let result = Parser::parse_input("sleep 5 &").unwrap();
+        assert_eq!(result.elements.len(), 1);
+        assert!(result.elements[0].background);
+
+        // & は AND-OR リスト全体に掛かる
+        let result = Parser::parse_input("echo a; cmd1 && cmd2 & echo b").unwrap();
+        assert_eq!(result.elements.len(), 4);
+        assert!(!result.elements[0].background);
+        assert!(result.elements[1].background);
+        assert!(result.elements[2].background);
+        assert!(!result.elements[3].background);
+        assert_eq!(result.elements[3].connector, Connector::Sequential);
+    }
+
+    #[test]
+    fn test_unexpected_separator() {
+        assert!(Parser::parse_input("; echo a").is_err());
+        assert!(Parser::parse_input("echo a;; echo b").is_err());
+        assert!(Parser::parse_input("&& echo a").is_err());
+    }
+
+    #[test]
+    fn test_if() {
+        let result = Parser::parse_input("if true; then echo yes; fi").unwrap();
+        assert_eq!(result.elements.len(), 1);
+        assert_eq!(
+            result.elements[0].pipeline.commands[0],
+            Command::Compound(0)
+        );
+
+        let CompoundKind::If(clause) = &result.compounds[0].kind else {
+            panic!("Expected if clause");
+        };
+        assert_eq!(clause.branches.len(), 1);
+        assert_eq!(clause.branches[0].condition.len(), 1);
+        assert_eq!(clause.branches[0].body.len(), 1);
+        assert_eq!(clause.else_body, None);
+    }
+
+    #[test]
+    fn test_if_elif_else() {
+        let input =
+            "if test -f a\nthen\n  echo a\nelif test -f b; then echo b\nelse\n  echo c; echo d\nfi";
+        let result = Parser::parse_input(input).unwrap();
+        let CompoundKind::If(clause) = &result.compounds[0].kind else {
+            panic!("Expected if clause");
+        };
+        assert_eq!(clause.branches.len(), 2);
+        assert_eq!(clause.else_body.as_ref().map(|b| b.len()), Some(2));
+    }
+
+    #[test]
+    fn test_nested_if() {
+        let input = "if true; then if false; then echo a; fi; fi && echo b";
+        let result = Parser::parse_input(input).unwrap();
+        assert_eq!(result.elements.len(), 2);
+        assert_eq!(result.elements[1].connector, Connector::And);
+        // 内側の if が先に登録される
+        assert_eq!(result.compounds.len(), 2);
+        assert_eq!(
+            result.elements[0].pipeline.commands[0],
+            Command::Compound(1)
+        );
+    }
+
+    #[test]
+    fn test_while_loop() {
+        let result = Parser::parse_input("while true; do echo a; done").unwrap();
+        let CompoundKind::Loop(clause) = &result.compounds[0].kind else {
+            panic!("Expected loop clause");
+        };
+        assert_eq!(clause.kind, LoopKind::While);
+        assert_eq!(clause.condition.len(), 1);
+        assert_eq!(clause.body.len(), 1);
+    }
+
+    #[test]
+    fn test_until_loop_nested() {
+        let input = "until false\ndo\n  while true; do break 2; done\n  continue\ndone";
+        let result = Parser::parse_input(input).unwrap();
+        assert_eq!(result.compounds.len(), 2);
+
+        let CompoundKind::Loop(outer) = &result.compounds[1].kind else {
+            panic!("Expected loop clause");
+        };
+        assert_eq!(outer.kind, LoopKind::Until);
+        assert_eq!(outer.body.len(), 2);
+        assert_eq!(
+            outer.body[1].pipeline.commands[0],
+            Command::LoopControl(LoopControl {
+                kind: LoopControlKind::Continue,
+                depth: 1,
+            })
+        );
+
+        let CompoundKind::Loop(inner) = &result.compounds[0].kind else {
+            panic!("Expected loop clause");
+        };
+        assert_eq!(
+            inner.body[0].pipeline.commands[0],
+            Command::LoopControl(LoopControl {
+                kind: LoopControlKind::Break,
+                depth: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_for_loop() {
+        let result = Parser::parse_input("for f in a.md $FILES $(ls); do cat $f; done").unwrap();
+        let CompoundKind::For(clause) = &result.compounds[0].kind else {
+            panic!("Expected for clause");
+        };
+        assert_eq!(clause.variable, "f");
+        let words = clause.words.as_ref().unwrap();
+        assert_eq!(words.len(), 3);
+        assert_eq!(words[0], ParsedWord::literal("a.md"));
+        assert!(matches!(&segments(&words[1])[0], WordSegment::EnvVar(env) if env.name == "FILES"));
+        assert_eq!(segments(&words[2]), vec![WordSegment::CommandSubst(0)]);
+        assert_eq!(result.substitutions[0].input, "ls");
+        assert_eq!(clause.body.len(), 1);
+    }
+
+    #[test]
+    fn test_for_loop_forms() {
+        // 改行区切り
+        let result = Parser::parse_input("for x in 1 2\ndo\n  echo $x\ndone").unwrap();
+        let CompoundKind::For(clause) = &result.compounds[0].kind else {
+            panic!("Expected for clause");
+        };
+        assert_eq!(clause.words.as_ref().map(|w| w.len()), Some(2));
+
+        // 空のワードリスト
+        let result = Parser::parse_input("for x in; do echo $x; done").unwrap();
+        let CompoundKind::For(clause) = &result.compounds[0].kind else {
+            panic!("Expected for clause");
+        };
+        assert_eq!(clause.words, Some(vec![]));
+
+        // in を省略すると位置パラメータが対象
+        for input in ["for x; do echo $x; done", "for x do echo $x; done"] {
+            let result = Parser::parse_input(input).unwrap();
+            let CompoundKind::For(clause) = &result.compounds[0].kind else {
+                panic!("Expected for clause");
+            };
+            assert_eq!(clause.words, None);
+        }
+    }
+
+    #[test]
+    fn test_for_loop_errors() {
+        assert!(Parser::parse_input("for 1x in a; do echo; done").is_err());
+        assert!(Parser::parse_input("for x in a b do echo; done").is_err());
+        assert!(Parser::parse_input("for x in a; do echo $x").is_err());
+    }
+
+    #[test]
+    fn test_case() {
+        let input = "case $1 in\n  start|run) echo start;;\n  *.md) markdown $1; echo done ;;\n  stop)\n    ;;\n  *) echo other\nesac";
+        let result = Parser::parse_input(input).unwrap();
+        let CompoundKind::Case(clause) = &result.compounds[0].kind else {
+            panic!("Expected case clause");
+        };
+        assert_eq!(segments(&clause.word), vec![WordSegment::Positional(1)]);
+        assert_eq!(clause.arms.len(), 4);
+        assert_eq!(
+            clause.arms[0].patterns,
+            vec![ParsedWord::literal("start"), ParsedWord::literal("run")]
+        );
+        assert_eq!(clause.arms[1].body.len(), 2);
+        assert!(clause.arms[2].body.is_empty());
+        assert_eq!(clause.arms[3].patterns, vec![ParsedWord::literal("*")]);
+    }
+
+    #[test]
+    fn test_case_empty_and_nested() {
+        let result = Parser::parse_input("case x in esac").unwrap();
+        let CompoundKind::Case(clause) = &result.compounds[0].kind else {
+            panic!("Expected case clause");
+        };
+        assert!(clause.arms.is_empty());
+
+        let input = "for f in a b; do case $f in a) echo A;; esac; done";
+        let result = Parser::parse_input(input).unwrap();
+        assert!(matches!(result.compounds[0].kind, CompoundKind::Case(_)));
+        assert!(matches!(result.compounds[1].kind, CompoundKind::For(_)));
+    }
+
+    #[test]
+    fn test_case_errors() {
+        assert!(Parser::parse_input("case x in a) echo a;; b) echo b").is_err());
+        assert!(Parser::parse_input("case x a) echo a;; esac").is_err());
+        assert!(Parser::parse_input("case x in a echo a;; esac").is_err());
+        assert!(Parser::parse_input("case x in a) echo a; b) echo b;; esac").is_err());
+    }
+
+    #[test]
+    fn test_loop_errors() {
+        assert!(Parser::parse_input("while true; do echo a").is_err());
+        assert!(Parser::parse_input("while true; echo a; done").is_err());
+        assert!(Parser::parse_input("while true; do done").is_err());
+        assert!(Parser::parse_input("break 0").is_err());
+        assert!(Parser::parse_input("continue x").is_err());
+    }
+
+    #[test]
+    fn test_function_definition() {
+        let input = "show() {\n  markdown /home/uzimaru0000/$1.md\n}\nshow work";
+        let result = Parser::parse_input(input).unwrap();
+        assert_eq!(result.elements.len(), 2);
+        assert_eq!(
+            result.elements[0].pipeline.commands[0],
+            Command::FunctionDef(0)
+        );
+        assert_eq!(result.functions[0].name, "show");
+
+        let CompoundKind::BraceGroup(body) =
+            &result.compounds[result.functions[0].body as usize].kind
+        else {
+            panic!("Expected brace group");
+        };
+        let Command::Simple(cmd) = &body[0].pipeline.commands[0] else {
+            panic!("Expected simple command");
+        };
+        assert_eq!(
+            segments(&cmd.args[1]),
+            vec![
+                WordSegment::Literal("/home/uzimaru0000/".to_string()),
+                WordSegment::Positional(1),
+                WordSegment::Literal(".md".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_function_with_compound_body() {
+        let result = Parser::parse_input("f() if true; then echo a; fi").unwrap();
+        assert_eq!(result.functions.len(), 1);
+        assert!(matches!(
+            result.compounds[result.functions[0].body as usize].kind,
+            CompoundKind::If(_)
+        ));
+    }
+
+    #[test]
+    fn test_brace_group() {
+        let result = Parser::parse_input("{ echo a; echo b; } && echo c").unwrap();
+        assert_eq!(result.elements.len(), 2);
+        let CompoundKind::BraceGroup(body) = &result.compounds[0].kind else {
+            panic!("Expected brace group");
+        };
+        assert_eq!(body.len(), 2);
+
+        // } はコマンド位置でのみ予約語
+        let result = Parser::parse_input("{ echo }; }").unwrap();
+        let CompoundKind::BraceGroup(body) = &result.compounds[0].kind else {
+            panic!("Expected brace group");
+        };
+        let Command::Simple(cmd) = &body[0].pipeline.commands[0] else {
+            panic!("Expected simple command");
+        };
+        assert_eq!(cmd.args[1], ParsedWord::literal("}"));
+    }
+
+    #[test]
+    fn test_subshell() {
+        let result = Parser::parse_input("(cd /tmp && ls) | cat").unwrap();
+        assert_eq!(result.elements.len(), 1);
+        let commands = &result.elements[0].pipeline.commands;
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0], Command::Compound(0));
+
+        let CompoundKind::Subshell(body) = &result.compounds[0].kind else {
+            panic!("Expected subshell");
+        };
+        assert_eq!(body.len(), 2);
+        assert_eq!(body[1].connector, Connector::And);
+
+        // 閉じ括弧の前に改行を置ける
+        let result = Parser::parse_input("(\n    a\n    b\n)").unwrap();
+        let CompoundKind::Subshell(body) = &result.compounds[0].kind else {
+            panic!("Expected subshell");
+        };
+        assert_eq!(body.len(), 2);
+    }
+
+    #[test]
+    fn test_nested_subshell_in_substitution() {
+        let result = Parser::parse_input("echo $( (echo a) )").unwrap();
+        assert_eq!(result.substitutions.len(), 1);
+        assert!(result.compounds.is_empty());
+
+        // 置換の中身は単独でパースできる
+        let inner = Parser::parse_input(&result.substitutions[0].input).unwrap();
+        assert!(matches!(inner.compounds[0].kind, CompoundKind::Subshell(_)));
+    }
+
+    #[test]
+    fn test_group_redirects() {
+        let result = Parser::parse_input("{ echo a; echo b; } > out").unwrap();
+        let compound = &result.compounds[0];
+        assert!(matches!(compound.kind, CompoundKind::BraceGroup(_)));
+        assert_eq!(compound.redirects.len(), 1);
+        assert_eq!(compound.redirects[0].kind, RedirectKind::Stdout);
+        assert_eq!(
+            compound.redirects[0].target,
+            RedirectTarget::Word(ParsedWord::literal("out"))
+        );
+
+        let result = Parser::parse_input("(cat) < in >> log; echo done").unwrap();
+        assert_eq!(result.elements.len(), 2);
+        let kinds: Vec<_> = result.compounds[0]
+            .redirects
+            .iter()
+            .map(|r| r.kind)
+            .collect();
+        assert_eq!(kinds, vec![RedirectKind::Stdin, RedirectKind::StdoutAppend]);
+
+        let result = Parser::parse_input("while true; do echo a; done > out").unwrap();
+        assert_eq!(result.compounds[0].redirects.len(), 1);
+    }
+
+    #[test]
+    fn test_here_doc() {
+        let result = Parser::parse_input("cat <<EOF\nhi $USER\nEOF\necho done").unwrap();
+        assert_eq!(result.elements.len(), 2);
+        let cmd = first_simple(&result);
+        assert_eq!(cmd.args, vec![ParsedWord::literal("cat")]);
+        assert_eq!(cmd.redirects[0].kind, RedirectKind::HereDoc);
+        let RedirectTarget::Word(body) = &cmd.redirects[0].target else {
+            panic!("Expected here-doc body");
+        };
+        assert_eq!(
+            segments(body),
+            vec![
+                WordSegment::Literal("hi ".to_string()),
+                WordSegment::EnvVar(EnvRef::new("USER")),
+                WordSegment::Literal("\n".to_string()),
+            ]
+        );
+
+        // クォートされた区切り文字では展開しない
+        let result = Parser::parse_input("cat <<'EOF' | markdown\n$(date)\nEOF").unwrap();
+        assert_eq!(result.elements[0].pipeline.commands.len(), 2);
+        assert_eq!(
+            first_simple(&result).redirects[0].target,
+            RedirectTarget::Word(ParsedWord::literal("$(date)\n"))
+        );
+        assert!(result.substitutions.is_empty());
+
+        assert!(Parser::parse_input("cat <<").is_err());
+    }
+
+    #[test]
+    fn test_here_doc_escapes() {
+        let input = "cat <<EOF\n\\$HOME costs \\`x\\` a\\\\b \\n $X\\\nY\nEOF";
+        let result = Parser::parse_input(input).unwrap();
+        let RedirectTarget::Word(body) = &first_simple(&result).redirects[0].target else {
+            panic!("Expected here-doc body");
+        };
+        assert_eq!(
+            segments(body),
+            vec![
+                WordSegment::Literal("$HOME costs `x` a\\b \\n ".to_string()),
+                WordSegment::EnvVar(EnvRef::new("XY")),
+                WordSegment::Literal("\n".to_string()),
+            ]
+        );
+        assert!(result.substitutions.is_empty());
+    }
+
+    #[test]
+    fn test_here_string() {
+        let result = Parser::parse_input("cat <<< \"$HOME\"").unwrap();
+        let cmd = first_simple(&result);
+        assert_eq!(cmd.redirects[0].kind, RedirectKind::HereString);
+        assert!(matches!(
+            &cmd.redirects[0].target,
+            RedirectTarget::Word(word) if matches!(segments(word)[0], WordSegment::EnvVar(_))
+        ));
+
+        assert!(Parser::parse_input("cat <<<").is_err());
+    }
+
+    #[test]
+    fn test_fd_redirects() {
+        let result = Parser::parse_input("rm -f x 2>/dev/null").unwrap();
+        let cmd = first_simple(&result);
+        assert_eq!(cmd.args.len(), 3);
+        assert_eq!(
+            cmd.redirects,
+            vec![Redirect {
+                fd: 2,
+                kind: RedirectKind::Stdout,
+                target: RedirectTarget::Word(ParsedWord::literal("/dev/null")),
+                span: Span::new(8, 19),
+            }]
+        );
+
+        let result = Parser::parse_input("cmd 2>>log >&2 2>&1 <>rw").unwrap();
+        let redirects: Vec<_> = first_simple(&result)
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.kind, r.target.clone()))
+            .collect();
+        assert_eq!(
+            redirects,
+            vec![
+                (
+                    2,
+                    RedirectKind::StdoutAppend,
+                    RedirectTarget::Word(ParsedWord::literal("log"))
+                ),
+                (1, RedirectKind::Stdout, RedirectTarget::Fd(2)),
+                (2, RedirectKind::Stdout, RedirectTarget::Fd(1)),
+                (
+                    0,
+                    RedirectKind::ReadWrite,
+                    RedirectTarget::Word(ParsedWord::literal("rw"))
+                ),
+            ]
+        );
+
+        // &> は >file 2>&1 と同じ
+        let result = Parser::parse_input("cmd &> all").unwrap();
+        let redirects = &first_simple(&result).redirects;
+        assert_eq!(redirects.len(), 2);
+        assert_eq!((redirects[0].fd, redirects[1].fd), (1, 2));
+        assert_eq!(redirects[1].target, RedirectTarget::Fd(1));
+
+        assert!(Parser::parse_input("cmd >&file").is_err());
+        assert!(Parser::parse_input("cmd 2>").is_err());
+    }
+
+    #[test]
+    fn test_case_leading_paren() {
+        let result = Parser::parse_input("case a in (a|b) echo ab;; esac").unwrap();
+        let CompoundKind::Case(clause) = &result.compounds[0].kind else {
+            panic!("Expected case clause");
+        };
+        assert_eq!(clause.arms[0].patterns.len(), 2);
+    }
+
+    #[test]
+    fn test_subshell_errors() {
+        assert!(Parser::parse_input("(echo a").is_err());
+        assert!(Parser::parse_input("()").is_err());
+        assert!(Parser::parse_input("(echo a) echo b").is_err());
+    }
+
+    #[test]
+    fn test_function_errors() {
+        assert!(Parser::parse_input("f() echo a").is_err());
+        assert!(Parser::parse_input("1f() { echo a; }").is_err());
+        assert!(Parser::parse_input("{ echo a }").is_err());
+        assert!(Parser::parse_input("{ }").is_err());
+    }
+
+    #[test]
+    fn test_positional() {
+        let result = Parser::parse_input("echo $1 $23").unwrap();
+        let cmd = first_simple(&result);
+        assert_eq!(segments(&cmd.args[1]), vec![WordSegment::Positional(1)]);
+        assert_eq!(
+            segments(&cmd.args[2]),
+            vec![
+                WordSegment::Positional(2),
+                WordSegment::Literal("3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_special_params() {
+        let result = Parser::parse_input("echo $? $$ $! $# $@ $* $0 ${10} ${?}").unwrap();
+        let cmd = first_simple(&result);
+        let params: Vec<_> = cmd.args[1..].iter().map(segments).collect();
+        assert_eq!(
+            params,
+            vec![
+                vec![WordSegment::Special(SpecialParam::ExitStatus)],
+                vec![WordSegment::Special(SpecialParam::Pid)],
+                vec![WordSegment::Special(SpecialParam::LastBackgroundPid)],
+                vec![WordSegment::Special(SpecialParam::ArgCount)],
+                vec![WordSegment::Special(SpecialParam::AllArgs)],
+                vec![WordSegment::Special(SpecialParam::AllArgsJoined)],
+                vec![WordSegment::Positional(0)],
+                vec![WordSegment::Positional(10)],
+                vec![WordSegment::Special(SpecialParam::ExitStatus)],
+            ]
+        );
+
+        // 演算子付きは名前で参照する
+        let result = Parser::parse_input("echo ${1:-none} \"$?!\"").unwrap();
+        let cmd = first_simple(&result);
+        assert_eq!(
+            segments(&cmd.args[1]),
+            vec![WordSegment::EnvVar(EnvRef {
+                name: "1".to_string(),
+                op: Some(ParamOp::Default {
+                    colon: true,
+                    word: "none".to_string()
+                }),
+                substitutions: Vec::new(),
+            })]
+        );
+        assert_eq!(
+            segments(&cmd.args[2]),
+            vec![
+                WordSegment::Special(SpecialParam::ExitStatus),
+                WordSegment::Literal("!".to_string())
+            ]
+        );
+
+        // $$ の後ろの { は ${ として扱わない。名前の続かない $ は前のリテラルとまとめる
+        let result = Parser::parse_input("echo $${a} \"a$\" b$-").unwrap();
+        let cmd = first_simple(&result);
+        assert_eq!(
+            segments(&cmd.args[1]),
+            vec![
+                WordSegment::Special(SpecialParam::Pid),
+                WordSegment::Literal("{a}".to_string())
+            ]
+        );
+        assert_eq!(cmd.args[2], ParsedWord::literal("a$").into_quoted());
+        assert_eq!(cmd.args[3], ParsedWord::literal("b$-"));
+    }
+
+    #[test]
+    fn test_reserved_word_as_argument() {
+        let result = Parser::parse_input("echo if then fi").unwrap();
+        let cmd = first_simple(&result);
+        assert_eq!(cmd.args.len(), 4);
+        assert!(result.compounds.is_empty());
+    }
+
+    #[test]
+    fn test_reserved_word_with_quoted_fragment() {
+        // 断片が続くワードは予約語ではない
+        let result = Parser::parse_input("fi'x'").unwrap();
+        let mut expected = ParsedWord::literal("fi");
+        expected.append(ParsedWord::literal("x").into_quoted());
+        assert_eq!(first_simple(&result).args[0], expected);
+        let result = Parser::parse_input("echo; then\"y\"").unwrap();
+        assert_eq!(result.elements.len(), 2);
+        assert!(result.compounds.is_empty());
+        assert!(Parser::parse_input("if a; then b; fi\"\"").is_err());
+        // ; などの演算子が続いても予約語のまま
+        assert!(Parser::parse_input("if a; then b; fi;").is_ok());
+    }
+
+    #[test]
+    fn test_if_errors() {
+        assert!(Parser::parse_input("if true; then echo a").is_err());
+        assert!(Parser::parse_input("if true; echo a; fi").is_err());
+        assert!(Parser::parse_input("if; then echo a; fi").is_err());
+        assert!(Parser::parse_input("echo a; fi").is_err());
+    }
+
     #[test]
     fn test_redirect() {
         let result = Parser::parse_input("echo hello > file").unwrap();
-        let cmd = &result.elements[0].pipeline.commands[0];
+        let cmd = first_simple(&result);
         assert_eq!(cmd.redirects.len(), 1);
         assert_eq!(cmd.redirects[0].kind, RedirectKind::Stdout);
     }
@@ -456,6 +2787,17 @@ mod tests {
         let result = Parser::parse_input("echo $(ls)").unwrap();
         assert_eq!(result.substitutions.len(), 1);
         assert_eq!(result.substitutions[0].input, "ls");
+
+        // ヒアドキュメントの本文も含める
+        let result =
+            Parser::parse_input("echo $(cat <<'EOF' <<-END\n$x\nEOF\n\tb\n\tEND\n)").unwrap();
+        assert_eq!(
+            result.substitutions[0].input,
+            "cat <<'EOF' <<-END\n$x\nEOF\nb\nEND"
+        );
+
+        // ダブルクォートの中で閉じていない $( はエラー
+        assert!(Parser::parse_input("echo \"$(ls\"").is_err());
     }
 
     #[test]
@@ -469,4 +2811,108 @@ mod tests {
         let result = Parser::parse_input("\n\necho hello\n\necho world\n").unwrap();
         assert_eq!(result.elements.len(), 2);
     }
+
+    /// エイリアスを展開した結果が、展開後のソースをパースした結果と等しいか
+    fn assert_aliased(input: &str, expected: &str) {
+        let aliases = [
+            ("ll", "ls -l"),
+            ("ls", "ls -F"),
+            ("sudo", "sudo "),
+            ("loop", "while true; do"),
+            ("a", "b"),
+            ("b", "a"),
+            ("nothing", ""),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let result = Parser::parse_input_with_aliases(input, aliases).unwrap();
+        assert_eq!(result, Parser::parse_input(expected).unwrap(), "{}", input);
+    }
+
+    #[test]
+    fn test_alias() {
+        assert_aliased("ll /tmp", "ls -F -l /tmp");
+        assert_aliased(
+            "cat x | ll && ll; ll &",
+            "cat x | ls -F -l && ls -F -l; ls -F -l &",
+        );
+        assert_aliased("if ll; then ll; fi", "if ls -F -l; then ls -F -l; fi");
+        assert_aliased("X=1 ll", "X=1 ls -F -l");
+        // コマンド位置以外やクォートされたワードは展開しない
+        assert_aliased("echo ll", "echo ll");
+        assert_aliased("'ll' \\ll ll''", "'ll' \\ll ll''");
+        assert_aliased("for ll in ll; do :; done", "for ll in ll; do :; done");
+        assert_aliased("ll() { :; }", "ll() { :; }");
+        // 値に予約語を含む
+        assert_aliased("loop echo; done", "while true; do echo; done");
+        assert_aliased("nothing ll", "ls -F -l");
+
+        // 値が空のエイリアスだけなら空のコマンドになる
+        let aliases = HashMap::from([("nothing".to_string(), String::new())]);
+        let result = Parser::parse_input_with_aliases("nothing", aliases).unwrap();
+        assert!(first_simple(&result).is_empty());
+    }
+
+    #[test]
+    fn test_alias_chaining_and_recursion() {
+        // 値が空白で終われば次のワードも展開する
+        assert_aliased("sudo ll", "sudo ls -F -l");
+        assert_aliased("sudo sudo ll", "sudo sudo ls -F -l");
+        assert_aliased("sudo echo ll", "sudo echo ll");
+        // 展開中のエイリアスは再び展開しない
+        assert_aliased("ls", "ls -F");
+        assert_aliased("a", "a");
+        assert_aliased("b; a", "b; a");
+    }
+
+    #[test]
+    fn test_alias_errors() {
+        let aliases = HashMap::from([("bad".to_string(), "echo 'x".to_string())]);
+        let err = Parser::parse_input_with_aliases("echo; bad", aliases).unwrap_err();
+        assert_eq!(err.position, 6);
+        assert!(err.incomplete.is_none());
+    }
+
+    #[test]
+    fn test_parse_next() {
+        let input = "a && b; c &\n\nif x\nthen y\nfi\ncat <<EOF\nbody\nEOF\n# comment\nd\n";
+        let (result, rest) = Parser::parse_next_with_aliases(input, HashMap::new()).unwrap();
+        assert_eq!(result, Parser::parse_input("a && b; c &").unwrap());
+        let (result, rest) = Parser::parse_next_with_aliases(rest, HashMap::new()).unwrap();
+        assert_eq!(result, Parser::parse_input("if x; then y; fi").unwrap());
+        let (result, rest) = Parser::parse_next_with_aliases(rest, HashMap::new()).unwrap();
+        assert_eq!(result.elements.len(), 1);
+        assert_eq!(rest, "# comment\nd\n");
+        let (result, rest) = Parser::parse_next_with_aliases(rest, HashMap::new()).unwrap();
+        assert_eq!(result, Parser::parse_input("d").unwrap());
+        assert_eq!(rest, "");
+
+        // 次の行は渡されたエイリアスで展開される
+        let aliases = HashMap::from([("ll".to_string(), "ls -l".to_string())]);
+        let (_, rest) =
+            Parser::parse_next_with_aliases("alias ll='ls -l'\nll\n", HashMap::new()).unwrap();
+        let (result, _) = Parser::parse_next_with_aliases(rest, aliases).unwrap();
+        assert_eq!(result, Parser::parse_input("ls -l").unwrap());
+
+        // 行の途中のエラーはその行で報告する
+        assert!(Parser::parse_next_with_aliases("a\nfi\n", HashMap::new()).is_ok());
+        assert!(Parser::parse_next_with_aliases("a; fi\nb\n", HashMap::new()).is_err());
+
+        // 次の行に続くコマンドはまとめてパースする
+        let (result, rest) =
+            Parser::parse_next_with_aliases("f()\n{ a\n}\nb\n", HashMap::new()).unwrap();
+        assert_eq!(result, Parser::parse_input("f() { a; }").unwrap());
+        assert_eq!(rest, "b\n");
+
+        // 後ろの行のトークン化エラーは、その行に進むまで報告しない
+        let input = "{ a\n}\n\ncat <<EOF\nx";
+        let (result, rest) = Parser::parse_next_with_aliases(input, HashMap::new()).unwrap();
+        assert_eq!(result, Parser::parse_input("{ a; }").unwrap());
+        assert_eq!(rest, "cat <<EOF\nx");
+        let err = Parser::parse_next_with_aliases(rest, HashMap::new()).unwrap_err();
+        assert_eq!(err.incomplete, Some(Incomplete::HereDoc("EOF".to_string())));
+        let (_, rest) = Parser::parse_next_with_aliases("a\necho 'b\n", HashMap::new()).unwrap();
+        assert!(Parser::parse_next_with_aliases(rest, HashMap::new()).is_err());
+    }
 }
@@ -0,0 +1,329 @@
+//! シェルのパターンマッチ（case 文やパス名展開で共用）
+//!
+//! `*`, `?`, `[...]`, `[!...]` と、`|` で区切られた選択肢をサポートする。
+//! `\` に続く文字はリテラルとして扱う。
+
+/// パターンにマッチするかどうか
+/// `|` で区切られた選択肢のいずれかにマッチすれば真
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let text: Vec<char> = text.chars().collect();
+    split_alternatives(pattern)
+        .iter()
+        .any(|alt| match_chars(alt, &text))
+}
+
+/// 選択肢を持たない単一のパターンにマッチするか（パス名展開で使う）
+pub fn matches_single(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    match_chars(&pattern, &text)
+}
+
+/// エスケープされていないワイルドカード（`*`, `?`, `[`）を含むか
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// `\` によるエスケープを取り除く
+pub fn unescape(pattern: &str) -> String {
+    let mut result = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// ワイルドカードと `\` をエスケープしてリテラルとして扱わせる
+pub fn escape(text: &str) -> String {
+    let mut result = String::new();
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '|' | '\\') {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result
+}
+
+/// トップレベルの `|` で選択肢に分割
+fn split_alternatives(pattern: &str) -> Vec<Vec<char>> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut alternatives = Vec::new();
+    let mut current = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '\\' if i + 1 < chars.len() => {
+                current.push(chars[i]);
+                current.push(chars[i + 1]);
+                i += 2;
+                continue;
+            }
+            // ブラケット内の | は文字クラスの一部
+            '[' => {
+                if let Some(end) = bracket_end(&chars, i) {
+                    current.extend_from_slice(&chars[i..end]);
+                    i = end;
+                    continue;
+                }
+                current.push('[');
+            }
+            '|' => alternatives.push(std::mem::take(&mut current)),
+            c => current.push(c),
+        }
+        i += 1;
+    }
+
+    alternatives.push(current);
+    alternatives
+}
+
+/// パターンとテキストを照合（`*` はバックトラックで処理）
+fn match_chars(pattern: &[char], text: &[char]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // 直近の `*` の位置と、その `*` がマッチを始めたテキスト位置
+    let mut star: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        let step = if p < pattern.len() {
+            match pattern[p] {
+                '*' => {
+                    star = Some((p, t));
+                    p += 1;
+                    continue;
+                }
+                '?' => Some(1),
+                '[' => match match_bracket(pattern, p, text[t]) {
+                    Some((true, end)) => Some(end - p),
+                    Some((false, _)) => None,
+                    // 閉じられていない [ はリテラル
+                    None => (text[t] == '[').then_some(1),
+                },
+                '\\' if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+                c => (c == text[t]).then_some(1),
+            }
+        } else {
+            None
+        };
+
+        match step {
+            Some(n) => {
+                p += n;
+                t += 1;
+            }
+            None => match star {
+                // 直前の * にもう 1 文字食わせてやり直す
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    // 残りのパターンが * だけならマッチ
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// `[` で始まるブラケット式の終端（`]` の次の位置）を探す
+fn bracket_end(pattern: &[char], start: usize) -> Option<usize> {
+    let mut i = start + 1;
+    if matches!(pattern.get(i), Some('!' | '^')) {
+        i += 1;
+    }
+    // 先頭の ] は文字として扱う
+    if pattern.get(i) == Some(&']') {
+        i += 1;
+    }
+
+    while i < pattern.len() {
+        match pattern[i] {
+            ']' => return Some(i + 1),
+            '\\' => i += 2,
+            '[' if pattern.get(i + 1) == Some(&':') => {
+                // [:class:] を読み飛ばす
+                let close = (i + 2..pattern.len().saturating_sub(1))
+                    .find(|&j| pattern[j] == ':' && pattern[j + 1] == ']');
+                i = close.map_or(i + 1, |j| j + 2);
+            }
+            _ => i += 1,
+        }
+    }
+
+    None
+}
+
+/// ブラケット式を 1 文字と照合
+/// 戻り値は (マッチしたか, ブラケット式の終端)。閉じられていなければ None
+fn match_bracket(pattern: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+    let end = bracket_end(pattern, start)?;
+    let mut i = start + 1;
+    let negate = matches!(pattern[i], '!' | '^');
+    if negate {
+        i += 1;
+    }
+
+    let mut matched = false;
+
+    // 先頭の ] も含めて、閉じ括弧の手前までを走査
+    while i < end - 1 {
+        let mut lo = pattern[i];
+
+        // 文字クラス [:alpha:] など
+        if lo == '[' && pattern.get(i + 1) == Some(&':') {
+            if let Some(close) =
+                (i + 2..end - 1).find(|&j| pattern[j] == ':' && pattern.get(j + 1) == Some(&']'))
+            {
+                let name: String = pattern[i + 2..close].iter().collect();
+                matched |= char_class(&name, c);
+                i = close + 2;
+                continue;
+            }
+        }
+
+        if lo == '\\' && i + 1 < end - 1 {
+            i += 1;
+            lo = pattern[i];
+        }
+
+        // 範囲指定 a-z
+        if pattern.get(i + 1) == Some(&'-') && i + 2 < end - 1 {
+            let mut hi = pattern[i + 2];
+            let mut next = i + 3;
+            if hi == '\\' && i + 3 < end - 1 {
+                hi = pattern[i + 3];
+                next += 1;
+            }
+            matched |= lo <= c && c <= hi;
+            i = next;
+        } else {
+            matched |= lo == c;
+            i += 1;
+        }
+    }
+
+    Some((matched != negate, end))
+}
+
+/// POSIX 文字クラス
+fn char_class(name: &str, c: char) -> bool {
+    match name {
+        "alpha" => c.is_alphabetic(),
+        "digit" => c.is_ascii_digit(),
+        "alnum" => c.is_alphanumeric(),
+        "upper" => c.is_uppercase(),
+        "lower" => c.is_lowercase(),
+        "space" => c.is_whitespace(),
+        "blank" => c == ' ' || c == '\t',
+        "punct" => c.is_ascii_punctuation(),
+        "xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_literal() {
+        assert_eq!(escape("a*b?[c]"), "a\\*b\\?\\[c\\]");
+        assert!(matches_single(&escape("*.md"), "*.md"));
+        assert!(!matches_single(&escape("*.md"), "a.md"));
+        assert!(!has_wildcards(&escape("[x]")));
+    }
+
+    #[test]
+    fn test_literal() {
+        assert!(matches("hello", "hello"));
+        assert!(!matches("hello", "hell"));
+        assert!(!matches("hello", "hello!"));
+        assert!(matches("", ""));
+    }
+
+    #[test]
+    fn test_star() {
+        assert!(matches("*", ""));
+        assert!(matches("*", "anything"));
+        assert!(matches("*.md", "README.md"));
+        assert!(!matches("*.md", "README.txt"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(matches("a*b*c", "abcbc"));
+        assert!(!matches("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn test_question() {
+        assert!(matches("?", "a"));
+        assert!(!matches("?", ""));
+        assert!(matches("log?.txt", "log1.txt"));
+        assert!(!matches("log?.txt", "log10.txt"));
+    }
+
+    #[test]
+    fn test_bracket() {
+        assert!(matches("[abc]", "b"));
+        assert!(!matches("[abc]", "d"));
+        assert!(matches("[a-z]x", "qx"));
+        assert!(!matches("[a-z]x", "Qx"));
+        assert!(matches("[!a-z]", "Q"));
+        assert!(!matches("[!a-z]", "q"));
+        assert!(matches("[^0-9]", "a"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[!]]", "a"));
+        assert!(matches("[[:digit:]][[:alpha:]]", "1a"));
+        assert!(!matches("[[:digit:]]", "a"));
+    }
+
+    #[test]
+    fn test_unclosed_bracket_is_literal() {
+        assert!(matches("[abc", "[abc"));
+        assert!(!matches("[abc", "a"));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+        assert!(matches("[\\]]", "]"));
+    }
+
+    #[test]
+    fn test_alternatives() {
+        assert!(matches("yes|y|Y", "y"));
+        assert!(matches("*.md|*.txt", "a.txt"));
+        assert!(!matches("*.md|*.txt", "a.rs"));
+        assert!(matches("[|]", "|"));
+        assert!(matches("a\\|b", "a|b"));
+        assert!(!matches("a\\|b", "a"));
+    }
+
+    #[test]
+    fn test_single_and_helpers() {
+        assert!(matches_single("a|b", "a|b"));
+        assert!(!matches_single("a|b", "a"));
+        assert!(has_wildcards("*.md"));
+        assert!(has_wildcards("file[0-9]"));
+        assert!(!has_wildcards("\\*.md"));
+        assert!(!has_wildcards("README.md"));
+        assert_eq!(unescape("a\\*b\\\\"), "a*b\\");
+    }
+}
@@ -95,6 +95,28 @@ function getPrompt(shell: ReturnType<typeof createShell>): string {
   return expandPS1(ps1, state);
 }
 
+/**
+ * 続きの行を読むときのプロンプト（PS2）
+ */
+function getContinuationPrompt(shell: ReturnType<typeof createShell>): string {
+  const state = shell.getState();
+  const ps2 = state.env.get('PS2') || '> ';
+  return expandPS1(ps2, state);
+}
+
+/**
+ * 候補の共通の先頭部分
+ */
+function commonPrefix(values: string[]): string {
+  let prefix = values[0] ?? '';
+  for (const value of values.slice(1)) {
+    while (!value.startsWith(prefix)) {
+      prefix = prefix.slice(0, -1);
+    }
+  }
+  return prefix;
+}
+
 type Props = {
   onClose: () => void;
 }
@@ -109,6 +131,8 @@ export function Terminal({ onClose }: Props) {
   const [historyIndex, setHistoryIndex] = useState(-1);
   const inputBufferRef = useRef('');
   const cursorPosRef = useRef(0);
+  // 途中で終わっている入力（続きの行を待っている間の前の行）
+  const pendingInputRef = useRef('');
 
   // inputBuffer と cursorPos を ref で追跡
   useEffect(() => {
@@ -210,12 +234,63 @@ export function Terminal({ onClose }: Props) {
 
     // 通常モード：コマンド入力
     if (data === '\n') {
-      // Enter: コマンド実行
+      // Enter: 入力が完結していればコマンド実行、途中なら PS2 で続きを読む
       ttyRef.current?.write('\n');
-      const cmd = inputBufferRef.current;
+      const cmd = pendingInputRef.current + inputBufferRef.current;
       setInputBuffer('');
       setCursorPos(0);
-      executeCommand(cmd);
+      shell.current.needsMoreInput(cmd).then((incomplete) => {
+        if (incomplete) {
+          pendingInputRef.current = cmd + '\n';
+          ttyRef.current?.write(getContinuationPrompt(shell.current));
+        } else {
+          pendingInputRef.current = '';
+          executeCommand(cmd);
+        }
+      });
+    } else if (data === '\t') {
+      // Tab: カーソル位置のワードを補完
+      const buf = inputBufferRef.current;
+      const pos = cursorPosRef.current;
+      shell.current.complete(buf, pos).then(({ start, end, candidates }) => {
+        if (candidates.length === 0 || inputBufferRef.current !== buf) {
+          return;
+        }
+        // 候補が 1 つなら確定（ディレクトリ以外は空白を付ける）、複数なら共通部分まで補完
+        const insert = candidates.length === 1
+          ? candidates[0].value + (candidates[0].kind === 'directory' ? '' : ' ')
+          : commonPrefix(candidates.map((c) => c.value));
+
+        if (insert.length <= end - start) {
+          // これ以上補完できなければ候補を一覧表示して入力行を描き直す
+          ttyRef.current?.write('\n' + candidates.map((c) => c.value).join('  ') + '\n');
+          if (pendingInputRef.current) {
+            ttyRef.current?.write(getContinuationPrompt(shell.current));
+          } else {
+            showPrompt();
+          }
+          ttyRef.current?.write(buf);
+          const afterCursorWidth = getDisplayWidth(buf.slice(pos));
+          if (afterCursorWidth > 0) {
+            ttyRef.current?.write('\x1b[D'.repeat(afterCursorWidth));
+          }
+          return;
+        }
+
+        const newBuf = buf.slice(0, start) + insert + buf.slice(end);
+        const newPos = start + insert.length;
+        setInputBuffer(newBuf);
+        setCursorPos(newPos);
+        // 置き換える範囲の先頭まで戻り、残りを再描画
+        const remaining = newBuf.slice(newPos);
+        const shrink = Math.max(0, getDisplayWidth(buf.slice(start)) - getDisplayWidth(newBuf.slice(start)));
+        ttyRef.current?.write(
+          '\b'.repeat(getDisplayWidth(buf.slice(start, pos))) +
+          newBuf.slice(start) +
+          ' '.repeat(shrink) +
+          '\b'.repeat(getDisplayWidth(remaining) + shrink)
+        );
+      });
     } else if (data === '\x7f') {
       // Backspace
       const pos = cursorPosRef.current;
@@ -317,6 +392,13 @@ export function Terminal({ onClose }: Props) {
       ttyRef.current?.write('\b \b'.repeat(bufWidth));
       setInputBuffer('');
       setCursorPos(0);
+    } else if (data === '\x03') {
+      // Ctrl+C: 続きの行を待っている入力も含めて破棄
+      pendingInputRef.current = '';
+      setInputBuffer('');
+      setCursorPos(0);
+      ttyRef.current?.write('^C\n');
+      showPrompt();
     } else if (data.length >= 1 && data[0] >= ' ') {
       // 通常の文字（複数文字対応）
       const buf = inputBufferRef.current;
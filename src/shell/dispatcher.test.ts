@@ -0,0 +1,295 @@
+import { describe, it, expect, vi, beforeEach } from 'vitest';
+import type {
+  ParseResult,
+  ParsedWord,
+  WordSegment,
+  Command,
+  Connector,
+  ConditionalElement,
+} from './shell-types';
+import type { ExpandState } from './shell-parser';
+
+// パース結果は入力ごとにテストで用意する（WASM のパーサーは使わない）
+// syntaxErrors の行は構文エラーになる
+const { parsed, syntaxErrors } = vi.hoisted(() => ({
+  parsed: new Map<string, ParseResult>(),
+  syntaxErrors: new Set<string>(),
+}));
+
+vi.mock('./wasm-executor', () => ({ executeWasmCommand: vi.fn() }));
+
+vi.mock('./shell-parser', () => {
+  // リテラル・変数・コマンド置換だけを扱う簡易的な展開
+  const expand = (word: ParsedWord, state: ExpandState, substitutions: Map<number, string>) =>
+    word.parts
+      .map(({ segment }) => {
+        switch (segment.tag) {
+          case 'literal':
+            return segment.val;
+          // 未設定ならオペランド中のコマンド置換の結果（${VAR:-$(cmd)} の簡略版）
+          case 'env-var':
+            return (
+              state.env.get(segment.val.name) ??
+              segment.val.substitutions.map((id) => substitutions.get(id) ?? '').join('')
+            );
+          case 'command-subst':
+            return substitutions.get(segment.val) ?? '';
+          default:
+            return '';
+        }
+      })
+      .join('');
+
+  return {
+    parseShellCommand: async (input: string) => ({
+      ok: true,
+      value: parsed.get(input) ?? { elements: [], substitutions: [], compounds: [], functions: [] },
+    }),
+    // 1 行ずつ返す（行全体がエイリアス名なら値に置き換える）
+    parseNextShellCommand: async (input: string, aliases: Map<string, string>) => {
+      const end = input.indexOf('\n');
+      const line = end === -1 ? input : input.slice(0, end);
+      if (syntaxErrors.has(line)) {
+        return {
+          ok: false,
+          error: { message: `syntax error: ${line}`, position: 0 },
+          incomplete: null,
+        };
+      }
+      return {
+        ok: true,
+        value: parsed.get(aliases.get(line) ?? line) ?? {
+          elements: [],
+          substitutions: [],
+          compounds: [],
+          functions: [],
+        },
+        rest: end === -1 ? '' : input.slice(end + 1),
+      };
+    },
+    formatParseError: (_input: string, error: { message: string }) => error.message,
+    expandWord: expand,
+    expandWords: (word: ParsedWord, state: ExpandState, substitutions: Map<number, string>) => [
+      { value: expand(word, state, substitutions), pattern: null },
+    ],
+    expandPattern: expand,
+    expandGlob: async (pattern: string) => [pattern],
+    matchPattern: async (pattern: string, text: string) => pattern === text,
+    completeShellInput: async () => ({ start: 0, end: 0, candidates: [] }),
+    ExpansionError: class extends Error {},
+  };
+});
+
+import { createShell } from './dispatcher';
+import { getFileSystem } from '../filesystem';
+
+// ヘルパー: AST の組み立て
+const span = { start: 0, end: 0 };
+
+function word(...segments: WordSegment[]): ParsedWord {
+  return { parts: segments.map((segment) => ({ segment, quoted: false })), span };
+}
+
+function literal(val: string): WordSegment {
+  return { tag: 'literal', val };
+}
+
+function envVar(name: string): WordSegment {
+  return { tag: 'env-var', val: { name, op: null, substitutions: [] } };
+}
+
+function simple(...args: ParsedWord[]): Command {
+  return { tag: 'simple', val: { args, redirects: [], span } };
+}
+
+function element(command: Command, connector: Connector = 'none'): ConditionalElement {
+  return { connector, pipeline: { commands: [command], span }, background: false, span };
+}
+
+describe('dispatcher', () => {
+  beforeEach(() => {
+    parsed.clear();
+    syntaxErrors.clear();
+  });
+
+  describe('command substitution', () => {
+    it('runs each time the word containing it is expanded', async () => {
+      parsed.set('pwd', {
+        elements: [element(simple(word(literal('pwd'))))],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      // for d in /tmp /bin; do cd $d; export SEEN=$SEEN$(pwd); done
+      parsed.set('loop', {
+        elements: [element({ tag: 'compound', val: 0 })],
+        substitutions: [{ id: 0, input: 'pwd' }],
+        compounds: [
+          {
+            kind: {
+              tag: 'for-clause',
+              val: {
+                variable: 'd',
+                words: [word(literal('/tmp')), word(literal('/bin'))],
+                body: [
+                  element(simple(word(literal('cd')), word(envVar('d')))),
+                  element(
+                    simple(
+                      word(literal('export')),
+                      word(literal('SEEN='), envVar('SEEN'), { tag: 'command-subst', val: 0 })
+                    ),
+                    'sequential'
+                  ),
+                ],
+              },
+            },
+            redirects: [],
+            span,
+          },
+        ],
+        functions: [],
+      });
+
+      const shell = createShell();
+      await shell.execute('loop');
+      expect(shell.getState().env.get('SEEN')).toBe('/tmp/bin');
+    });
+
+    it('runs substitutions inside parameter operands', async () => {
+      parsed.set('pwd', {
+        elements: [element(simple(word(literal('pwd'))))],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      // export OUT=${MISSING:-$(pwd)}
+      parsed.set('operand', {
+        elements: [
+          element(
+            simple(
+              word(literal('export')),
+              word(literal('OUT='), {
+                tag: 'env-var',
+                val: {
+                  name: 'MISSING',
+                  op: { tag: 'default', val: { colon: true, word: '$(pwd)' } },
+                  substitutions: [0],
+                },
+              })
+            )
+          ),
+        ],
+        substitutions: [{ id: 0, input: 'pwd' }],
+        compounds: [],
+        functions: [],
+      });
+
+      const shell = createShell();
+      await shell.execute('operand');
+      expect(shell.getState().env.get('OUT')).toBe(shell.getCwd());
+    });
+  });
+
+  describe('background jobs', () => {
+    it('run on a copy of the shell state', async () => {
+      // cd /tmp &
+      parsed.set('job', {
+        elements: [
+          { ...element(simple(word(literal('cd')), word(literal('/tmp')))), background: true },
+        ],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+
+      const shell = createShell();
+      const cwd = shell.getCwd();
+      const result = await shell.execute('job');
+      await new Promise((resolve) => setTimeout(resolve, 0));
+      expect(result.exitCode).toBe(0);
+      expect(shell.getCwd()).toBe(cwd);
+      expect(shell.getState().lastBackgroundPid).toBe(shell.getState().pid + 1);
+    });
+  });
+
+  describe('scripts', () => {
+    it('uses aliases defined on earlier lines', async () => {
+      parsed.set('source /tmp/script.sh', {
+        elements: [element(simple(word(literal('source')), word(literal('/tmp/script.sh'))))],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      parsed.set("alias greet='export GREETING=hi'", {
+        elements: [
+          element(simple(word(literal('alias')), word(literal('greet=export GREETING=hi')))),
+        ],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      parsed.set('export GREETING=hi', {
+        elements: [element(simple(word(literal('export')), word(literal('GREETING=hi'))))],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      const script = "alias greet='export GREETING=hi'\ngreet\n";
+      getFileSystem().writeFile('/tmp/script.sh', new TextEncoder().encode(script));
+
+      const shell = createShell();
+      const result = await shell.execute('source /tmp/script.sh');
+      expect(result.exitCode).toBe(0);
+      expect(shell.getState().env.get('GREETING')).toBe('hi');
+    });
+
+    it('runs the lines before a syntax error', async () => {
+      parsed.set('source /tmp/broken.sh', {
+        elements: [element(simple(word(literal('source')), word(literal('/tmp/broken.sh'))))],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      parsed.set('export FIRST=1', {
+        elements: [element(simple(word(literal('export')), word(literal('FIRST=1'))))],
+        substitutions: [],
+        compounds: [],
+        functions: [],
+      });
+      syntaxErrors.add('fi');
+      const script = 'export FIRST=1\nfi\n';
+      getFileSystem().writeFile('/tmp/broken.sh', new TextEncoder().encode(script));
+
+      const shell = createShell();
+      const result = await shell.execute('source /tmp/broken.sh');
+      expect(result.exitCode).toBe(1);
+      expect(result.stderr).toContain('syntax error: fi');
+      expect(shell.getState().env.get('FIRST')).toBe('1');
+    });
+  });
+
+  describe('redirects', () => {
+    it('reports errors on compound commands as the shell', async () => {
+      // { pwd; } < missing
+      parsed.set('group', {
+        elements: [element({ tag: 'compound', val: 0 })],
+        substitutions: [],
+        compounds: [
+          {
+            kind: { tag: 'brace-group', val: [element(simple(word(literal('pwd'))))] },
+            redirects: [
+              { fd: 0, kind: 'stdin', target: { tag: 'word', val: word(literal('missing')) }, span },
+            ],
+            span,
+          },
+        ],
+        functions: [],
+      });
+
+      const shell = createShell();
+      const result = await shell.execute('group');
+      expect(result.stderr).toBe('sh: missing: No such file or directory');
+      expect(result.exitCode).toBe(1);
+    });
+  });
+});
@@ -5,9 +5,49 @@
 
 import { getFileSystem } from '../filesystem';
 import { executeWasmCommand } from './wasm-executor';
-import { parseShellCommand, expandWord } from './shell-parser';
-import type { CommandInput, ShellState, ExecResult } from './types';
-import type { ParseResult, SimpleCommand, Pipeline } from './shell-types';
+import {
+  parseShellCommand,
+  parseNextShellCommand,
+  formatParseError,
+  expandWord,
+  expandWords,
+  expandPattern,
+  expandGlob,
+  matchPattern,
+  completeShellInput,
+  ExpansionError,
+} from './shell-parser';
+import type { CompletionRange } from './shell-parser';
+import type { CommandInput, ShellState, ShellFunction, ExecResult } from './types';
+import type {
+  ParseResult,
+  SimpleCommand,
+  Pipeline,
+  ConditionalElement,
+  Command,
+  CompoundCommand,
+  CompoundKind,
+  FunctionDef,
+  Redirect,
+  LoopControl,
+  ParsedWord,
+} from './shell-types';
+
+/**
+ * パース結果の実行中に参照する情報
+ */
+interface ExecContext {
+  /** コマンド置換の入力（id → コマンド文字列）。ワードを展開するたびに実行する */
+  substitutions: Map<number, string>;
+  /** 複合コマンドのテーブル */
+  compounds: CompoundCommand[];
+  /** 関数定義のテーブル */
+  functions: FunctionDef[];
+  /** 実行中のループの入れ子の深さ */
+  loopDepth: number;
+  /** 実行された break / continue（ループに届くまで保持） */
+  loopControl: LoopControl | null;
+}
 
 /**
  * ビルトインコマンドの定義
@@ -18,7 +58,7 @@ const builtinCommands: Record<
 > = {
   cd: (state, args) => {
     const fs = getFileSystem();
-    const target = args[1] ?? '/home/uzimaru0000';
+    const target = args[1] ?? state.env.get('HOME') ?? '/';
     const newPath = resolvePath(state.cwd, target);
 
     if (!fs.exists(newPath)) {
@@ -30,6 +70,8 @@ const builtinCommands: Record<
       return { stdout: '', stderr: `cd: ${target}: Not a directory`, exitCode: 1 };
     }
 
+    state.env.set('OLDPWD', state.cwd);
+    state.env.set('PWD', newPath);
     state.cwd = newPath;
     return { stdout: '', stderr: '', exitCode: 0 };
   },
@@ -343,77 +385,59 @@ const builtinCommands: Record<
     }
 
     const content = new TextDecoder().decode(readResult.val);
-    return await executeScript(content, state, scriptPath);
+    return await executeScript(content, state, scriptPath, args.slice(2));
   },
 };
 
 /**
- * エイリアス展開（循環参照検出付き）
+ * スクリプト内容を実行
  */
-function expandAlias(
-  name: string,
-  aliases: Map<string, string>,
-  seen: Set<string> = new Set()
-): { expanded: boolean; value?: string; error?: string } {
-  if (!aliases.has(name)) {
-    return { expanded: false };
-  }
-
-  if (seen.has(name)) {
-    return { expanded: false, error: `alias: circular reference detected: ${name}` };
+async function executeScript(
+  content: string,
+  state: ShellState,
+  sourceName?: string,
+  args?: string[]
+): Promise<ExecResult> {
+  // 引数付きで呼ばれた場合は実行中だけ $0 と位置パラメータを置き換える
+  if (!args) {
+    return executeScriptCommands(content, state);
   }
-
-  seen.add(name);
-  const expansion = aliases.get(name)!;
-
-  // 展開結果の最初の単語もエイリアスかチェック
-  const firstWord = expansion.split(/\s+/)[0];
-  if (aliases.has(firstWord) && firstWord !== name) {
-    const nested = expandAlias(firstWord, aliases, seen);
-    if (nested.error) {
-      return nested;
-    }
-    if (nested.expanded) {
-      const rest = expansion.slice(firstWord.length);
-      return { expanded: true, value: nested.value + rest };
-    }
+  const saved = { scriptName: state.scriptName, positional: state.positional };
+  state.scriptName = sourceName ?? state.scriptName;
+  state.positional = args;
+  try {
+    return await executeScriptCommands(content, state);
+  } finally {
+    state.scriptName = saved.scriptName;
+    state.positional = saved.positional;
   }
-
-  return { expanded: true, value: expansion };
 }
 
 /**
- * スクリプト内容を複数行パースして実行
+ * スクリプトを 1 コマンド（トップレベルの 1 行）ずつパースして実行
+ * 前の行で定義したエイリアスが次の行から使えるように、行ごとにエイリアスを渡し直す
  */
-async function executeScript(
-  content: string,
-  state: ShellState,
-  _sourceName?: string
-): Promise<ExecResult> {
-  // コメント行を除去（行頭が # で始まる行を空行に）
-  const processedContent = content
-    .split('\n')
-    .map((line) => {
-      const trimmed = line.trim();
-      if (trimmed.startsWith('#')) {
-        return '';
-      }
-      return line;
-    })
-    .join('\n');
-
-  // 複数行をまとめてパース
-  const parseResult = await parseShellCommand(processedContent);
+async function executeScriptCommands(content: string, state: ShellState): Promise<ExecResult> {
+  let stdout = '';
+  let stderr = '';
+  let exitCode = 0;
+  let rest = content;
+
+  while (rest) {
+    const parseResult = await parseNextShellCommand(rest, state.aliases);
+    if (!parseResult.ok) {
+      // 構文エラーの行に達したら、それまでの出力とともに終了する
+      return { stdout, stderr: stderr + formatParseError(rest, parseResult.error), exitCode: 1 };
+    }
 
-  if (!parseResult.ok) {
-    return {
-      stdout: '',
-      stderr: `parse error: ${parseResult.error.message}`,
-      exitCode: 1,
-    };
+    const result = await executeParseResult(parseResult.value, state);
+    stdout += result.stdout;
+    stderr += result.stderr;
+    exitCode = result.exitCode;
+    rest = parseResult.rest;
   }
 
-  return executeParseResult(parseResult.value, state);
+  return { stdout, stderr, exitCode };
 }
 
 /**
@@ -457,7 +481,7 @@ function detectShebang(content: string): string | null {
  */
 async function executeExecutableFile(
   cmdPath: string,
-  _args: string[],
+  args: string[],
   state: ShellState,
   _stdin?: string
 ): Promise<ExecResult> {
@@ -475,7 +499,7 @@ async function executeExecutableFile(
 
   // シェルスクリプトとして実行（shebang なし、または sh 系インタプリタ）
   if (!interpreter || interpreter === 'sh' || interpreter === '/bin/sh' || interpreter === '/usr/bin/sh') {
-    return await executeScript(content, state, cmdPath);
+    return await executeScript(content, state, cmdPath, args.slice(1));
   }
 
   // 他のインタプリタは未対応
@@ -638,29 +662,94 @@ async function dispatchExternalCommand(
   // PATH からシェルスクリプトを探す
   const script = findScriptInPath(cmd, state);
   if (script) {
-    return await executeScript(script.content, state, script.path);
+    return await executeScript(script.content, state, script.path, args.slice(1));
   }
 
   return { stdout: '', stderr: `${cmd}: command not found`, exitCode: 127 };
 }
 
+/**
+ * ワードに含まれるコマンド置換を実行し、id → 出力 の表を作る
+ * ループ本体などで同じワードを展開するたびに実行し直す
+ * ${VAR:-$(cmd)} などのオペランド中のコマンド置換も、演算子が使うかどうかに関わらず実行する
+ */
+async function runSubstitutions(
+  word: ParsedWord,
+  state: ShellState,
+  ctx: ExecContext
+): Promise<Map<number, string>> {
+  const ids = word.parts.flatMap(({ segment }) => {
+    switch (segment.tag) {
+      case 'command-subst':
+        return [segment.val];
+      case 'env-var':
+        return segment.val.substitutions;
+      default:
+        return [];
+    }
+  });
+
+  const outputs = new Map<number, string>();
+  for (const id of ids) {
+    const input = ctx.substitutions.get(id);
+    if (input === undefined) {
+      continue;
+    }
+    const result = await executeInput(input, state);
+    outputs.set(id, result.stdout.trimEnd());
+  }
+  return outputs;
+}
+
+/**
+ * ワードを 1 つの文字列に展開
+ */
+async function expandText(word: ParsedWord, state: ShellState, ctx: ExecContext): Promise<string> {
+  return expandWord(word, state, await runSubstitutions(word, state, ctx));
+}
+
+/**
+ * ワードを展開してフィールドに分割し、クォートされていないワイルドカードがあればパス名展開を行う
+ * マッチしなければ展開後の文字列をそのまま使う
+ */
+async function expandFields(word: ParsedWord, state: ShellState, ctx: ExecContext): Promise<string[]> {
+  const expanded: string[] = [];
+  const substitutions = await runSubstitutions(word, state, ctx);
+  for (const field of expandWords(word, state, substitutions)) {
+    if (field.pattern === null) {
+      expanded.push(field.value);
+      continue;
+    }
+    const matches = await expandGlob(field.pattern, state.cwd);
+    if (matches.length === 1 && matches[0] === field.pattern) {
+      expanded.push(field.value);
+    } else {
+      expanded.push(...matches);
+    }
+  }
+  return expanded;
+}
+
 /**
  * 単純コマンドを実行
  */
 async function executeSimpleCommand(
   cmd: SimpleCommand,
   state: ShellState,
-  substitutions: Map<number, string>,
+  ctx: ExecContext,
   stdin?: string
 ): Promise<ExecResult> {
-  // 引数を展開
-  const expandedArgs = cmd.args.map((word) => expandWord(word, state.env, substitutions));
+  // 引数を左から順に展開（コマンド置換の副作用の順序を保つ）
+  const expandedArgs: string[] = [];
+  for (const word of cmd.args) {
+    expandedArgs.push(...(await expandFields(word, state, ctx)));
+  }
 
   if (expandedArgs.length === 0) {
     return { stdout: '', stderr: '', exitCode: 0 };
   }
 
-  let cmdName = expandedArgs[0];
+  const cmdName = expandedArgs[0];
 
   // source / . コマンドの特殊処理（非同期のため）
   if (cmdName === 'source' || cmdName === '.') {
@@ -670,85 +759,444 @@ async function executeSimpleCommand(
     return await executeSourceCommand(state, expandedArgs[1]);
   }
 
-  // エイリアス展開
-  const aliasResult = expandAlias(cmdName, state.aliases);
-  if (aliasResult.error) {
-    return { stdout: '', stderr: aliasResult.error, exitCode: 1 };
+  // リダイレクト処理
+  const redirects = await resolveRedirects(cmd.redirects, state, ctx, cmdName, stdin);
+  if ('error' in redirects) {
+    return redirects.error;
   }
-  if (aliasResult.expanded) {
-    const aliasArgs = aliasResult.value!.split(/\s+/);
-    cmdName = aliasArgs[0];
-    expandedArgs.splice(0, 1, ...aliasArgs);
+  const finalStdin = redirects.plan.stdin;
+
+  // コマンド実行
+  let result: ExecResult;
+
+  const fn = state.functions.get(cmdName);
+  if (fn) {
+    result = await executeFunction(fn, expandedArgs, state, finalStdin);
+  } else if (cmdName in builtinCommands) {
+    result = await Promise.resolve(builtinCommands[cmdName](state, expandedArgs));
+  } else if (cmdName.startsWith('./') || cmdName.startsWith('/')) {
+    // パス形式のコマンド（./xxx または /xxx）を検出し、実行可能ファイルとして実行
+    const cmdPath = resolvePath(state.cwd, cmdName);
+    result = await executeExecutableFile(cmdPath, expandedArgs, state, finalStdin);
+  } else {
+    result = await dispatchExternalCommand(cmdName, expandedArgs, state, finalStdin);
   }
 
-  // リダイレクト処理
+  return writeRedirectedOutput(result, redirects.plan);
+}
+
+/**
+ * 出力先（stdout / stderr はリダイレクトされていない元の出力）
+ */
+type OutputTarget = { tag: 'stdout' } | { tag: 'stderr' } | { tag: 'file'; path: string };
+
+/**
+ * リダイレクトの解決結果
+ */
+interface RedirectPlan {
+  stdin: string | undefined;
+  /** ファイルディスクリプタごとの出力先 */
+  outputs: Map<number, OutputTarget>;
+  /** 書き込むファイル（パス → 追記するか） */
+  files: Map<string, boolean>;
+}
+
+/** 出力を捨てるパス */
+const DEV_NULL = '/dev/null';
+
+/**
+ * リダイレクトを解決する（入力リダイレクトはここでファイルを読み込む）
+ * 左から順に適用するので、2>&1 >file と >file 2>&1 は異なる結果になる
+ * cmdName はエラーメッセージに使うコマンド名（複合コマンドでは null）
+ */
+async function resolveRedirects(
+  redirects: Redirect[],
+  state: ShellState,
+  ctx: ExecContext,
+  cmdName: string | null,
+  stdin?: string
+): Promise<{ plan: RedirectPlan } | { error: ExecResult }> {
+  // 複合コマンドのエラーはシェル自身のエラーとして出す
+  const prefix = cmdName ?? 'sh';
+
   // stdin が undefined の場合はインタラクティブモードになるので、そのまま保持
-  let finalStdin: string | undefined = stdin;
-  let stdoutFile: string | null = null;
-  let stdoutAppend = false;
+  const plan: RedirectPlan = {
+    stdin,
+    outputs: new Map([
+      [1, { tag: 'stdout' }],
+      [2, { tag: 'stderr' }],
+    ]),
+    files: new Map(),
+  };
 
-  for (const redirect of cmd.redirects) {
-    const target = expandWord(redirect.target, state.env, substitutions);
+  for (const redirect of redirects) {
+    if (redirect.target.tag === 'fd') {
+      // 入力の複製は標準入力しか扱わないので何もしない
+      if (redirect.kind === 'stdin') {
+        continue;
+      }
+      const source = plan.outputs.get(redirect.target.val);
+      if (!source) {
+        return {
+          error: { stdout: '', stderr: `${prefix}: ${redirect.target.val}: Bad file descriptor`, exitCode: 1 },
+        };
+      }
+      plan.outputs.set(redirect.fd, source);
+      continue;
+    }
+
+    const target = await expandText(redirect.target.val, state, ctx);
     switch (redirect.kind) {
-      case 'stdin': {
+      case 'stdin':
+      case 'read-write': {
+        // 標準入力以外への入力リダイレクトは扱わない
+        if (redirect.fd !== 0) {
+          break;
+        }
+        if (target === DEV_NULL) {
+          plan.stdin = '';
+          break;
+        }
         const fs = getFileSystem();
         const path = resolvePath(state.cwd, target);
         const readResult = fs.readFile(path);
         if (readResult.tag === 'ok') {
-          finalStdin = new TextDecoder().decode(readResult.val);
+          plan.stdin = new TextDecoder().decode(readResult.val);
+        } else if (redirect.kind === 'read-write') {
+          // <> は存在しないファイルを作成する
+          fs.writeFile(path, new Uint8Array());
+          plan.stdin = '';
         } else {
-          return { stdout: '', stderr: `${cmdName}: ${target}: No such file or directory`, exitCode: 1 };
+          return {
+            error: { stdout: '', stderr: `${prefix}: ${target}: No such file or directory`, exitCode: 1 },
+          };
         }
         break;
       }
       case 'stdout':
-        stdoutFile = target;
-        stdoutAppend = false;
+      case 'stdout-append': {
+        if (target === DEV_NULL) {
+          plan.outputs.set(redirect.fd, { tag: 'file', path: DEV_NULL });
+          break;
+        }
+        const path = resolvePath(state.cwd, target);
+        plan.outputs.set(redirect.fd, { tag: 'file', path });
+        if (!plan.files.has(path)) {
+          plan.files.set(path, redirect.kind === 'stdout-append');
+        }
+        break;
+      }
+      case 'here-doc':
+        if (redirect.fd === 0) {
+          plan.stdin = target;
+        }
         break;
-      case 'stdout-append':
-        stdoutFile = target;
-        stdoutAppend = true;
+      case 'here-string':
+        if (redirect.fd === 0) {
+          plan.stdin = `${target}\n`;
+        }
         break;
     }
   }
 
-  // コマンド実行
-  let result: ExecResult;
+  return { plan };
+}
 
-  if (cmdName in builtinCommands) {
-    result = await Promise.resolve(builtinCommands[cmdName](state, expandedArgs));
-  } else if (cmdName.startsWith('./') || cmdName.startsWith('/')) {
-    // パス形式のコマンド（./xxx または /xxx）を検出し、実行可能ファイルとして実行
-    const cmdPath = resolvePath(state.cwd, cmdName);
-    result = await executeExecutableFile(cmdPath, expandedArgs, state, finalStdin);
-  } else {
-    result = await dispatchExternalCommand(cmdName, expandedArgs, state, finalStdin);
-  }
+/**
+ * リダイレクトに従って stdout / stderr を振り分け、ファイルに書き出す
+ */
+function writeRedirectedOutput(result: ExecResult, plan: RedirectPlan): ExecResult {
+  let stdout = '';
+  let stderr = '';
+  const contents = new Map<string, string>();
+
+  const route = (output: string, fd: number) => {
+    const target = plan.outputs.get(fd);
+    switch (target?.tag) {
+      case 'stdout':
+        stdout += output;
+        break;
+      case 'stderr':
+        stderr += output;
+        break;
+      case 'file':
+        if (target.path !== DEV_NULL) {
+          contents.set(target.path, (contents.get(target.path) ?? '') + output);
+        }
+        break;
+    }
+  };
+  route(result.stdout, 1);
+  route(result.stderr, 2);
 
-  // stdout リダイレクト処理
-  if (stdoutFile) {
-    const fs = getFileSystem();
-    const path = resolvePath(state.cwd, stdoutFile);
-    const content = new TextEncoder().encode(result.stdout);
-
-    if (stdoutAppend) {
-      const existing = fs.readFile(path);
-      if (existing.tag === 'ok') {
-        const combined = new Uint8Array(existing.val.length + content.length);
-        combined.set(existing.val);
-        combined.set(content, existing.val.length);
-        fs.writeFile(path, combined);
-      } else {
-        fs.writeFile(path, content);
-      }
+  // 出力がなくてもリダイレクト先のファイルは作成する
+  const fs = getFileSystem();
+  for (const [path, append] of plan.files) {
+    const content = new TextEncoder().encode(contents.get(path) ?? '');
+    const existing = append ? fs.readFile(path) : null;
+    if (existing && existing.tag === 'ok') {
+      const combined = new Uint8Array(existing.val.length + content.length);
+      combined.set(existing.val);
+      combined.set(content, existing.val.length);
+      fs.writeFile(path, combined);
     } else {
       fs.writeFile(path, content);
     }
+  }
+
+  return { ...result, stdout, stderr };
+}
+
+/**
+ * シェル関数を呼び出す
+ */
+async function executeFunction(
+  fn: ShellFunction,
+  args: string[],
+  state: ShellState,
+  stdin?: string
+): Promise<ExecResult> {
+  const ctx: ExecContext = {
+    substitutions: fn.substitutions,
+    compounds: fn.compounds,
+    functions: fn.functions,
+    loopDepth: 0,
+    loopControl: null,
+  };
 
-    result = { ...result, stdout: '' };
+  // 呼び出し中は引数を位置パラメータにする
+  const savedPositional = state.positional;
+  state.positional = args.slice(1);
+  try {
+    return await executeCompoundCommand(fn.compounds[fn.body], state, ctx, stdin);
+  } finally {
+    state.positional = savedPositional;
   }
+}
 
-  return result;
+/**
+ * 複合コマンドを実行（付随するリダイレクトは全体に適用する）
+ */
+async function executeCompoundCommand(
+  compound: CompoundCommand,
+  state: ShellState,
+  ctx: ExecContext,
+  stdin?: string
+): Promise<ExecResult> {
+  const redirects = await resolveRedirects(compound.redirects, state, ctx, null, stdin);
+  if ('error' in redirects) {
+    return redirects.error;
+  }
+
+  const result = await executeCompoundKind(compound.kind, state, ctx, redirects.plan.stdin);
+  return writeRedirectedOutput(result, redirects.plan);
+}
+
+/**
+ * 複合コマンドの本体を実行
+ */
+async function executeCompoundKind(
+  compound: CompoundKind,
+  state: ShellState,
+  ctx: ExecContext,
+  stdin?: string
+): Promise<ExecResult> {
+  let stdout = '';
+  let stderr = '';
+
+  switch (compound.tag) {
+    case 'if-clause': {
+      for (const branch of compound.val.branches) {
+        const condition = await executeElements(branch.condition, state, ctx, stdin);
+        stdout += condition.stdout;
+        stderr += condition.stderr;
+
+        if (ctx.loopControl) {
+          return { stdout, stderr, exitCode: condition.exitCode };
+        }
+
+        if (condition.exitCode === 0) {
+          const body = await executeElements(branch.body, state, ctx, stdin);
+          return { stdout: stdout + body.stdout, stderr: stderr + body.stderr, exitCode: body.exitCode };
+        }
+      }
+
+      if (compound.val.elseBody) {
+        const body = await executeElements(compound.val.elseBody, state, ctx, stdin);
+        return { stdout: stdout + body.stdout, stderr: stderr + body.stderr, exitCode: body.exitCode };
+      }
+
+      // どの節も実行されなかった場合の終了ステータスは 0
+      return { stdout, stderr, exitCode: 0 };
+    }
+
+    case 'loop-clause': {
+      const { kind, condition, body } = compound.val;
+      let exitCode = 0;
+
+      ctx.loopDepth++;
+      try {
+        for (;;) {
+          const cond = await executeElements(condition, state, ctx, stdin);
+          stdout += cond.stdout;
+          stderr += cond.stderr;
+          if (takeLoopControl(ctx) === 'break') break;
+
+          const succeeded = cond.exitCode === 0;
+          if (kind === 'while' ? !succeeded : succeeded) break;
+
+          const result = await executeElements(body, state, ctx, stdin);
+          stdout += result.stdout;
+          stderr += result.stderr;
+          exitCode = result.exitCode;
+          if (takeLoopControl(ctx) === 'break') break;
+        }
+      } finally {
+        ctx.loopDepth--;
+      }
+
+      return { stdout, stderr, exitCode };
+    }
+
+    case 'for-clause': {
+      const { variable, words, body } = compound.val;
+      let exitCode = 0;
+
+      // ワードを展開（フィールド分割とパス名展開を行う）
+      const values: string[] = [];
+      if (!words) {
+        values.push(...state.positional);
+      }
+      for (const word of words ?? []) {
+        values.push(...(await expandFields(word, state, ctx)));
+      }
+
+      ctx.loopDepth++;
+      try {
+        for (const value of values) {
+          state.env.set(variable, value);
+
+          const result = await executeElements(body, state, ctx, stdin);
+          stdout += result.stdout;
+          stderr += result.stderr;
+          exitCode = result.exitCode;
+          if (takeLoopControl(ctx) === 'break') break;
+        }
+      } finally {
+        ctx.loopDepth--;
+      }
+
+      return { stdout, stderr, exitCode };
+    }
+
+    case 'case-clause': {
+      const value = await expandText(compound.val.word, state, ctx);
+
+      for (const arm of compound.val.arms) {
+        for (const pattern of arm.patterns) {
+          const expanded = expandPattern(pattern, state, await runSubstitutions(pattern, state, ctx));
+          if (await matchPattern(expanded, value)) {
+            return executeElements(arm.body, state, ctx, stdin);
+          }
+        }
+      }
+
+      // どのパターンにもマッチしなかった場合の終了ステータスは 0
+      return { stdout, stderr, exitCode: 0 };
+    }
+
+    case 'brace-group':
+      return executeElements(compound.val, state, ctx, stdin);
+
+    case 'subshell': {
+      // サブシェル内での cd や変数の変更は呼び出し元に影響させない
+      const subCtx: ExecContext = { ...ctx, loopDepth: 0, loopControl: null };
+      return executeElements(compound.val, forkState(state), subCtx, stdin);
+    }
+  }
+}
+
+/**
+ * サブシェルやバックグラウンドジョブ用にシェルの状態を複製する
+ */
+function forkState(state: ShellState): ShellState {
+  return {
+    ...state,
+    env: new Map(state.env),
+    aliases: new Map(state.aliases),
+    functions: new Map(state.functions),
+    positional: [...state.positional],
+  };
+}
+
+/**
+ * ループ本体の実行後に break / continue を処理する
+ * このループが対象であれば消費して種別を返し、外側のループが対象なら null を返して伝播させる
+ */
+function takeLoopControl(ctx: ExecContext): 'break' | 'continue' | null {
+  const control = ctx.loopControl;
+  if (!control) {
+    return null;
+  }
+  if (control.depth > 1) {
+    ctx.loopControl = { ...control, depth: control.depth - 1 };
+    return 'break';
+  }
+  ctx.loopControl = null;
+  return control.kind;
+}
+
+/**
+ * コマンド（単純コマンドまたは複合コマンド）を実行
+ */
+async function executeCommand(
+  command: Command,
+  state: ShellState,
+  ctx: ExecContext,
+  stdin?: string
+): Promise<ExecResult> {
+  try {
+    return await dispatchCommand(command, state, ctx, stdin);
+  } catch (e) {
+    // 展開エラーはそのコマンドだけを失敗させる
+    if (e instanceof ExpansionError) {
+      return { stdout: '', stderr: `sh: ${e.message}`, exitCode: 1 };
+    }
+    throw e;
+  }
+}
+
+/**
+ * コマンドの種別ごとに実行
+ */
+async function dispatchCommand(
+  command: Command,
+  state: ShellState,
+  ctx: ExecContext,
+  stdin?: string
+): Promise<ExecResult> {
+  switch (command.tag) {
+    case 'simple':
+      return executeSimpleCommand(command.val, state, ctx, stdin);
+    case 'compound':
+      return executeCompoundCommand(ctx.compounds[command.val], state, ctx, stdin);
+    case 'loop-control':
+      // ループ外では何もしない。深さは実行中のループ数に切り詰める
+      if (ctx.loopDepth > 0) {
+        ctx.loopControl = { ...command.val, depth: Math.min(command.val.depth, ctx.loopDepth) };
+      }
+      return { stdout: '', stderr: '', exitCode: 0 };
+    case 'function-def': {
+      const def = ctx.functions[command.val];
+      state.functions.set(def.name, {
+        body: def.body,
+        compounds: ctx.compounds,
+        functions: ctx.functions,
+        substitutions: ctx.substitutions,
+      });
+      return { stdout: '', stderr: '', exitCode: 0 };
+    }
+  }
 }
 
 /**
@@ -757,7 +1205,8 @@ async function executeSimpleCommand(
 async function executePipeline(
   pipeline: Pipeline,
   state: ShellState,
-  substitutions: Map<number, string>
+  ctx: ExecContext,
+  initialStdin?: string
 ): Promise<ExecResult> {
   const { commands } = pipeline;
 
@@ -766,17 +1215,17 @@ async function executePipeline(
   }
 
   if (commands.length === 1) {
-    return executeSimpleCommand(commands[0], state, substitutions);
+    return executeCommand(commands[0], state, ctx, initialStdin);
   }
 
   // パイプライン実行
-  let stdin = '';
+  let stdin = initialStdin ?? '';
   let lastResult: ExecResult = { stdout: '', stderr: '', exitCode: 0 };
   let accumulatedStderr = '';
 
   for (let i = 0; i < commands.length; i++) {
     const isLast = i === commands.length - 1;
-    lastResult = await executeSimpleCommand(commands[i], state, substitutions, stdin);
+    lastResult = await executeCommand(commands[i], state, ctx, stdin);
     accumulatedStderr += lastResult.stderr;
     stdin = lastResult.stdout;
 
@@ -799,21 +1248,34 @@ async function executeParseResult(
   parseResult: ParseResult,
   state: ShellState
 ): Promise<ExecResult> {
-  // コマンド置換を解決
-  const substitutions = new Map<number, string>();
-
-  for (const subst of parseResult.substitutions) {
-    // 再帰的にコマンドを実行
-    const substResult = await executeInput(subst.input, state);
-    substitutions.set(subst.id, substResult.stdout.trimEnd());
-  }
+  // コマンド置換は含まれるワードを展開するときに実行する
+  const ctx: ExecContext = {
+    substitutions: new Map(parseResult.substitutions.map(({ id, input }) => [id, input])),
+    compounds: parseResult.compounds,
+    functions: parseResult.functions,
+    loopDepth: 0,
+    loopControl: null,
+  };
+  return executeElements(parseResult.elements, state, ctx);
+}
 
+/**
+ * 条件要素の列を実行
+ */
+async function executeElements(
+  elements: ConditionalElement[],
+  state: ShellState,
+  ctx: ExecContext,
+  stdin?: string
+): Promise<ExecResult> {
   // 条件分岐を評価しながら実行
   let lastExitCode = 0;
   let accumulatedStdout = '';
   let accumulatedStderr = '';
 
-  for (const element of parseResult.elements) {
+  for (let i = 0; i < elements.length; i++) {
+    const element = elements[i];
+
     // 接続子に基づいてスキップ判定
     if (element.connector === 'and' && lastExitCode !== 0) {
       continue;
@@ -822,10 +1284,39 @@ async function executeParseResult(
       continue;
     }
 
-    const result = await executePipeline(element.pipeline, state, substitutions);
+    if (element.background) {
+      // & で終わる AND-OR リストをまとめて取り出す
+      let end = i + 1;
+      while (
+        end < elements.length &&
+        elements[end].background &&
+        (elements[end].connector === 'and' || elements[end].connector === 'or')
+      ) {
+        end++;
+      }
+      const job = elements.slice(i, end).map((e) => ({ ...e, background: false }));
+      i = end - 1;
+
+      // 完了を待たずにサブシェルとして実行する（stdin は空、出力とエラーは端末に戻せないため破棄）
+      const jobCtx: ExecContext = { ...ctx, loopDepth: 0, loopControl: null };
+      executeElements(job, forkState(state), jobCtx, '').catch(() => {});
+      lastExitCode = 0;
+      state.lastExitCode = 0;
+      // プロセスはないため、$! には pid から連番で振った仮の ID を入れる
+      state.lastBackgroundPid = (state.lastBackgroundPid ?? state.pid) + 1;
+      continue;
+    }
+
+    const result = await executePipeline(element.pipeline, state, ctx, stdin);
     lastExitCode = result.exitCode;
+    state.lastExitCode = result.exitCode;
     accumulatedStdout += result.stdout;
     accumulatedStderr += result.stderr;
+
+    // break / continue が実行されたら残りのコマンドは飛ばす
+    if (ctx.loopControl) {
+      break;
+    }
   }
 
   return {
@@ -845,12 +1336,12 @@ async function executeInput(input: string, state: ShellState): Promise<ExecResul
   }
 
   // シェルパーサーでパース
-  const parseResult = await parseShellCommand(trimmed);
+  const parseResult = await parseShellCommand(trimmed, state.aliases);
 
   if (!parseResult.ok) {
     return {
       stdout: '',
-      stderr: `parse error: ${parseResult.error.message}`,
+      stderr: formatParseError(trimmed, parseResult.error),
       exitCode: 1,
     };
   }
@@ -863,6 +1354,8 @@ async function executeInput(input: string, state: ShellState): Promise<ExecResul
  */
 export function createShell(): {
   execute: (input: string) => Promise<ExecResult>;
+  needsMoreInput: (input: string) => Promise<boolean>;
+  complete: (line: string, cursor: number) => Promise<CompletionRange>;
   getCwd: () => string;
   getState: () => ShellState;
   initialize: () => Promise<ExecResult>;
@@ -872,9 +1365,16 @@ export function createShell(): {
     env: new Map([
       ['USER', 'uzimaru0000'],
       ['HOME', '/home/uzimaru0000'],
+      ['PWD', '/home/uzimaru0000'],
       ['PATH', '/bin'],
     ]),
     aliases: new Map(),
+    functions: new Map(),
+    positional: [],
+    scriptName: 'sh',
+    lastExitCode: 0,
+    pid: 1,
+    lastBackgroundPid: null,
   };
 
   let initialized = false;
@@ -902,8 +1402,51 @@ export function createShell(): {
     return executeInput(input, state);
   }
 
+  /**
+   * 入力が途中で終わっていて、続きの行が必要か
+   */
+  async function needsMoreInput(input: string): Promise<boolean> {
+    const result = await parseShellCommand(input, state.aliases);
+    return !result.ok && result.incomplete !== null;
+  }
+
+  /**
+   * カーソル位置（文字列のインデックス）のワードを補完
+   */
+  function complete(line: string, cursor: number): Promise<CompletionRange> {
+    const fs = getFileSystem();
+    return completeShellInput(line, cursor, {
+      commands: () => {
+        const names = [...Object.keys(builtinCommands), ...state.functions.keys()];
+        const paths = (state.env.get('PATH') ?? '/bin').split(':').filter(Boolean);
+        for (const dir of paths) {
+          const result = fs.listDir(dir);
+          if (result.tag === 'err') {
+            continue;
+          }
+          for (const entry of result.val) {
+            // PATH 上の WASM コマンドとシェルスクリプト
+            const match = /^(.+)\.(wasm|sh)$/.exec(entry.name);
+            if (!entry.isDir && match) {
+              names.push(match[1]);
+            }
+          }
+        }
+        return names;
+      },
+      listDir: (dir) => {
+        const result = fs.listDir(resolvePath(state.cwd, dir || '.'));
+        return result.tag === 'ok' ? result.val : [];
+      },
+      envNames: () => [...state.env.keys()],
+      aliases: () => [...state.aliases.keys()],
+    });
+  }
+
   return {
     execute,
+    needsMoreInput,
+    complete,
     getCwd: () => state.cwd,
     getState: () => state,
     initialize,
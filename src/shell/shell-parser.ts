@@ -3,16 +3,28 @@
  */
 
 import { getFileSystem } from '../filesystem';
+import type { FileEntry, FsError, Result } from '../filesystem/types';
 import { WASIShim } from '@bytecodealliance/preview2-shim/instantiation';
 import type {
   ShellParserModule,
   ParseResult,
+  NextCommand,
   ParseError,
+  ParseFailure,
+  IncompleteReason,
   ParsedWord,
+  Field,
+  ExpandContext,
+  HighlightKind,
+  Candidate,
 } from './shell-types';
+import type { ShellState } from './types';
 
 let shellParserModule: ShellParserModule | null = null;
 
+/** 補完候補の取得元（completeShellInput の呼び出し中だけ設定する） */
+let completionSource: CompletionSource | null = null;
+
 /**
  * WASI imports を取得（シェルパーサーは I/O を使わないが、WASI shim は必要）
  */
@@ -21,6 +33,46 @@ async function getWasiImports(): Promise<Record<string, unknown>> {
   return shim.getImportObject();
 }
 
+/**
+ * uzimaru:filesystem の imports を取得（パス名展開でディレクトリ一覧を読む）
+ * jco は result の err を例外の payload として受け取る
+ */
+function getFilesystemImports(): Record<string, unknown> {
+  const fs = getFileSystem();
+  const unwrap = <T>(result: Result<T, FsError>): T => {
+    if (result.tag === 'err') {
+      throw { payload: result.val };
+    }
+    return result.val;
+  };
+
+  return {
+    'uzimaru:filesystem/filesystem': {
+      listDir: (path: string) => unwrap(fs.listDir(path)),
+      readFile: (path: string) => unwrap(fs.readFile(path)),
+      writeFile: (path: string, data: Uint8Array) => unwrap(fs.writeFile(path, data)),
+      mkdir: (path: string) => unwrap(fs.mkdir(path)),
+      remove: (path: string) => unwrap(fs.remove(path)),
+      stat: (path: string) => unwrap(fs.stat(path)),
+      exists: (path: string) => fs.exists(path),
+    },
+  };
+}
+
+/**
+ * uzimaru:shell/completion-source の imports を取得（補完中の取得元に委譲する）
+ */
+function getCompletionImports(): Record<string, unknown> {
+  return {
+    'uzimaru:shell/completion-source': {
+      commands: () => completionSource?.commands() ?? [],
+      listDir: (dir: string) => completionSource?.listDir(dir) ?? [],
+      envNames: () => completionSource?.envNames() ?? [],
+      aliases: () => completionSource?.aliases() ?? [],
+    },
+  };
+}
+
 /**
  * シェルパーサー WASM をロード
  */
@@ -82,7 +134,11 @@ export async function loadShellParser(): Promise<ShellParserModule> {
 
     // WASI imports を提供してインスタンス化
     const wasiImports = await getWasiImports();
-    const instance = await module.instantiate(getCoreModule, wasiImports);
+    const instance = await module.instantiate(getCoreModule, {
+      ...wasiImports,
+      ...getFilesystemImports(),
+      ...getCompletionImports(),
+    });
 
     shellParserModule = instance as ShellParserModule;
     return shellParserModule;
@@ -92,80 +148,265 @@ export async function loadShellParser(): Promise<ShellParserModule> {
 }
 
 /**
- * チルダ展開を行う
- * ~ → $HOME
- * ~/path → $HOME/path
+ * ワードの展開に失敗した（コマンドは実行されない）
+ */
+export class ExpansionError extends Error {}
+
+/**
+ * 展開時に参照するシェルの状態
+ */
+export type ExpandState = Pick<
+  ShellState,
+  'env' | 'positional' | 'scriptName' | 'lastExitCode' | 'pid' | 'lastBackgroundPid'
+>;
+
+/**
+ * WASM の展開関数に渡すコンテキストを作成
+ */
+function toExpandContext(state: ExpandState, substitutions: Map<number, string>): ExpandContext {
+  return {
+    env: [...state.env],
+    arg0: state.scriptName,
+    positional: state.positional,
+    lastStatus: state.lastExitCode,
+    pid: state.pid,
+    lastBackgroundPid: state.lastBackgroundPid,
+    substitutions: [...substitutions],
+  };
+}
+
+/**
+ * WASM の展開関数を呼び出し、${VAR:=word} や算術式の代入を env に反映する
+ * パース済みのワードを展開するので、パーサーはロード済みのはず
  */
-function expandTilde(value: string, home: string): string {
-  if (value === '~') {
-    return home;
+function callExpander<T extends { assignments: [string, string][] }>(
+  state: ExpandState,
+  expand: (expander: ShellParserModule['expander']) => T
+): T {
+  if (!shellParserModule) {
+    throw new ExpansionError('shell parser is not loaded');
   }
-  if (value.startsWith('~/')) {
-    return home + value.slice(1);
+  let result: T;
+  try {
+    result = expand(shellParserModule.expander);
+  } catch (e) {
+    const error = e as { payload?: string; message?: string };
+    throw new ExpansionError(error.payload ?? error.message);
   }
-  return value;
+  for (const [name, value] of result.assignments) {
+    state.env.set(name, value);
+  }
+  return result;
 }
 
 /**
- * パース結果からワードを展開
+ * パース結果からワードを 1 つの文字列に展開
  */
 export function expandWord(
   word: ParsedWord,
-  env: Map<string, string>,
+  state: ExpandState,
   substitutions: Map<number, string>
 ): string {
-  const home = env.get('HOME') ?? '/home/uzimaru0000';
-  const segments = word.segments;
-
-  // 最初のセグメントがリテラルでチルダから始まる場合は展開
-  const expanded = segments.map((seg, index) => {
-    switch (seg.tag) {
-      case 'literal':
-        // 最初のセグメントのみチルダ展開を適用
-        if (index === 0) {
-          return expandTilde(seg.val, home);
-        }
-        return seg.val;
-      case 'env-var':
-        return env.get(seg.val.name) ?? seg.val.defaultValue ?? '';
-      case 'command-subst':
-        return substitutions.get(seg.val) ?? '';
-      default:
-        return '';
-    }
-  });
+  return callExpander(state, (expander) =>
+    expander.expandWord(word, toExpandContext(state, substitutions))
+  ).value;
+}
 
-  return expanded.join('');
+/**
+ * パース結果からワードを展開し、IFS でフィールドに分割する
+ * クォートされていない空の展開はフィールドを作らない
+ */
+export function expandWords(
+  word: ParsedWord,
+  state: ExpandState,
+  substitutions: Map<number, string>
+): Field[] {
+  return callExpander(state, (expander) =>
+    expander.expandWords(word, toExpandContext(state, substitutions))
+  ).fields;
 }
 
 /**
- * シェルコマンドをパース
+ * パース結果からワードを case 文のパターンに展開する
+ * クォートされていた部分はエスケープされ、リテラルとしてマッチする
  */
-export async function parseShellCommand(
-  input: string
-): Promise<{ ok: true; value: ParseResult } | { ok: false; error: ParseError }> {
+export function expandPattern(
+  word: ParsedWord,
+  state: ExpandState,
+  substitutions: Map<number, string>
+): string {
+  return callExpander(state, (expander) =>
+    expander.expandPattern(word, toExpandContext(state, substitutions))
+  ).value;
+}
+
+/**
+ * シェルパターン（case 文のパターン）にマッチするか
+ */
+export async function matchPattern(pattern: string, text: string): Promise<boolean> {
+  const parser = await loadShellParser();
+  return parser.pattern.matchPattern(pattern, text);
+}
+
+/**
+ * パス名展開（マッチしなければパターンをそのまま返す）
+ */
+export async function expandGlob(pattern: string, cwd: string): Promise<string[]> {
+  if (!/[*?[]/.test(pattern)) {
+    return [pattern];
+  }
+  const parser = await loadShellParser();
+  return parser.glob.expandGlob(pattern, cwd);
+}
+
+/**
+ * パース結果を正規化したソースに整形
+ */
+export async function formatShellAst(ast: ParseResult): Promise<string> {
+  const parser = await loadShellParser();
+  return parser.formatter.format(ast);
+}
+
+/**
+ * UTF-8 のバイト位置を文字列のインデックスに直す関数を作る
+ */
+function byteOffsetToIndex(input: string): (position: number) => number {
+  const bytes = new TextEncoder().encode(input);
+  return (position) => new TextDecoder().decode(bytes.slice(0, position)).length;
+}
+
+/** ハイライトの範囲（start, end は文字列のインデックス） */
+export interface HighlightRange {
+  kind: HighlightKind;
+  start: number;
+  end: number;
+}
+
+/**
+ * 入力をシンタックスハイライト用に分類（不完全・不正な入力でも途中まで分類する）
+ */
+export async function highlightShellInput(input: string): Promise<HighlightRange[]> {
+  const parser = await loadShellParser();
+  // span は UTF-8 のバイト位置なので文字列の位置に直す
+  const toIndex = byteOffsetToIndex(input);
+  return parser.highlighter.highlight(input).map(({ kind, span }) => ({
+    kind,
+    start: toIndex(span.start),
+    end: toIndex(span.end),
+  }));
+}
+
+/** 補完候補の取得元 */
+export interface CompletionSource {
+  /** PATH から実行できるコマンド名 */
+  commands(): string[];
+  /** ディレクトリのエントリ（dir は入力されたままのパス。空文字列ならカレントディレクトリ） */
+  listDir(dir: string): FileEntry[];
+  /** 環境変数名 */
+  envNames(): string[];
+  /** エイリアス名 */
+  aliases(): string[];
+}
+
+/** 補完結果（start, end は置き換える範囲の文字列のインデックス） */
+export interface CompletionRange {
+  start: number;
+  end: number;
+  candidates: Candidate[];
+}
+
+/**
+ * カーソル位置（文字列のインデックス）のワードを補完
+ */
+export async function completeShellInput(
+  line: string,
+  cursor: number,
+  source: CompletionSource
+): Promise<CompletionRange> {
+  const parser = await loadShellParser();
+  const cursorOffset = new TextEncoder().encode(line.slice(0, cursor)).length;
+  const toIndex = byteOffsetToIndex(line);
+  completionSource = source;
+  try {
+    const { replace, candidates } = parser.completer.complete(line, cursorOffset);
+    return { start: toIndex(replace.start), end: toIndex(replace.end), candidates };
+  } finally {
+    completionSource = null;
+  }
+}
+
+/**
+ * パースエラーを該当行とエラー位置を指す ^ 付きで整形
+ */
+export function formatParseError(input: string, error: ParseError): string {
+  // position は UTF-8 のバイト位置なので文字列の位置に直す
+  const bytes = new TextEncoder().encode(input);
+  const offset = new TextDecoder().decode(bytes.slice(0, error.position)).length;
+  const lineStart = input.lastIndexOf('\n', offset - 1) + 1;
+  const lineEnd = input.indexOf('\n', offset);
+  const line = input.slice(lineStart, lineEnd === -1 ? undefined : lineEnd);
+  const lineNumber = input.slice(0, lineStart).split('\n').length;
+  const column = offset - lineStart;
+  return [
+    `parse error: ${error.message} (line ${lineNumber}, column ${column + 1})`,
+    line,
+    `${' '.repeat(column)}^`,
+  ].join('\n');
+}
+
+/** パースの失敗（incomplete は入力が途中で終わっている場合の理由） */
+export type ParseFailureOutcome = { ok: false; error: ParseError; incomplete: IncompleteReason | null };
+
+/** パース結果 */
+export type ParseOutcome = { ok: true; value: ParseResult } | ParseFailureOutcome;
+
+/** 先頭のコマンドだけのパース結果（rest は残りの入力） */
+export type NextParseOutcome = { ok: true; value: ParseResult; rest: string } | ParseFailureOutcome;
+
+/**
+ * パースの失敗を ParseOutcome に変換
+ */
+function fromParseFailure(failure: ParseFailure): ParseFailureOutcome {
+  if (failure.tag === 'incomplete') {
+    return { ok: false, error: failure.val.error, incomplete: failure.val.reason };
+  }
+  return { ok: false, error: failure.val, incomplete: null };
+}
+
+/**
+ * パーサーの呼び出し結果を正規化
+ * key は成功時の値だけが持つフィールド
+ */
+async function callParser<T extends object>(
+  call: (parser: ShellParserModule) => T | { tag: 'ok'; val: T } | { tag: 'err'; val: ParseFailure },
+  key: keyof T
+): Promise<{ ok: true; value: T } | ParseFailureOutcome> {
   try {
     const parser = await loadShellParser();
-    const result = parser.parser.parse(input);
+    const result = call(parser);
 
     // jco が生成するコードでは Result 型は直接値を返す
     // エラーの場合は例外を投げる
-    if ('elements' in result) {
-      return { ok: true, value: result as unknown as ParseResult };
+    if (key in result) {
+      return { ok: true, value: result as T };
     } else if ('tag' in result) {
       if (result.tag === 'ok') {
         return { ok: true, value: result.val };
       } else {
-        return { ok: false, error: result.val };
+        return fromParseFailure(result.val as ParseFailure);
       }
     } else {
-      return { ok: false, error: { message: 'Unknown parse result format', position: 0 } };
+      return {
+        ok: false,
+        error: { message: 'Unknown parse result format', position: 0 },
+        incomplete: null,
+      };
     }
   } catch (e) {
-    // jco のエラー形式: { payload: ParseError }
-    const error = e as { payload?: ParseError; message?: string };
+    // jco のエラー形式: { payload: ParseFailure }
+    const error = e as { payload?: ParseFailure; message?: string };
     if (error.payload && typeof error.payload === 'object') {
-      return { ok: false, error: error.payload };
+      return fromParseFailure(error.payload);
     }
     return {
       ok: false,
@@ -173,6 +414,35 @@ export async function parseShellCommand(
         message: error.message || String(e),
         position: 0,
       },
+      incomplete: null,
     };
   }
 }
+
+/**
+ * シェルコマンドをパース
+ */
+export async function parseShellCommand(
+  input: string,
+  aliases: Map<string, string> = new Map()
+): Promise<ParseOutcome> {
+  return callParser<ParseResult>((parser) => parser.parser.parse(input, [...aliases]), 'elements');
+}
+
+/**
+ * 先頭の完全なコマンド（トップレベルの 1 行）だけをパース
+ * 成功時は残りの入力を rest に返す
+ */
+export async function parseNextShellCommand(
+  input: string,
+  aliases: Map<string, string> = new Map()
+): Promise<NextParseOutcome> {
+  const result = await callParser<NextCommand>(
+    (parser) => parser.parser.parseNext(input, [...aliases]),
+    'rest'
+  );
+  if (!result.ok) {
+    return result;
+  }
+  return { ok: true, value: result.value.parsed, rest: result.value.rest };
+}
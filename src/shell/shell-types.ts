@@ -3,50 +3,227 @@
  * WIT インターフェースに対応
  */
 
+/** 入力中の範囲（UTF-8 のバイト位置、end は含まない） */
+export interface Span {
+  start: number;
+  end: number;
+}
+
 /** 環境変数参照 */
 export interface EnvRef {
   name: string;
-  defaultValue: string | null;
+  /** ${VAR:-word} などの展開演算子 */
+  op: ParamOp | null;
+  /** 演算子のオペランド中のコマンド置換の ID（入れ子も含む） */
+  substitutions: number[];
+}
+
+/** word を取る展開演算子（colon が真なら空文字列も未設定として扱う） */
+export interface ParamWord {
+  colon: boolean;
+  word: string;
+}
+
+/** 前方・後方一致の削除 (#, ##, %, %%) */
+export interface ParamTrim {
+  pattern: string;
+  longest: boolean;
+}
+
+/** 置換方法 (/, //, /#, /%) */
+export type ReplaceMode = 'first' | 'all' | 'prefix' | 'suffix';
+
+/** 置換 ${VAR/pattern/replacement} */
+export interface ParamReplace {
+  pattern: string;
+  replacement: string;
+  mode: ReplaceMode;
 }
 
+/** 部分文字列 ${VAR:offset:length}（どちらも算術式） */
+export interface ParamSubstring {
+  offset: string;
+  length: string | null;
+}
+
+/** 大文字・小文字変換 (^, ^^, ",", ",,") */
+export interface ParamCase {
+  upper: boolean;
+  all: boolean;
+  pattern: string;
+}
+
+/** パラメータ展開の演算子 */
+export type ParamOp =
+  | { tag: 'length' }
+  | { tag: 'default'; val: ParamWord }
+  | { tag: 'assign'; val: ParamWord }
+  | { tag: 'error'; val: ParamWord }
+  | { tag: 'alternative'; val: ParamWord }
+  | { tag: 'remove-prefix'; val: ParamTrim }
+  | { tag: 'remove-suffix'; val: ParamTrim }
+  | { tag: 'replace'; val: ParamReplace }
+  | { tag: 'substring'; val: ParamSubstring }
+  | { tag: 'case-mod'; val: ParamCase };
+
+/** 特殊パラメータ ($?, $$, $!, $#, $@, $*) */
+export type SpecialParam =
+  | 'exit-status'
+  | 'pid'
+  | 'last-background-pid'
+  | 'arg-count'
+  | 'all-args'
+  | 'all-args-joined';
+
 /** ワードセグメント */
 export type WordSegment =
   | { tag: 'literal'; val: string }
   | { tag: 'env-var'; val: EnvRef }
-  | { tag: 'command-subst'; val: number };
+  | { tag: 'command-subst'; val: number }
+  | { tag: 'positional'; val: number }
+  | { tag: 'special'; val: SpecialParam }
+  | { tag: 'tilde'; val: string }
+  | { tag: 'arith'; val: string };
+
+/** ワードを構成する部分 */
+export interface WordPart {
+  segment: WordSegment;
+  /** クォートされていた（分割やパス名展開を行わない） */
+  quoted: boolean;
+}
 
-/** パース済みワード */
+/** パース済みワード（"$A"b'c' のように隣接する部分を連結したもの） */
 export interface ParsedWord {
-  segments: WordSegment[];
+  parts: WordPart[];
+  span: Span;
 }
 
 /** リダイレクト種別 */
-export type RedirectKind = 'stdin' | 'stdout' | 'stdout-append';
+export type RedirectKind =
+  | 'stdin'
+  | 'stdout'
+  | 'stdout-append'
+  | 'here-doc'
+  | 'here-string'
+  | 'read-write';
+
+/** リダイレクト先（fd は複製元のファイルディスクリプタ） */
+export type RedirectTarget = { tag: 'word'; val: ParsedWord } | { tag: 'fd'; val: number };
 
 /** リダイレクト */
 export interface Redirect {
+  /** 対象のファイルディスクリプタ */
+  fd: number;
   kind: RedirectKind;
-  target: ParsedWord;
+  target: RedirectTarget;
+  /** IO 番号からターゲットまで（ヒアドキュメントの本文は含まない） */
+  span: Span;
 }
 
 /** 単純コマンド */
 export interface SimpleCommand {
   args: ParsedWord[];
   redirects: Redirect[];
+  span: Span;
+}
+
+/** コマンド（compound は ParseResult.compounds のインデックス） */
+export type Command =
+  | { tag: 'simple'; val: SimpleCommand }
+  | { tag: 'compound'; val: number }
+  | { tag: 'loop-control'; val: LoopControl }
+  | { tag: 'function-def'; val: number };
+
+/** ループ制御の種別 */
+export type LoopControlKind = 'break' | 'continue';
+
+/** ループ制御（depth は 1 が最も内側） */
+export interface LoopControl {
+  kind: LoopControlKind;
+  depth: number;
 }
 
 /** パイプライン */
 export interface Pipeline {
-  commands: SimpleCommand[];
+  commands: Command[];
+  span: Span;
 }
 
 /** 条件接続子 */
-export type Connector = 'none' | 'and' | 'or';
+export type Connector = 'none' | 'and' | 'or' | 'sequential';
 
 /** 条件要素 */
 export interface ConditionalElement {
   connector: Connector;
   pipeline: Pipeline;
+  /** & で終わる AND-OR リストに属する */
+  background: boolean;
+  /** パイプラインの範囲（直後が & ならそれも含む） */
+  span: Span;
+}
+
+/** if / elif 節 */
+export interface IfBranch {
+  condition: ConditionalElement[];
+  body: ConditionalElement[];
+}
+
+/** if 文 */
+export interface IfClause {
+  branches: IfBranch[];
+  elseBody: ConditionalElement[] | null;
+}
+
+/** ループ種別 */
+export type LoopKind = 'while' | 'until';
+
+/** while / until ループ */
+export interface LoopClause {
+  kind: LoopKind;
+  condition: ConditionalElement[];
+  body: ConditionalElement[];
+}
+
+/** for ループ（words が null の場合は位置パラメータを対象とする） */
+export interface ForClause {
+  variable: string;
+  words: ParsedWord[] | null;
+  body: ConditionalElement[];
+}
+
+/** case の分岐 */
+export interface CaseArm {
+  patterns: ParsedWord[];
+  body: ConditionalElement[];
+}
+
+/** case 文 */
+export interface CaseClause {
+  word: ParsedWord;
+  arms: CaseArm[];
+}
+
+/** 複合コマンドの種別 */
+export type CompoundKind =
+  | { tag: 'if-clause'; val: IfClause }
+  | { tag: 'loop-clause'; val: LoopClause }
+  | { tag: 'for-clause'; val: ForClause }
+  | { tag: 'case-clause'; val: CaseClause }
+  | { tag: 'brace-group'; val: ConditionalElement[] }
+  | { tag: 'subshell'; val: ConditionalElement[] };
+
+/** 複合コマンド（redirects は全体に適用される） */
+export interface CompoundCommand {
+  kind: CompoundKind;
+  redirects: Redirect[];
+  span: Span;
+}
+
+/** 関数定義（body は ParseResult.compounds のインデックス） */
+export interface FunctionDef {
+  name: string;
+  body: number;
+  span: Span;
 }
 
 /** コマンド置換 */
@@ -59,20 +236,156 @@ export interface CommandSubstitution {
 export interface ParseResult {
   elements: ConditionalElement[];
   substitutions: CommandSubstitution[];
+  compounds: CompoundCommand[];
+  functions: FunctionDef[];
+}
+
+/** 先頭のコマンドだけのパース結果（rest は残りの入力） */
+export interface NextCommand {
+  parsed: ParseResult;
+  rest: string;
 }
 
 /** パースエラー */
 export interface ParseError {
   message: string;
+  /** 入力中のバイト位置 */
   position: number;
 }
 
+/** 入力が途中で終わっている理由（compound は待っている予約語または ")"） */
+export type IncompleteReason =
+  | { tag: 'single-quote' }
+  | { tag: 'double-quote' }
+  | { tag: 'line-continuation' }
+  | { tag: 'here-doc'; val: string }
+  | { tag: 'command-substitution' }
+  | { tag: 'pipe' }
+  | { tag: 'and-or' }
+  | { tag: 'compound'; val: string };
+
+/** 途中で終わっている入力 */
+export interface IncompleteInput {
+  reason: IncompleteReason;
+  /** 続きがないまま終わった場合のエラー */
+  error: ParseError;
+}
+
+/** パースの失敗 */
+export type ParseFailure =
+  | { tag: 'error'; val: ParseError }
+  | { tag: 'incomplete'; val: IncompleteInput };
+
+/** 展開後のフィールド */
+export interface Field {
+  value: string;
+  /** パス名展開のパターン（クォートされていないワイルドカードを含む場合のみ） */
+  pattern: string | null;
+}
+
+/** フィールド分割の結果 */
+export interface FieldsResult {
+  fields: Field[];
+  /** ${VAR:=word} や算術式で代入された変数 */
+  assignments: [string, string][];
+}
+
+/** 展開時に参照するシェルの状態 */
+export interface ExpandContext {
+  env: [string, string][];
+  /** $0 */
+  arg0: string;
+  /** $1 以降 */
+  positional: string[];
+  /** $? */
+  lastStatus: number;
+  /** $$ */
+  pid: number;
+  /** $! */
+  lastBackgroundPid: number | null;
+  /** コマンド置換の結果（id と出力） */
+  substitutions: [number, string][];
+}
+
+/** ワード展開の結果 */
+export interface WordResult {
+  value: string;
+  /** ${VAR:=word} や算術式で代入された変数 */
+  assignments: [string, string][];
+}
+
+/** シンタックスハイライトの分類 */
+export type HighlightKind =
+  | 'command'
+  | 'keyword'
+  | 'argument'
+  | 'option'
+  | 'quoted'
+  | 'variable'
+  | 'substitution'
+  | 'operator'
+  | 'redirect'
+  | 'comment'
+  | 'error';
+
+/** 分類された範囲 */
+export interface HighlightSpan {
+  kind: HighlightKind;
+  span: Span;
+}
+
+/** 補完候補の種類 */
+export type CandidateKind = 'command' | 'alias' | 'file' | 'directory' | 'variable';
+
+/** 補完候補 */
+export interface Candidate {
+  /** 置き換える範囲に入れる文字列（必要なエスケープ済み） */
+  value: string;
+  kind: CandidateKind;
+}
+
+/** 補完結果 */
+export interface Completion {
+  /** 候補で置き換える範囲 */
+  replace: Span;
+  /** 候補（ソート済み） */
+  candidates: Candidate[];
+}
+
 /** シェルパーサーモジュールのインターフェース */
 export interface ShellParserModule {
   parser: {
-    parse(input: string): { tag: 'ok'; val: ParseResult } | { tag: 'err'; val: ParseError };
+    /** aliases は [名前, 値] の一覧 */
+    parse(
+      input: string,
+      aliases: [string, string][]
+    ): { tag: 'ok'; val: ParseResult } | { tag: 'err'; val: ParseFailure };
+    /** 先頭の完全なコマンド（トップレベルの 1 行）だけをパース */
+    parseNext(
+      input: string,
+      aliases: [string, string][]
+    ): { tag: 'ok'; val: NextCommand } | { tag: 'err'; val: ParseFailure };
   };
   expander: {
-    expandWord(word: ParsedWord, env: [string, string][]): string;
+    /** 以下、エラー時は payload にメッセージを持つ例外を投げる */
+    expandWord(word: ParsedWord, ctx: ExpandContext): WordResult;
+    expandWords(word: ParsedWord, ctx: ExpandContext): FieldsResult;
+    expandPattern(word: ParsedWord, ctx: ExpandContext): WordResult;
+  };
+  pattern: {
+    matchPattern(pattern: string, text: string): boolean;
+  };
+  glob: {
+    expandGlob(pattern: string, cwd: string): string[];
+  };
+  formatter: {
+    format(ast: ParseResult): string;
+  };
+  highlighter: {
+    highlight(input: string): HighlightSpan[];
+  };
+  completer: {
+    /** cursor は line 中のバイト位置 */
+    complete(line: string, cursor: number): Completion;
   };
 }
@@ -2,6 +2,8 @@
  * Shell 関連の型定義
  */
 
+import type { CompoundCommand, FunctionDef } from './shell-types';
+
 export interface CommandInput {
   args: string[];
   env: Array<[string, string]>;
@@ -19,6 +21,30 @@ export interface ShellState {
   cwd: string;
   env: Map<string, string>;
   aliases: Map<string, string>;
+  functions: Map<string, ShellFunction>;
+  /** 位置パラメータ（$1 以降） */
+  positional: string[];
+  /** $0（実行中のスクリプト名。対話シェルでは sh） */
+  scriptName: string;
+  /** $? 直前のコマンドの終了ステータス */
+  lastExitCode: number;
+  /** $$ シェルのプロセス ID */
+  pid: number;
+  /** $! 直前のバックグラウンドジョブの ID（プロセスはないため pid からの連番の仮の値） */
+  lastBackgroundPid: number | null;
+}
+
+/**
+ * シェル関数
+ * 本体は定義元のパース結果の複合コマンドテーブルを参照する
+ */
+export interface ShellFunction {
+  /** 本体（compounds のインデックス） */
+  body: number;
+  compounds: CompoundCommand[];
+  functions: FunctionDef[];
+  /** 定義元のコマンド置換の入力（id → コマンド文字列） */
+  substitutions: Map<number, string>;
 }
 
 export interface ExecResult {